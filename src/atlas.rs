@@ -0,0 +1,74 @@
+use crate::block::BlockType;
+
+/// Size in pixels of a single (square) tile in the atlas
+const TILE_SIZE: u32 = 16;
+
+/// The tiles in the atlas, all in a single row, the index of the tile is
+/// [`BlockType::texture_index`]
+const TILES: [BlockType; 4] = [
+    BlockType::Grass,
+    BlockType::Dirt,
+    BlockType::Stone,
+    BlockType::Water,
+];
+
+/// Deterministic pseudo random value in `[0, 1]` for a pixel of a tile
+fn random(x: u32, y: u32, tile: u32) -> f32 {
+    let mut h = x.wrapping_mul(0x27d4_eb2d) ^ y.wrapping_mul(0x1656_67b1) ^ tile;
+    h = (h ^ (h >> 15)).wrapping_mul(0x2c1b_3c6d);
+    h ^= h >> 12;
+
+    (h & 0xFF) as f32 / 255.
+}
+
+/// Brightness of a pixel inside the tile of `block`
+fn tile_pixel(block: BlockType, x: u32, y: u32) -> f32 {
+    let tile = block.texture_index();
+
+    let value = match block {
+        BlockType::Grass => 0.8 + 0.2 * random(x, y, tile),
+        BlockType::Dirt => {
+            // a few darker pebbles
+            if random(x, y, tile + 100) > 0.9 {
+                0.6
+            } else {
+                0.75 + 0.25 * random(x, y, tile)
+            }
+        }
+        // blotches of 2x2 pixels
+        BlockType::Stone => 0.7 + 0.2 * random(x / 2, y / 2, tile) + 0.1 * random(x, y, tile),
+        // horizontal waves
+        BlockType::Water => 0.9 + 0.1 * random(0, (y + x / 4) % TILE_SIZE, tile),
+    };
+
+    // darken the edges a bit so that separate blocks are visible
+    if x == 0 || y == 0 || x == TILE_SIZE - 1 || y == TILE_SIZE - 1 {
+        value * 0.85
+    } else {
+        value
+    }
+}
+
+/// The `[width, height]` of the atlas in pixels
+pub(crate) const fn atlas_size() -> [u32; 2] {
+    [TILE_SIZE * TILES.len() as u32, TILE_SIZE]
+}
+
+/// Generates the RGBA pixels of the atlas row by row.
+///
+/// The tiles are grayscale, and the shader multiplies them by the color
+/// of the block, so that the color of a block is still controlled by
+/// [`BlockType::color`].
+pub(crate) fn atlas_pixels() -> Vec<[u8; 4]> {
+    let [width, height] = atlas_size();
+
+    (0..height)
+        .flat_map(|y| (0..width).map(move |x| (x, y)))
+        .map(|(x, y)| {
+            let block = TILES[(x / TILE_SIZE) as usize];
+            let value = (tile_pixel(block, x % TILE_SIZE, y) * 255.) as u8;
+
+            [value, value, value, 255]
+        })
+        .collect()
+}
@@ -0,0 +1,186 @@
+use cgmath::Vector3;
+
+/// The type of a block in the world, this decides how the block looks
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub(crate) enum BlockType {
+    Grass,
+    Dirt,
+    Stone,
+    Water,
+}
+
+impl BlockType {
+    pub const ALL: [BlockType; 4] = [
+        BlockType::Grass,
+        BlockType::Dirt,
+        BlockType::Stone,
+        BlockType::Water,
+    ];
+
+    /// The base color of the block type
+    pub const fn color(&self) -> [f32; 4] {
+        match self {
+            BlockType::Grass => [0.33, 0.62, 0.21, 1.],
+            BlockType::Dirt => [0.53, 0.38, 0.25, 1.],
+            BlockType::Stone => [0.5, 0.5, 0.5, 1.],
+            BlockType::Water => [0.15, 0.35, 0.85, 0.7],
+        }
+    }
+
+    /// The color of one face of the block, most blocks use [`Self::color`]
+    /// for all of their faces
+    pub const fn face_color(&self, face: BlockFace) -> [f32; 4] {
+        match (self, face) {
+            // grass only grows on top
+            (BlockType::Grass, BlockFace::Top) => self.color(),
+            (BlockType::Grass, _) => BlockType::Dirt.color(),
+            _ => self.color(),
+        }
+    }
+}
+
+impl BlockType {
+    /// A stable id for the block type, used when saving the world
+    pub const fn id(&self) -> u8 {
+        match self {
+            BlockType::Grass => 0,
+            BlockType::Dirt => 1,
+            BlockType::Stone => 2,
+            BlockType::Water => 3,
+        }
+    }
+
+    /// Transparent blocks don't hide the faces of the blocks behind them, and
+    /// are drawn after all the opaque blocks
+    pub const fn is_transparent(&self) -> bool {
+        matches!(self, BlockType::Water)
+    }
+
+    /// Liquids are transparent, and their surface waves, so their faces are
+    /// never merged into bigger quads
+    #[cfg_attr(feature = "per_cube_mesh", allow(dead_code))]
+    pub const fn is_liquid(&self) -> bool {
+        matches!(self, BlockType::Water)
+    }
+
+    /// A lowercase name for the block type, shown in the debug overlay
+    pub const fn name(&self) -> &'static str {
+        match self {
+            BlockType::Grass => "grass",
+            BlockType::Dirt => "dirt",
+            BlockType::Stone => "stone",
+            BlockType::Water => "water",
+        }
+    }
+
+    /// The index of the tile of this block in the texture atlas
+    pub const fn texture_index(&self) -> u32 {
+        self.id() as u32
+    }
+
+    pub const fn from_id(id: u8) -> Option<Self> {
+        match id {
+            0 => Some(BlockType::Grass),
+            1 => Some(BlockType::Dirt),
+            2 => Some(BlockType::Stone),
+            3 => Some(BlockType::Water),
+            _ => None,
+        }
+    }
+}
+
+/// The geometry of a block
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub(crate) enum BlockShape {
+    Cube,
+    /// the bottom half of a cube
+    Slab,
+}
+
+impl BlockShape {
+    /// A stable id for the shape, used when saving the world
+    pub const fn id(&self) -> u8 {
+        match self {
+            BlockShape::Cube => 0,
+            BlockShape::Slab => 1,
+        }
+    }
+
+    pub const fn from_id(id: u8) -> Option<Self> {
+        match id {
+            0 => Some(BlockShape::Cube),
+            1 => Some(BlockShape::Slab),
+            _ => None,
+        }
+    }
+
+    /// Full blocks hide the faces of the blocks touching them
+    pub const fn is_full(&self) -> bool {
+        matches!(self, BlockShape::Cube)
+    }
+}
+
+/// A face of a block, named the same way as the faces of the cube mesh
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum BlockFace {
+    /// `-z`
+    Front,
+    /// `+z`
+    Back,
+    /// `+x`
+    Right,
+    /// `-x`
+    Left,
+    /// `+y`
+    Top,
+    /// `-y`
+    Bottom,
+}
+
+impl BlockFace {
+    #[cfg_attr(feature = "per_cube_mesh", allow(dead_code))]
+    pub const ALL: [BlockFace; 6] = [
+        BlockFace::Front,
+        BlockFace::Back,
+        BlockFace::Right,
+        BlockFace::Left,
+        BlockFace::Top,
+        BlockFace::Bottom,
+    ];
+
+    /// The face that has the normal `normal`, must be a unit axis vector
+    pub fn from_normal(normal: Vector3<i32>) -> Option<Self> {
+        match (normal.x, normal.y, normal.z) {
+            (0, 0, -1) => Some(BlockFace::Front),
+            (0, 0, 1) => Some(BlockFace::Back),
+            (1, 0, 0) => Some(BlockFace::Right),
+            (-1, 0, 0) => Some(BlockFace::Left),
+            (0, 1, 0) => Some(BlockFace::Top),
+            (0, -1, 0) => Some(BlockFace::Bottom),
+            _ => None,
+        }
+    }
+
+    /// The direction of the normal as text, like `+x` for [`BlockFace::Right`]
+    pub const fn axis_name(&self) -> &'static str {
+        match self {
+            BlockFace::Front => "-z",
+            BlockFace::Back => "+z",
+            BlockFace::Right => "+x",
+            BlockFace::Left => "-x",
+            BlockFace::Top => "+y",
+            BlockFace::Bottom => "-y",
+        }
+    }
+
+    pub const fn normal(&self) -> Vector3<i32> {
+        match self {
+            BlockFace::Front => Vector3::new(0, 0, -1),
+            BlockFace::Back => Vector3::new(0, 0, 1),
+            BlockFace::Right => Vector3::new(1, 0, 0),
+            BlockFace::Left => Vector3::new(-1, 0, 0),
+            BlockFace::Top => Vector3::new(0, 1, 0),
+            BlockFace::Bottom => Vector3::new(0, -1, 0),
+        }
+    }
+}
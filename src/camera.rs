@@ -1,39 +1,142 @@
-use std::f32::consts::PI;
+use std::{f32::consts::PI, time::Duration};
 
-use cgmath::{Angle, InnerSpace, Matrix3, Matrix4, Point3, Rad, SquareMatrix, Vector3};
+use cgmath::{
+    Angle, InnerSpace, Matrix3, Matrix4, Point3, Quaternion, Rad, Rotation3, SquareMatrix, Vector3,
+};
 
+use crate::math::{self, Plane};
+
+/// The default pitch limits, looking straight up or down is avoided
 const MIN_PITCH: Rad<f32> = Rad(-89.0 * PI / 180.0);
 const MAX_PITCH: Rad<f32> = Rad(89.0 * PI / 180.0);
 
+const MIN_ROLL: Rad<f32> = Rad(-45.0 * PI / 180.0);
+const MAX_ROLL: Rad<f32> = Rad(45.0 * PI / 180.0);
+
 const MIN_FOV: Rad<f32> = Rad(1.0 * PI / 180.0);
 const MAX_FOV: Rad<f32> = Rad(179.0 * PI / 180.0);
 
+/// The near plane must be in front of the camera, the perspective divides by it
+const MIN_NEAR: f32 = 0.001;
+/// The smallest distance between the near and far planes
+const MIN_DEPTH_RANGE: f32 = 0.1;
+
+/// How long it takes the fov to reach the target after zooming
+const ZOOM_DURATION: Duration = Duration::from_millis(150);
+
+/// The viewpoint of a camera, saved with the world to restore it later
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct CameraState {
+    pub position: Point3<f32>,
+    pub yaw: Rad<f32>,
+    pub pitch: Rad<f32>,
+    pub fov: Rad<f32>,
+}
+
+/// The closest of `N`, `E`, `S` and `W` to a heading from [`Camera::heading`]
+pub(crate) fn compass_direction(heading: f32) -> char {
+    ['N', 'E', 'S', 'W'][((heading + 45.) / 90.) as usize % 4]
+}
+
 fn clamp_rad(rad: Rad<f32>, min: Rad<f32>, max: Rad<f32>) -> Rad<f32> {
     Rad(rad.0.clamp(min.0, max.0))
 }
 
+/// Moves `value` towards `target` by at most `step`
+fn move_towards(value: Rad<f32>, target: Rad<f32>, step: f32) -> Rad<f32> {
+    let remaining = target.0 - value.0;
+    if remaining.abs() <= step {
+        target
+    } else {
+        Rad(value.0 + step.copysign(remaining))
+    }
+}
+
+/// How the camera forward/backward movement is applied
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum MovementMode {
+    /// move only on the horizontal plane, regardless of the pitch
+    Grounded,
+    /// move in the direction the camera is looking at
+    Fly,
+}
+
+/// How the depth between the near and far planes is stored in the depth buffer
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub(crate) enum DepthMode {
+    /// near is `1` and far is `0`. Floats are more precise close to `0`,
+    /// which balances the perspective putting most of the depth range
+    /// close to the camera, so far away faces don't z-fight
+    #[default]
+    Reversed,
+    /// near is `0` and far is `1`, as most renderers do. Simpler to compare
+    /// against, but loses precision quickly with the distance
+    Standard,
+}
+
+impl DepthMode {
+    /// The depth of the far plane, the depth buffer is cleared to this
+    pub const fn far_depth(&self) -> f32 {
+        match self {
+            DepthMode::Reversed => 0.,
+            DepthMode::Standard => 1.,
+        }
+    }
+}
+
 pub(crate) struct Camera {
     position: Point3<f32>,
 
     yaw: Rad<f32>,
     pitch: Rad<f32>,
+    roll: Rad<f32>,
+    // the range of the pitch, `None` allows rotating all the way around
+    pitch_limits: Option<(Rad<f32>, Rad<f32>)>,
 
     camera_front: Vector3<f32>,
+    // the axes and forward direction used for movement, these don't
+    // follow the view while in free look
     movement_axes: Matrix3<f32>,
+    movement_front: Vector3<f32>,
+    movement_mode: MovementMode,
+    free_look: bool,
+    camera_up: Vector3<f32>,
 
     fov: Rad<f32>,
+    // `fov` moves towards this in `update`, with `fov_speed` per second
+    target_fov: Rad<f32>,
+    fov_speed: Rad<f32>,
+    // the fov it was created with, restored by `reset_fov`
+    default_fov: Rad<f32>,
+    // added to `fov` for rendering, like `fov` it moves towards its target,
+    // it's kept separate so zooming and saving don't include it
+    fov_offset: Rad<f32>,
+    target_fov_offset: Rad<f32>,
+    fov_offset_speed: Rad<f32>,
     aspect: f32,
     near: f32,
     far: f32,
+    depth_mode: DepthMode,
 
     perspective: Matrix4<f32>,
     view: Matrix4<f32>,
 
     perspective_dirty: bool,
     view_dirty: bool,
+
+    // the view is moved by `shake_offset`, which fades out over `shake_duration`,
+    // the position is not changed, so movement and ray tracing are not affected
+    shake_intensity: f32,
+    shake_duration: Duration,
+    shake_remaining: Duration,
+    shake_offset: Vector3<f32>,
+    // seconds since the shake started, drives the offset
+    shake_time: f32,
 }
 
 impl Camera {
+    /// `near` is clamped to be in front of the camera, and `far` to be
+    /// further than `near`, so the perspective is always valid
     pub fn new<F: Into<Rad<f32>>>(
         fov: F,
         aspect: f32,
@@ -41,25 +144,48 @@ impl Camera {
         far: f32,
         position: Point3<f32>,
     ) -> Camera {
+        let fov = clamp_rad(fov.into(), MIN_FOV, MAX_FOV);
+        let near = near.max(MIN_NEAR);
+        let far = far.max(near + MIN_DEPTH_RANGE);
+
         Camera {
             position,
 
             yaw: Rad(0.),
             pitch: Rad(0.),
+            roll: Rad(0.),
+            pitch_limits: Some((MIN_PITCH, MAX_PITCH)),
 
             camera_front: Vector3::unit_z(),
             movement_axes: Matrix3::identity(),
-
-            fov: clamp_rad(fov.into(), MIN_FOV, MAX_FOV),
+            movement_front: Vector3::unit_z(),
+            movement_mode: MovementMode::Grounded,
+            free_look: false,
+            camera_up: Vector3::unit_y(),
+
+            fov,
+            target_fov: fov,
+            fov_speed: Rad(0.),
+            default_fov: fov,
+            fov_offset: Rad(0.),
+            target_fov_offset: Rad(0.),
+            fov_offset_speed: Rad(0.),
             aspect,
             near,
             far,
+            depth_mode: DepthMode::default(),
 
             perspective: Matrix4::identity(),
             view: Matrix4::identity(),
 
             perspective_dirty: true,
             view_dirty: true,
+
+            shake_intensity: 0.,
+            shake_duration: Duration::ZERO,
+            shake_remaining: Duration::ZERO,
+            shake_offset: Vector3::new(0., 0., 0.),
+            shake_time: 0.,
         }
     }
 
@@ -71,23 +197,63 @@ impl Camera {
         &self.camera_front
     }
 
-    pub fn reversed_depth_perspective(&mut self) -> cgmath::Matrix4<f32> {
+    pub fn yaw(&self) -> Rad<f32> {
+        self.yaw
+    }
+
+    pub fn pitch(&self) -> Rad<f32> {
+        self.pitch
+    }
+
+    /// The direction the camera is looking at in degrees clockwise from north,
+    /// in `[0, 360)`. North is `-z` and east is `+x`
+    pub fn heading(&self) -> f32 {
+        // a yaw of `0` looks at `+z`, which is south
+        let heading = (self.yaw.0.to_degrees() + 180.).rem_euclid(360.);
+        // `rem_euclid` can round up to `360` for tiny negative values
+        if heading >= 360. {
+            0.
+        } else {
+            heading
+        }
+    }
+
+    #[allow(dead_code)]
+    pub fn roll(&self) -> Rad<f32> {
+        self.roll
+    }
+
+    /// The current fov with the offset from [`Camera::set_fov_offset`], which
+    /// may be moving towards the fov set with [`Camera::zoom`]
+    pub fn fov(&self) -> Rad<f32> {
+        clamp_rad(self.fov + self.fov_offset, MIN_FOV, MAX_FOV)
+    }
+
+    /// The projection matrix, with the depth stored as set by
+    /// [`Camera::set_depth_mode`]
+    pub fn perspective(&mut self) -> cgmath::Matrix4<f32> {
         if self.perspective_dirty {
             // compute the focal length (1 / tan(fov / 2))
-            let focal_length = (self.fov / 2.0).cot();
+            let focal_length = (self.fov() / 2.0).cot();
+
+            let depth_range = self.far - self.near;
+            let (depth_scale, depth_offset) = match self.depth_mode {
+                // `z = near` maps to 1 and `z = far` maps to 0
+                DepthMode::Reversed => {
+                    (-self.near / depth_range, self.far * self.near / depth_range)
+                }
+                // `z = near` maps to 0 and `z = far` maps to 1
+                DepthMode::Standard => {
+                    (self.far / depth_range, -self.far * self.near / depth_range)
+                }
+            };
 
-            // projection matrix, this uses reversed depth (near is 1, far is 0)
             // this matrix is transposed to work for the shader
             self.perspective = [
                 [focal_length / self.aspect, 0.0, 0.0, 0.0],
                 [0.0, -focal_length, 0.0, 0.0],
-                [0.0, 0.0, self.near / (self.far - self.near), 1.0],
-                [
-                    0.0,
-                    0.0,
-                    (self.far * self.near) / (self.far - self.near),
-                    0.0,
-                ],
+                [0.0, 0.0, depth_scale, 1.0],
+                [0.0, 0.0, depth_offset, 0.0],
             ]
             .into();
 
@@ -99,52 +265,199 @@ impl Camera {
 
     pub fn view(&mut self) -> cgmath::Matrix4<f32> {
         if self.view_dirty {
-            self.view = Matrix4::look_to_lh(self.position, self.camera_front, Vector3::unit_y());
+            self.view = Matrix4::look_to_lh(
+                self.position + self.shake_offset,
+                self.camera_front,
+                self.camera_up,
+            );
             self.view_dirty = false;
         }
         self.view
     }
+
+    /// The planes of the view frustum in world space, the normals point inside
+    pub fn frustum_planes(&mut self) -> [Plane; 6] {
+        math::frustum_planes(&(self.perspective() * self.view()))
+    }
 }
 
 impl Camera {
     pub fn rotate_camera<P: Into<Rad<f32>>, Y: Into<Rad<f32>>>(&mut self, pitch: P, yaw: Y) {
+        self.rotate_camera_with_roll(pitch, yaw, Rad(0.));
+    }
+
+    /// Same as [`Camera::rotate_camera`], but also rolls the camera around
+    /// the direction it's looking at, the roll is clamped to +-45 degrees.
+    ///
+    /// Roll only affects the view, movement stays on the horizontal plane.
+    pub fn rotate_camera_with_roll<P, Y, R>(&mut self, pitch: P, yaw: Y, roll: R)
+    where
+        P: Into<Rad<f32>>,
+        Y: Into<Rad<f32>>,
+        R: Into<Rad<f32>>,
+    {
         let yaw = yaw.into();
         let pitch = pitch.into();
+        self.roll = clamp_rad(self.roll + roll.into(), MIN_ROLL, MAX_ROLL);
 
         // TODO: need to subtract for some reason, would be better to
         //       stick with the euler rotation direction
         self.yaw -= yaw;
-        self.pitch = clamp_rad(self.pitch + pitch, MIN_PITCH, MAX_PITCH);
+        self.pitch = match self.pitch_limits {
+            Some((min, max)) => clamp_rad(self.pitch + pitch, min, max),
+            None => (self.pitch + pitch).normalize_signed(),
+        };
 
-        let mut front = Vector3::new(
+        let front = Vector3::new(
             -self.pitch.cos() * self.yaw.sin(),
             self.pitch.sin(),
             self.pitch.cos() * self.yaw.cos(),
         )
         .normalize();
+        // perpendicular to `front` in the direction the pitch increases, this is
+        // the same view as using the world up, but keeps working when looking
+        // straight up or down, and doesn't flip when going over them
+        let up = Vector3::new(
+            self.pitch.sin() * self.yaw.sin(),
+            self.pitch.cos(),
+            -self.pitch.sin() * self.yaw.cos(),
+        );
         self.camera_front = front;
-        // don't move up and down based on direction
-        front.y = 0.;
-        front = front.normalize();
+        self.camera_up = Quaternion::from_axis_angle(front, self.roll) * up;
+
+        if !self.free_look {
+            self.update_movement_axes();
+        }
+
+        self.view_dirty = true;
+    }
+
+    /// Rotates the camera to look at `yaw` and `pitch`, instead of rotating
+    /// relative to the current direction
+    pub fn set_orientation<Y: Into<Rad<f32>>, P: Into<Rad<f32>>>(&mut self, yaw: Y, pitch: P) {
+        let pitch = pitch.into() - self.pitch;
+        let yaw = self.yaw - yaw.into();
+        self.rotate_camera(pitch, yaw);
+    }
+
+    #[allow(dead_code)]
+    pub fn pitch_limits(&self) -> Option<(Rad<f32>, Rad<f32>)> {
+        self.pitch_limits
+    }
+
+    /// Limits the pitch to be between `min` and `max`, the default is +-89
+    /// degrees. The current pitch is clamped to the new limits.
+    ///
+    /// Panics if `min` is not less than `max`.
+    #[allow(dead_code)]
+    pub fn set_pitch_limits<P: Into<Rad<f32>>>(&mut self, min: P, max: P) {
+        let (min, max) = (min.into(), max.into());
+        assert!(min < max, "the minimum pitch must be less than the maximum");
+
+        self.pitch_limits = Some((min, max));
+        self.rotate_camera(Rad(0.), Rad(0.));
+    }
+
+    /// Allows the pitch to go over looking straight up or down, and rotate
+    /// all the way around, the view is upside down past them.
+    ///
+    /// Set limits again with [`Camera::set_pitch_limits`].
+    #[allow(dead_code)]
+    pub fn set_full_pitch_rotation(&mut self) {
+        self.pitch_limits = None;
+    }
+
+    /// Makes the movement follow the current view direction
+    fn update_movement_axes(&mut self) {
+        self.movement_front = self.camera_front;
+
+        // don't move up and down based on direction, the yaw is used as
+        // the horizontal part of `front` is empty when looking straight up
+        let front = Vector3::new(-self.yaw.sin(), 0., self.yaw.cos());
 
         let up = Vector3::unit_y();
         let right = up.cross(front).normalize();
         self.movement_axes = Matrix3::from_cols(right, up, front);
+    }
 
-        self.view_dirty = true;
+    pub fn free_look(&self) -> bool {
+        self.free_look
+    }
+
+    /// In free look, rotating the camera only changes the view, and movement
+    /// keeps the direction from before free look was enabled.
+    ///
+    /// Disabling it makes the movement follow the view again.
+    pub fn set_free_look(&mut self, free_look: bool) {
+        self.free_look = free_look;
+
+        if !free_look {
+            self.update_movement_axes();
+        }
     }
 
     pub fn move_camera(&mut self, direction: Vector3<f32>) {
-        self.position += self.movement_axes * direction;
+        self.position += self.movement_vector(direction);
         self.view_dirty = true;
     }
 
-    #[allow(dead_code)]
+    /// Converts `direction` relative to the camera (`z` is forward, `x` is
+    /// right and `y` is up) into world space movement
+    pub fn movement_vector(&self, direction: Vector3<f32>) -> Vector3<f32> {
+        let axes = match self.movement_mode {
+            MovementMode::Grounded => self.movement_axes,
+            // keep the right and up axes, but move forward in the
+            // direction we are looking at (before free look)
+            MovementMode::Fly => Matrix3::from_cols(
+                self.movement_axes.x,
+                self.movement_axes.y,
+                self.movement_front,
+            ),
+        };
+        axes * direction
+    }
+
+    pub fn movement_mode(&self) -> MovementMode {
+        self.movement_mode
+    }
+
+    pub fn set_movement_mode(&mut self, mode: MovementMode) {
+        self.movement_mode = mode;
+    }
+
     pub fn set_position(&mut self, position: Point3<f32>) {
         self.position = position;
         self.view_dirty = true;
     }
 
+    /// Sets the near plane, anything closer is not drawn. It's clamped to
+    /// be in front of the camera and closer than the far plane
+    #[allow(dead_code)]
+    pub fn set_near(&mut self, near: f32) {
+        let near = near.clamp(MIN_NEAR, self.far - MIN_DEPTH_RANGE);
+        if self.near != near {
+            self.near = near;
+            self.perspective_dirty = true;
+        }
+    }
+
+    pub fn set_depth_mode(&mut self, depth_mode: DepthMode) {
+        if self.depth_mode != depth_mode {
+            self.depth_mode = depth_mode;
+            self.perspective_dirty = true;
+        }
+    }
+
+    /// Sets the far plane, anything further is not drawn. It's clamped to
+    /// be further than the near plane
+    pub fn set_far(&mut self, far: f32) {
+        let far = far.max(self.near + MIN_DEPTH_RANGE);
+        if self.far != far {
+            self.far = far;
+            self.perspective_dirty = true;
+        }
+    }
+
     pub fn set_aspect(&mut self, aspect: f32) {
         if self.aspect != aspect {
             self.aspect = aspect;
@@ -152,12 +465,313 @@ impl Camera {
         }
     }
 
+    /// The current viewpoint, which can be restored with [`Camera::apply_state`]
+    pub fn state(&self) -> CameraState {
+        CameraState {
+            position: self.position,
+            yaw: self.yaw,
+            pitch: self.pitch,
+            fov: self.target_fov,
+        }
+    }
+
+    /// Moves and rotates the camera to a saved viewpoint
+    pub fn apply_state(&mut self, state: &CameraState) {
+        self.set_position(state.position);
+        self.set_orientation(state.yaw, state.pitch);
+        self.set_fov_immediate(state.fov);
+    }
+
+    /// Changes the target fov by `delta`, the fov will reach it smoothly
+    /// over multiple calls to [`Camera::update`]
     pub fn zoom<F: Into<Rad<f32>>>(&mut self, delta: F) {
-        let fov = clamp_rad(self.fov + delta.into(), MIN_FOV, MAX_FOV);
+        self.target_fov = clamp_rad(self.target_fov + delta.into(), MIN_FOV, MAX_FOV);
+        self.fov_speed = Rad((self.target_fov - self.fov).0.abs() / ZOOM_DURATION.as_secs_f32());
+    }
 
+    /// Zooms back to the fov the camera was created with
+    pub fn reset_fov(&mut self) {
+        self.zoom(self.default_fov - self.target_fov);
+    }
+
+    /// Widens the fov by `offset` on top of the zoom, reaching it smoothly
+    /// like [`Camera::zoom`], `0` removes it
+    pub fn set_fov_offset<F: Into<Rad<f32>>>(&mut self, offset: F) {
+        self.target_fov_offset = offset.into();
+        self.fov_offset_speed =
+            Rad((self.target_fov_offset - self.fov_offset).0.abs() / ZOOM_DURATION.as_secs_f32());
+    }
+
+    /// Sets the fov without animating
+    pub fn set_fov_immediate<F: Into<Rad<f32>>>(&mut self, fov: F) {
+        let fov = clamp_rad(fov.into(), MIN_FOV, MAX_FOV);
+
+        self.target_fov = fov;
         if self.fov != fov {
             self.fov = fov;
             self.perspective_dirty = true;
         }
     }
+
+    /// Shakes the view by up to `intensity` blocks in every direction, fading
+    /// out over `duration`. A stronger shake replaces the current one.
+    pub fn add_shake(&mut self, intensity: f32, duration: Duration) {
+        if intensity >= self.shake_strength() && !duration.is_zero() {
+            self.shake_intensity = intensity;
+            self.shake_duration = duration;
+            self.shake_remaining = duration;
+            self.shake_time = 0.;
+        }
+    }
+
+    /// The current maximum offset of the shake, `0` when it has ended
+    fn shake_strength(&self) -> f32 {
+        if self.shake_remaining.is_zero() {
+            0.
+        } else {
+            self.shake_intensity * self.shake_remaining.as_secs_f32()
+                / self.shake_duration.as_secs_f32()
+        }
+    }
+
+    /// The offset the view is moved by because of [`Camera::add_shake`]
+    #[allow(dead_code)]
+    pub fn shake_offset(&self) -> Vector3<f32> {
+        self.shake_offset
+    }
+
+    /// Moves the fov towards the target set by [`Camera::zoom`], and advances
+    /// the shake
+    pub fn update(&mut self, delta: Duration) {
+        if !self.shake_remaining.is_zero() {
+            self.shake_remaining = self.shake_remaining.saturating_sub(delta);
+            self.shake_time += delta.as_secs_f32();
+
+            // sines of unrelated frequencies, so the movement doesn't look regular
+            let t = self.shake_time;
+            let strength = self.shake_strength();
+            self.shake_offset = Vector3::new(
+                (t * 47.).sin() * (t * 13.).cos(),
+                (t * 53.).sin() * (t * 7.).cos(),
+                (t * 41.).sin() * (t * 17.).cos(),
+            ) * strength;
+            self.view_dirty = true;
+        }
+
+        if self.fov != self.target_fov {
+            let step = self.fov_speed.0 * delta.as_secs_f32();
+            self.fov = move_towards(self.fov, self.target_fov, step);
+            self.perspective_dirty = true;
+        }
+
+        if self.fov_offset != self.target_fov_offset {
+            let step = self.fov_offset_speed.0 * delta.as_secs_f32();
+            self.fov_offset = move_towards(self.fov_offset, self.target_fov_offset, step);
+            self.perspective_dirty = true;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cgmath::Deg;
+
+    /// The depth `position` is stored with after the perspective divide
+    fn depth_at(camera: &mut Camera, position: Point3<f32>) -> f32 {
+        let clip = camera.perspective() * camera.view() * position.to_homogeneous();
+        clip.z / clip.w
+    }
+
+    #[test]
+    fn depth_maps_near_and_far_planes() {
+        for (near, far) in [(0.1, 100.), (1., 1000.), (0.5, 16.), (0.001, 0.5)] {
+            let mut camera = Camera::new(Deg(90.), 1., near, far, Point3::new(0., 0., 0.));
+            // looking at `+z`
+            let near_point = Point3::new(0., 0., near);
+            let far_point = Point3::new(0., 0., far);
+            let middle = Point3::new(0., 0., (near + far) / 2.);
+
+            assert!((depth_at(&mut camera, near_point) - 1.).abs() < 1e-4);
+            assert!(depth_at(&mut camera, far_point).abs() < 1e-4);
+            let middle_depth = depth_at(&mut camera, middle);
+            assert!(middle_depth > 0. && middle_depth < 0.5);
+
+            camera.set_depth_mode(DepthMode::Standard);
+            assert!(depth_at(&mut camera, near_point).abs() < 1e-4);
+            assert!((depth_at(&mut camera, far_point) - 1.).abs() < 1e-4);
+            let middle_depth = depth_at(&mut camera, middle);
+            assert!(middle_depth > 0.5 && middle_depth < 1.);
+        }
+    }
+
+    #[test]
+    fn shake_decays_to_zero() {
+        let mut camera = Camera::new(Deg(90.), 1., 0.1, 100., Point3::new(0., 0., 0.));
+        camera.add_shake(0.5, Duration::from_millis(300));
+
+        let mut last_strength = camera.shake_strength();
+        for _ in 0..10 {
+            camera.update(Duration::from_millis(20));
+            let strength = camera.shake_strength();
+            assert!(strength < last_strength);
+            assert!(camera.shake_offset().x.abs() <= strength);
+            last_strength = strength;
+        }
+
+        camera.update(Duration::from_millis(200));
+        assert_eq!(camera.shake_strength(), 0.);
+        assert_eq!(camera.shake_offset(), Vector3::new(0., 0., 0.));
+        // the position is never moved by the shake
+        assert_eq!(*camera.position(), Point3::new(0., 0., 0.));
+    }
+
+    #[test]
+    fn pitch_is_clamped_to_the_limits() {
+        let mut camera = Camera::new(Deg(90.), 1., 0.1, 100., Point3::new(0., 0., 0.));
+        camera.rotate_camera(Deg(120.), Deg(0.));
+        assert_eq!(camera.pitch(), MAX_PITCH);
+        camera.rotate_camera(Deg(-300.), Deg(0.));
+        assert_eq!(camera.pitch(), MIN_PITCH);
+
+        // the current pitch is clamped to new limits
+        camera.set_pitch_limits(Deg(-30.), Deg(30.));
+        assert!((camera.pitch() - Rad::from(Deg(-30.))).0.abs() < 1e-6);
+        camera.rotate_camera(Deg(100.), Deg(0.));
+        assert!((camera.pitch() - Rad::from(Deg(30.))).0.abs() < 1e-6);
+
+        // without limits the pitch wraps around
+        camera.set_full_pitch_rotation();
+        camera.rotate_camera(Deg(300.), Deg(0.));
+        assert!((camera.pitch() - Rad::from(Deg(-30.))).0.abs() < 1e-5);
+    }
+
+    #[test]
+    fn heading_wraps_at_360() {
+        let mut camera = Camera::new(Deg(90.), 1., 0.1, 100., Point3::new(0., 0., 0.));
+        // a yaw of `0` looks at south
+        assert!((camera.heading() - 180.).abs() < 1e-3);
+        assert_eq!(compass_direction(camera.heading()), 'S');
+
+        for yaw in [-180., 180., 540., -180. - 1e-5, 180. - 1e-5] {
+            camera.set_orientation(Deg(yaw), Deg(0.));
+            let heading = camera.heading();
+            assert!(
+                (0. ..360.).contains(&heading),
+                "heading {heading} for yaw {yaw}"
+            );
+            assert_eq!(compass_direction(heading), 'N');
+        }
+
+        camera.set_orientation(Deg(-90.), Deg(0.));
+        assert!((camera.heading() - 90.).abs() < 1e-3);
+        assert_eq!(compass_direction(camera.heading()), 'E');
+    }
+
+    #[test]
+    fn grounded_movement_stays_horizontal() {
+        let mut camera = Camera::new(Deg(90.), 1., 0.1, 100., Point3::new(0., 0., 0.));
+        // looking 45 degrees up at `+z`
+        camera.rotate_camera(Deg(45.), Deg(0.));
+        let forward = Vector3::new(0., 0., 1.);
+
+        let movement = camera.movement_vector(forward);
+        assert!((movement - Vector3::new(0., 0., 1.)).magnitude() < 1e-5);
+
+        camera.set_movement_mode(MovementMode::Fly);
+        let movement = camera.movement_vector(forward);
+        let half_sqrt2 = std::f32::consts::FRAC_1_SQRT_2;
+        assert!((movement - Vector3::new(0., half_sqrt2, half_sqrt2)).magnitude() < 1e-5);
+        // strafing is horizontal in both modes
+        let right = camera.movement_vector(Vector3::new(1., 0., 0.));
+        assert!((right - Vector3::new(1., 0., 0.)).magnitude() < 1e-5);
+    }
+
+    #[test]
+    fn fov_offset_is_separate_from_the_zoom() {
+        let mut camera = Camera::new(Deg(70.), 1., 0.1, 100., Point3::new(0., 0., 0.));
+        camera.set_fov_offset(Deg(10.));
+        camera.zoom(Deg(20.));
+        camera.update(Duration::from_secs(1));
+        assert!((camera.fov() - Rad::from(Deg(100.))).0.abs() < 1e-5);
+        // the offset is not saved
+        assert!((camera.state().fov - Rad::from(Deg(90.))).0.abs() < 1e-5);
+
+        // resetting the zoom keeps the offset, and removing the offset
+        // goes back to the default
+        camera.reset_fov();
+        camera.update(Duration::from_secs(1));
+        assert!((camera.fov() - Rad::from(Deg(80.))).0.abs() < 1e-5);
+        camera.set_fov_offset(Deg(0.));
+        camera.update(Duration::from_secs(1));
+        assert!((camera.fov() - Rad::from(Deg(70.))).0.abs() < 1e-5);
+
+        // near the maximum, the offset is clamped without changing the zoom
+        camera.set_fov_immediate(MAX_FOV);
+        camera.set_fov_offset(Deg(10.));
+        camera.update(Duration::from_secs(1));
+        assert_eq!(camera.fov(), MAX_FOV);
+        camera.set_fov_offset(Deg(0.));
+        camera.update(Duration::from_secs(1));
+        assert_eq!(camera.fov(), MAX_FOV);
+    }
+
+    #[test]
+    fn orientation_round_trips_through_the_front_vector() {
+        let mut camera = Camera::new(Deg(90.), 1., 0.1, 100., Point3::new(0., 0., 0.));
+        // the limits are +-89 degrees
+        for (yaw, pitch) in [
+            (0., 0.),
+            (90., 30.),
+            (-135., -60.),
+            (-20., 88.9),
+            (170., -88.9),
+        ] {
+            camera.set_orientation(Deg(yaw), Deg(pitch));
+            let (yaw_rad, pitch_rad) = (Rad::from(Deg(yaw)), Rad::from(Deg(pitch)));
+            assert!((camera.yaw() - yaw_rad).0.abs() < 1e-5);
+            assert!((camera.pitch() - pitch_rad).0.abs() < 1e-5);
+
+            let expected = Vector3::new(
+                -pitch_rad.cos() * yaw_rad.sin(),
+                pitch_rad.sin(),
+                pitch_rad.cos() * yaw_rad.cos(),
+            );
+            let front = *camera.direction();
+            assert!((front - expected).magnitude() < 1e-5);
+
+            // and back from the front vector
+            let front_yaw = Rad::atan2(-front.x, front.z);
+            let front_pitch = Rad::asin(front.y);
+            assert!((front_yaw - yaw_rad).normalize_signed().0.abs() < 1e-4);
+            assert!((front_pitch - pitch_rad).0.abs() < 1e-4);
+        }
+
+        // past the limits, the pitch is clamped
+        camera.set_orientation(Deg(10.), Deg(95.));
+        assert_eq!(camera.pitch(), MAX_PITCH);
+    }
+
+    #[test]
+    fn zoom_reaches_the_target_within_the_zoom_duration() {
+        const STEPS: u32 = 10;
+        let mut camera = Camera::new(Deg(70.), 1., 0.1, 100., Point3::new(0., 0., 0.));
+        let start = camera.fov();
+        camera.zoom(Deg(-20.));
+        let target = camera.target_fov;
+        assert!((target - Rad::from(Deg(50.))).0.abs() < 1e-6);
+
+        let mut previous = start;
+        for _ in 0..STEPS - 1 {
+            camera.update(ZOOM_DURATION / STEPS);
+            let fov = camera.fov();
+            assert!(fov < previous && fov > target);
+            previous = fov;
+        }
+
+        // just past the duration, the last step would be short of the target
+        // by rounding otherwise
+        camera.update(ZOOM_DURATION / STEPS + Duration::from_millis(1));
+        assert_eq!(camera.fov(), target);
+    }
 }
@@ -0,0 +1,197 @@
+//! A fixed path for the camera, replayed to get the same frames every run
+//! when comparing the performance of changes.
+
+use std::{
+    fs::{self, File},
+    io::{self, BufWriter, Write},
+    path::Path,
+    time::Duration,
+};
+
+use cgmath::{Deg, EuclideanSpace, Point3};
+
+use crate::camera::Camera;
+
+/// Where the camera is at `time` seconds from the start of the path
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Keyframe {
+    pub time: f32,
+    pub position: Point3<f32>,
+    pub yaw: Deg<f32>,
+    pub pitch: Deg<f32>,
+}
+
+impl Keyframe {
+    /// Where `camera` is now, at `elapsed` time from the start of the path
+    pub fn from_camera(camera: &Camera, elapsed: Duration) -> Self {
+        Self {
+            time: elapsed.as_secs_f32(),
+            position: *camera.position(),
+            yaw: camera.yaw().into(),
+            pitch: camera.pitch().into(),
+        }
+    }
+}
+
+pub(crate) struct CameraPath {
+    // sorted by time
+    keyframes: Vec<Keyframe>,
+}
+
+impl CameraPath {
+    pub fn new(mut keyframes: Vec<Keyframe>) -> Self {
+        keyframes.sort_by(|a, b| a.time.total_cmp(&b.time));
+        Self { keyframes }
+    }
+
+    /// Adds a keyframe at the end, it must not be before the last one
+    pub fn push(&mut self, keyframe: Keyframe) {
+        debug_assert!(self
+            .keyframes
+            .last()
+            .is_none_or(|k| k.time <= keyframe.time));
+        self.keyframes.push(keyframe);
+    }
+
+    pub fn len(&self) -> usize {
+        self.keyframes.len()
+    }
+
+    /// Loads a path from a text file with a keyframe on every line:
+    ///
+    /// ```text
+    /// # time x y z yaw pitch
+    /// 0.0 0.0 125.0 -25.0 0.0 -20.0
+    /// 5.0 40.0 110.0 30.0 90.0 -10.0
+    /// ```
+    ///
+    /// `time` is in seconds and the angles are in degrees, empty lines and
+    /// lines starting with `#` are ignored.
+    pub fn load_from_path(path: &Path) -> io::Result<Self> {
+        let text = fs::read_to_string(path)?;
+
+        let mut keyframes = Vec::new();
+        for (i, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let invalid_line = || {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("invalid keyframe in line {}: {line}", i + 1),
+                )
+            };
+
+            let values = line
+                .split_whitespace()
+                .map(|v| v.parse::<f32>())
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(|_| invalid_line())?;
+            let [time, x, y, z, yaw, pitch]: [f32; 6] =
+                values.try_into().map_err(|_| invalid_line())?;
+
+            keyframes.push(Keyframe {
+                time,
+                position: Point3::new(x, y, z),
+                yaw: Deg(yaw),
+                pitch: Deg(pitch),
+            });
+        }
+
+        Ok(Self::new(keyframes))
+    }
+
+    /// Saves the path in the format read by [`CameraPath::load_from_path`]
+    pub fn save_to_path(&self, path: &Path) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+
+        writeln!(writer, "# time x y z yaw pitch")?;
+        for k in &self.keyframes {
+            writeln!(
+                writer,
+                "{} {} {} {} {} {}",
+                k.time, k.position.x, k.position.y, k.position.z, k.yaw.0, k.pitch.0
+            )?;
+        }
+
+        writer.flush()
+    }
+
+    /// The camera at `elapsed` time from the start, interpolated between the
+    /// keyframes around it, `None` when the path has ended
+    pub fn sample(&self, elapsed: Duration) -> Option<Keyframe> {
+        let time = elapsed.as_secs_f32();
+
+        let next = self.keyframes.iter().position(|k| k.time >= time)?;
+        if next == 0 {
+            return Some(self.keyframes[0]);
+        }
+
+        let a = self.keyframes[next - 1];
+        let b = self.keyframes[next];
+        let t = (time - a.time) / (b.time - a.time);
+
+        Some(Keyframe {
+            time,
+            position: Point3::from_vec(a.position.to_vec() * (1. - t) + b.position.to_vec() * t),
+            yaw: a.yaw + (b.yaw - a.yaw) * t,
+            pitch: a.pitch + (b.pitch - a.pitch) * t,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn save_and_load_keep_the_keyframes() {
+        let mut path = CameraPath::new(Vec::new());
+        for i in 0..4 {
+            path.push(Keyframe {
+                time: i as f32 * 0.25,
+                position: Point3::new(i as f32, 100.5, -3.25 * i as f32),
+                yaw: Deg(10. * i as f32),
+                pitch: Deg(-5.),
+            });
+        }
+
+        let file = std::env::temp_dir().join("minecraft-camera-path-test.txt");
+        path.save_to_path(&file).unwrap();
+        let loaded = CameraPath::load_from_path(&file).unwrap();
+        fs::remove_file(&file).unwrap();
+
+        assert_eq!(loaded.len(), path.len());
+        for (a, b) in loaded.keyframes.iter().zip(&path.keyframes) {
+            assert_eq!(a.time, b.time);
+            assert_eq!(a.position, b.position);
+            assert_eq!(a.yaw, b.yaw);
+            assert_eq!(a.pitch, b.pitch);
+        }
+    }
+
+    #[test]
+    fn sample_interpolates_and_ends() {
+        let path = CameraPath::new(vec![
+            Keyframe {
+                time: 1.,
+                position: Point3::new(10., 0., 0.),
+                yaw: Deg(90.),
+                pitch: Deg(0.),
+            },
+            Keyframe {
+                time: 0.,
+                position: Point3::new(0., 0., 0.),
+                yaw: Deg(0.),
+                pitch: Deg(0.),
+            },
+        ]);
+
+        let middle = path.sample(Duration::from_millis(500)).unwrap();
+        assert_eq!(middle.position, Point3::new(5., 0., 0.));
+        assert_eq!(middle.yaw, Deg(45.));
+        assert!(path.sample(Duration::from_millis(1001)).is_none());
+    }
+}
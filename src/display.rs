@@ -8,7 +8,9 @@ use vulkano::{
     format::Format,
     image::{ImageUsage, SwapchainImage},
     instance::{Instance, InstanceCreateInfo},
-    swapchain::{AcquireError, Surface, Swapchain, SwapchainCreateInfo, SwapchainCreationError},
+    swapchain::{
+        AcquireError, PresentMode, Surface, Swapchain, SwapchainCreateInfo, SwapchainCreationError,
+    },
     sync::{self, FlushError, GpuFuture},
 };
 use vulkano_win::VkSurfaceBuild;
@@ -49,16 +51,101 @@ pub(crate) struct Display {
     device: Arc<Device>,
     queue: Arc<Queue>,
     surface: Arc<Surface<Window>>,
-    swapchain: Arc<Swapchain<Window>>,
+    // only missing while switching to another device, see `swapchain()`
+    swapchain: Option<Arc<Swapchain<Window>>>,
     swapchain_images: Vec<Arc<SwapchainImage<Window>>>,
 
     current_image_num: usize,
     recreate_swapchain: bool,
+    /// used the next time the swapchain is recreated
+    present_mode: PresentMode,
     previous_frame_end: Option<Box<dyn GpuFuture>>,
+    cursor_captured: bool,
+}
+
+/// Which physical device to use, the options are checked in order:
+/// `index`, `name` then `device_type`. If none of them match a usable device,
+/// the discrete GPU is preferred, then integrated, virtual and CPU.
+#[derive(Debug, Default, Clone)]
+struct DevicePreference {
+    index: Option<usize>,
+    name: Option<String>,
+    device_type: Option<PhysicalDeviceType>,
+}
+
+/// Options for creating a [`Display`]
+pub(crate) struct DisplayBuilder {
+    swapchain_image_usage: ImageUsage,
+    present_mode: PresentMode,
+    device_preference: DevicePreference,
+}
+
+impl DisplayBuilder {
+    pub fn new(swapchain_image_usage: ImageUsage) -> Self {
+        Self {
+            swapchain_image_usage,
+            present_mode: PresentMode::Fifo,
+            device_preference: DevicePreference::default(),
+        }
+    }
+
+    /// If `present_mode` is not supported by the device, `Fifo` is used
+    /// (which is always supported)
+    pub fn present_mode(mut self, present_mode: PresentMode) -> Self {
+        self.present_mode = present_mode;
+        self
+    }
+
+    /// Prefers the device whose name contains `name` (case insensitive)
+    pub fn prefer_device_name(mut self, name: &str) -> Self {
+        self.device_preference.name = Some(name.to_lowercase());
+        self
+    }
+
+    /// Prefers the first device of type `device_type`
+    #[allow(dead_code)]
+    pub fn prefer_device_type(mut self, device_type: PhysicalDeviceType) -> Self {
+        self.device_preference.device_type = Some(device_type);
+        self
+    }
+
+    /// Uses the device at `index` in the list printed on startup
+    pub fn device_index(mut self, index: usize) -> Self {
+        self.device_preference.index = Some(index);
+        self
+    }
+
+    pub fn build(self, event_loop: &EventLoop<()>) -> Display {
+        Display::from_builder(event_loop, self)
+    }
 }
 
 impl Display {
+    #[allow(dead_code)]
     pub fn new(event_loop: &EventLoop<()>, swapchain_image_usage: ImageUsage) -> Self {
+        DisplayBuilder::new(swapchain_image_usage).build(event_loop)
+    }
+
+    /// Creates the display with the requested `present_mode`, if its not
+    /// supported by the device, `Fifo` is used (which is always supported)
+    #[allow(dead_code)]
+    pub fn with_present_mode(
+        event_loop: &EventLoop<()>,
+        swapchain_image_usage: ImageUsage,
+        present_mode: PresentMode,
+    ) -> Self {
+        DisplayBuilder::new(swapchain_image_usage)
+            .present_mode(present_mode)
+            .build(event_loop)
+    }
+
+    fn from_builder(event_loop: &EventLoop<()>, builder: DisplayBuilder) -> Self {
+        let DisplayBuilder {
+            swapchain_image_usage,
+            present_mode,
+            device_preference,
+        } = builder;
+
         let required_extensions = vulkano_win::required_extensions();
 
         let instance = Instance::new(InstanceCreateInfo {
@@ -71,51 +158,27 @@ impl Display {
             .build_vk_surface(event_loop, instance.clone())
             .unwrap();
 
-        let device_extensions = DeviceExtensions {
-            khr_swapchain: true,
-            ..DeviceExtensions::none()
-        };
-
-        let (physical_device, queue_family) = PhysicalDevice::enumerate(&instance)
-            .filter(|&p| p.supported_extensions().is_superset_of(&device_extensions))
-            .filter_map(|p| {
-                p.queue_families()
-                    .find(|&q| {
-                        q.supports_graphics() && q.supports_surface(&surface).unwrap_or(false)
-                    })
-                    .map(|q| (p, q))
-            })
-            .min_by_key(|(p, _)| match p.properties().device_type {
-                PhysicalDeviceType::DiscreteGpu => 0,
-                PhysicalDeviceType::IntegratedGpu => 1,
-                PhysicalDeviceType::VirtualGpu => 2,
-                PhysicalDeviceType::Cpu => 3,
-                PhysicalDeviceType::Other => 4,
-            })
-            .unwrap();
-
-        println!(
-            "Using device: {} (type: {:?})",
-            physical_device.properties().device_name,
-            physical_device.properties().device_type,
-        );
-
-        let (device, mut queues) = Device::new(
-            physical_device,
-            DeviceCreateInfo {
-                enabled_extensions: physical_device
-                    .required_extensions()
-                    .union(&device_extensions),
-                queue_create_infos: vec![QueueCreateInfo::family(queue_family)],
-                ..Default::default()
-            },
+        Self::with_surface(
+            surface,
+            swapchain_image_usage,
+            present_mode,
+            &device_preference,
         )
-        .unwrap();
+    }
 
-        let queue = queues.next().unwrap();
+    /// Creates the device and the swapchain presenting to `surface`, the
+    /// surface must not have a swapchain already
+    fn with_surface(
+        surface: Arc<Surface<Window>>,
+        swapchain_image_usage: ImageUsage,
+        present_mode: PresentMode,
+        device_preference: &DevicePreference,
+    ) -> Self {
+        let (device, queue) = create_device(surface.instance(), Some(&surface), device_preference);
+        let physical_device = device.physical_device();
 
         // create swapchains
-        let (swapchain, swapchain_images) = {
+        let ((swapchain, swapchain_images), present_mode) = {
             let surface_capabilities = physical_device
                 .surface_capabilities(&surface, Default::default())
                 .unwrap();
@@ -127,12 +190,21 @@ impl Display {
                     .0,
             );
 
-            Swapchain::new(
+            let present_mode = if supports_present_mode(&device, &surface, present_mode) {
+                present_mode
+            } else {
+                eprintln!("WARN: present mode {present_mode:?} is not supported, using Fifo");
+                PresentMode::Fifo
+            };
+            println!("Using present mode: {present_mode:?}");
+
+            let swapchain = Swapchain::new(
                 device.clone(),
                 surface.clone(),
                 SwapchainCreateInfo {
                     min_image_count: surface_capabilities.min_image_count,
                     image_format,
+                    present_mode,
                     image_extent: surface.window().inner_size().into(),
                     image_usage: swapchain_image_usage,
                     composite_alpha: surface_capabilities
@@ -143,7 +215,9 @@ impl Display {
                     ..Default::default()
                 },
             )
-            .unwrap()
+            .unwrap();
+
+            (swapchain, present_mode)
         };
 
         let previous_frame_end = Some(sync::now(device.clone()).boxed());
@@ -152,13 +226,70 @@ impl Display {
             device,
             queue,
             surface,
-            swapchain,
+            swapchain: Some(swapchain),
             swapchain_images,
 
             current_image_num: 0,
             recreate_swapchain: false,
+            present_mode,
             previous_frame_end,
+            cursor_captured: false,
+        }
+    }
+
+    /// Grabs and hides the cursor, or releases it
+    pub fn set_cursor_captured(&mut self, captured: bool) {
+        let window = self.surface.window();
+
+        // not all platforms support grabbing
+        if let Err(e) = window.set_cursor_grab(captured) {
+            eprintln!("Failed to grab the cursor: {e}");
         }
+        window.set_cursor_visible(!captured);
+        self.cursor_captured = captured;
+    }
+
+    pub fn cursor_captured(&self) -> bool {
+        self.cursor_captured
+    }
+
+    /// The index of the device used, in the list printed on startup
+    pub fn device_index(&self) -> usize {
+        self.device.physical_device().index()
+    }
+
+    /// Number of physical devices, usable or not
+    pub fn device_count(&self) -> usize {
+        PhysicalDevice::enumerate(self.surface.instance()).len()
+    }
+
+    /// Recreates the device, queue and swapchain on the device at `index`,
+    /// keeping the window. If that device can't present to the window,
+    /// the best one is picked like on startup.
+    ///
+    /// Everything created from the previous queue must be recreated too, see
+    /// [`Engine::switch_queue`](crate::engine::Engine::switch_queue).
+    pub fn switch_device(&mut self, index: usize) {
+        let swapchain_image_usage = self.swapchain().create_info().image_usage;
+
+        // waits for the gpu to finish the last frame
+        self.previous_frame_end = None;
+        // only one swapchain can present to a surface at a time
+        self.swapchain_images.clear();
+        self.swapchain = None;
+
+        let preference = DevicePreference {
+            index: Some(index),
+            ..Default::default()
+        };
+        let cursor_captured = self.cursor_captured;
+        *self = Self::with_surface(
+            self.surface.clone(),
+            swapchain_image_usage,
+            self.present_mode,
+            &preference,
+        );
+        self.cursor_captured = cursor_captured;
     }
 
     pub fn resize(&mut self) {
@@ -173,8 +304,32 @@ impl Display {
         self.swapchain_images[self.current_image_num].clone()
     }
 
+    pub fn present_mode(&self) -> PresentMode {
+        self.present_mode
+    }
+
+    pub fn supports_present_mode(&self, present_mode: PresentMode) -> bool {
+        supports_present_mode(&self.device, &self.surface, present_mode)
+    }
+
+    /// Switches to `present_mode` from the next frame, if its not supported
+    /// by the device, the current mode is kept and `false` is returned
+    pub fn set_present_mode(&mut self, present_mode: PresentMode) -> bool {
+        if !self.supports_present_mode(present_mode) {
+            eprintln!(
+                "WARN: present mode {present_mode:?} is not supported, keeping {:?}",
+                self.present_mode
+            );
+            return false;
+        }
+
+        self.present_mode = present_mode;
+        self.recreate_swapchain = true;
+        true
+    }
+
     pub fn swapchain_image_format(&self) -> Format {
-        self.swapchain.image_format()
+        self.swapchain().image_format()
     }
 
     pub fn begin_frame(&mut self) -> Result<Box<dyn GpuFuture>, FrameError> {
@@ -196,7 +351,7 @@ impl Display {
         }
 
         let (image_num, suboptimal, acquire_future) =
-            match vulkano::swapchain::acquire_next_image(self.swapchain.clone(), None) {
+            match vulkano::swapchain::acquire_next_image(self.swapchain().clone(), None) {
                 Ok(r) => r,
                 Err(AcquireError::OutOfDate) => {
                     self.recreate_swapchain = true;
@@ -221,7 +376,7 @@ impl Display {
         let future = future
             .then_swapchain_present(
                 self.queue.clone(),
-                self.swapchain.clone(),
+                self.swapchain().clone(),
                 self.current_image_num,
             )
             .then_signal_fence_and_flush();
@@ -243,6 +398,12 @@ impl Display {
 }
 
 impl Display {
+    fn swapchain(&self) -> &Arc<Swapchain<Window>> {
+        self.swapchain
+            .as_ref()
+            .expect("the swapchain is only missing while switching devices")
+    }
+
     fn is_empty(&self) -> bool {
         let dimensions = self.surface.window().inner_size();
         dimensions.width == 0 || dimensions.height == 0
@@ -250,9 +411,11 @@ impl Display {
 
     fn recreate_swapchains(&mut self) {
         let dimensions = self.surface.window().inner_size();
-        let (new_swapchain, new_images) = match self.swapchain.recreate(SwapchainCreateInfo {
+        // `create_info` keeps the rest of the options
+        let (new_swapchain, new_images) = match self.swapchain().recreate(SwapchainCreateInfo {
             image_extent: dimensions.into(),
-            ..self.swapchain.create_info()
+            present_mode: self.present_mode,
+            ..self.swapchain().create_info()
         }) {
             Ok(r) => r,
             // This error tends to happen when the user is manually resizing the window.
@@ -261,7 +424,120 @@ impl Display {
             Err(e) => panic!("Failed to recreate swapchain: {:?}", e),
         };
 
-        self.swapchain = new_swapchain;
+        self.swapchain = Some(new_swapchain);
         self.swapchain_images = new_images;
     }
 }
+
+fn supports_present_mode(
+    device: &Device,
+    surface: &Surface<Window>,
+    present_mode: PresentMode,
+) -> bool {
+    device
+        .physical_device()
+        .surface_present_modes(surface)
+        .is_ok_and(|mut modes| modes.any(|mode| mode == present_mode))
+}
+
+/// Picks the device matching `preference` or the best one otherwise, and
+/// creates it with a single graphics queue, if `surface` is given, the queue
+/// must be able to present to it
+fn create_device(
+    instance: &Arc<Instance>,
+    surface: Option<&Arc<Surface<Window>>>,
+    preference: &DevicePreference,
+) -> (Arc<Device>, Arc<Queue>) {
+    // the swapchain is only needed when presenting to a surface
+    let device_extensions = DeviceExtensions {
+        khr_swapchain: surface.is_some(),
+        ..DeviceExtensions::none()
+    };
+
+    println!("Available devices:");
+    for p in PhysicalDevice::enumerate(instance) {
+        println!(
+            "  [{}] {} (type: {:?})",
+            p.index(),
+            p.properties().device_name,
+            p.properties().device_type,
+        );
+    }
+
+    let candidates = PhysicalDevice::enumerate(instance)
+        .filter(|&p| p.supported_extensions().is_superset_of(&device_extensions))
+        .filter_map(|p| {
+            p.queue_families()
+                .find(|&q| {
+                    let supports_surface = match surface {
+                        Some(surface) => q.supports_surface(surface).unwrap_or(false),
+                        None => true,
+                    };
+                    q.supports_graphics() && supports_surface
+                })
+                .map(|q| (p, q))
+        })
+        .collect::<Vec<_>>();
+
+    let preferred = candidates
+        .iter()
+        .find(|(p, _)| preference.index == Some(p.index()))
+        .or_else(|| {
+            let name = preference.name.as_ref()?;
+            candidates
+                .iter()
+                .find(|(p, _)| p.properties().device_name.to_lowercase().contains(name))
+        })
+        .or_else(|| {
+            candidates
+                .iter()
+                .find(|(p, _)| Some(p.properties().device_type) == preference.device_type)
+        });
+
+    let has_preference =
+        preference.index.is_some() || preference.name.is_some() || preference.device_type.is_some();
+    if preferred.is_none() && has_preference {
+        eprintln!("WARN: no usable device matches {preference:?}, picking the best one");
+    }
+
+    let &(physical_device, queue_family) = preferred
+        .or_else(|| {
+            candidates
+                .iter()
+                .min_by_key(|(p, _)| match p.properties().device_type {
+                    PhysicalDeviceType::DiscreteGpu => 0,
+                    PhysicalDeviceType::IntegratedGpu => 1,
+                    PhysicalDeviceType::VirtualGpu => 2,
+                    PhysicalDeviceType::Cpu => 3,
+                    PhysicalDeviceType::Other => 4,
+                })
+        })
+        .unwrap();
+
+    println!(
+        "Using device: {} (type: {:?})",
+        physical_device.properties().device_name,
+        physical_device.properties().device_type,
+    );
+
+    let (device, mut queues) = Device::new(
+        physical_device,
+        DeviceCreateInfo {
+            enabled_extensions: physical_device
+                .required_extensions()
+                .union(&device_extensions),
+            queue_create_infos: vec![QueueCreateInfo::family(queue_family)],
+            ..Default::default()
+        },
+    )
+    .unwrap();
+
+    (device, queues.next().unwrap())
+}
+
+/// Creates a device and a queue without a window, used to render off-screen
+pub(crate) fn headless_queue() -> Arc<Queue> {
+    let instance = Instance::new(InstanceCreateInfo::default()).unwrap();
+
+    create_device(&instance, None, &DevicePreference::default()).1
+}
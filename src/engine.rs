@@ -1,36 +1,68 @@
-use std::{f32::consts::PI, sync::Arc, time::Duration};
+use std::{
+    collections::VecDeque,
+    f32::consts::PI,
+    io,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
 
-use cgmath::{Deg, Point2, Vector3};
+use cgmath::{Deg, InnerSpace, Matrix4, Point2, Point3, Vector2, Vector3};
 use vulkano::{
-    buffer::{BufferUsage, CpuAccessibleBuffer, CpuBufferPool, TypedBufferAccess},
+    buffer::{BufferContents, BufferUsage, CpuAccessibleBuffer, CpuBufferPool, TypedBufferAccess},
     command_buffer::{
         AutoCommandBufferBuilder, CommandBufferUsage, PrimaryAutoCommandBuffer, SubpassContents,
     },
-    descriptor_set::{SingleLayoutDescSetPool, WriteDescriptorSet},
+    descriptor_set::{PersistentDescriptorSet, SingleLayoutDescSetPool, WriteDescriptorSet},
     device::Queue,
     format::{ClearValue, Format},
-    image::{view::ImageView, AttachmentImage, ImageAccess},
+    image::{
+        view::ImageView, AttachmentImage, ImageAccess, ImageDimensions, ImageUsage, ImmutableImage,
+        MipmapsCount, SampleCount,
+    },
+    memory::DeviceMemoryAllocationError,
     pipeline::{
         graphics::{
-            color_blend::ColorBlendState,
+            color_blend::{ColorBlendState, ColorComponents},
             depth_stencil::{CompareOp, DepthState, DepthStencilState},
             input_assembly::{InputAssemblyState, PrimitiveTopology},
+            rasterization::{DepthBias, DepthBiasState, RasterizationState},
             vertex_input::BuffersDefinition,
             viewport::{Viewport, ViewportState},
         },
         GraphicsPipeline, PartialStateMode, Pipeline, PipelineBindPoint, StateMode,
     },
     render_pass::{Framebuffer, FramebufferCreateInfo, RenderPass, Subpass},
-    sync::GpuFuture,
+    sampler::{Filter, Sampler, SamplerAddressMode, SamplerCreateInfo},
+    sync::{self, GpuFuture},
 };
 use winit::event::{
-    ElementState, Event, KeyboardInput, MouseButton, MouseScrollDelta, VirtualKeyCode, WindowEvent,
+    DeviceEvent, ElementState, Event, KeyboardInput, ModifiersState, MouseButton, MouseScrollDelta,
+    VirtualKeyCode, WindowEvent,
 };
 
+#[cfg(feature = "gamepad")]
+use crate::gamepad::GamepadInput;
 use crate::{
-    camera::Camera,
-    object::{cube::Cube, Instance, Mesh, Vertex},
-    world::{CubeLookAt, World},
+    atlas,
+    block::{BlockFace, BlockShape, BlockType},
+    camera::{compass_direction, Camera, DepthMode, MovementMode},
+    camera_path::{CameraPath, Keyframe},
+    history::{Edit, EditHistory},
+    input::{number_key, Action, CameraControlConfig, KeyBindings},
+    light::{PointLight, MAX_POINT_LIGHTS},
+    math::Aabb,
+    minimap::{Corner, Minimap},
+    object::{cube::Cube, slab::Slab, square::Square, Instance, Mesh, MeshView, Vertex, NO_FACE},
+    particles::ParticleSystem,
+    player::Player,
+    schematic::Schematic,
+    screenshot::{self, UnsupportedFormat},
+    sky::{self, Sky},
+    text,
+    world::{
+        self, chunk_id, Chunk, CubeLookAt, OutOfBounds, PendingSave, RenderStats, World, CHUNK_SIZE,
+    },
 };
 
 #[allow(clippy::needless_question_mark)]
@@ -50,7 +82,12 @@ mod cubes_vs {
 mod cubes_fs {
     vulkano_shaders::shader! {
         ty: "fragment",
-        path: "src/shaders/cubes.frag.glsl"
+        path: "src/shaders/cubes.frag.glsl",
+        types_meta: {
+            use bytemuck::{Pod, Zeroable};
+
+            #[derive(Clone, Copy, Zeroable, Pod)]
+        },
     }
 }
 
@@ -75,6 +112,32 @@ mod ui_vs {
     }
 }
 
+#[allow(clippy::needless_question_mark)]
+mod billboard_vs {
+    vulkano_shaders::shader! {
+        ty: "vertex",
+        path: "src/shaders/billboard.vert.glsl",
+        types_meta: {
+            use bytemuck::{Pod, Zeroable};
+
+            #[derive(Clone, Copy, Zeroable, Pod)]
+        },
+    }
+}
+
+#[allow(clippy::needless_question_mark)]
+mod sky_vs {
+    vulkano_shaders::shader! {
+        ty: "vertex",
+        path: "src/shaders/sky.vert.glsl",
+        types_meta: {
+            use bytemuck::{Pod, Zeroable};
+
+            #[derive(Clone, Copy, Zeroable, Pod)]
+        },
+    }
+}
+
 #[allow(clippy::needless_question_mark)]
 mod ui_fs {
     vulkano_shaders::shader! {
@@ -83,60 +146,593 @@ mod ui_fs {
     }
 }
 
-/// Minecraft engine and renderer (for now)
-pub(crate) struct Engine {
+/// A snapshot of the engine state, useful for bug reports
+#[derive(Debug)]
+pub(crate) struct DebugSnapshot {
+    pub chunk_count: usize,
+    pub block_count: usize,
+    pub estimated_memory: usize,
+    pub camera_position: Point3<f32>,
+    pub camera_yaw: Deg<f32>,
+    pub camera_pitch: Deg<f32>,
+    pub camera_fov: Deg<f32>,
+    pub current_chunk: (i32, i32),
+    pub looking_at: Option<Point3<i32>>,
+    pub looking_at_distance: Option<f32>,
+    /// from the last rendered frame
+    pub render_stats: RenderStats,
+}
+
+impl DebugSnapshot {
+    /// `camera_orientation` is the `(yaw, pitch)` of the camera
+    fn new(
+        world: &World,
+        camera_position: Point3<f32>,
+        camera_orientation: (Deg<f32>, Deg<f32>),
+        camera_fov: Deg<f32>,
+        looking_at: Option<&CubeLookAt>,
+        render_stats: RenderStats,
+    ) -> Self {
+        let (camera_yaw, camera_pitch) = camera_orientation;
+
+        Self {
+            chunk_count: world.chunk_count(),
+            block_count: world.block_count(),
+            estimated_memory: world.estimated_memory(),
+            camera_position,
+            camera_yaw,
+            camera_pitch,
+            camera_fov,
+            current_chunk: chunk_id(camera_position.map(|a| a.floor() as i32)),
+            looking_at: looking_at.map(|c| c.cube),
+            looking_at_distance: looking_at.map(|c| c.t),
+            render_stats,
+        }
+    }
+}
+
+impl std::fmt::Display for DebugSnapshot {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "chunks: {}, blocks: {}, memory: ~{:.2} MB, camera: ({:.2}, {:.2}, {:.2}) \
+             yaw: {:.1} pitch: {:.1} fov: {:.1}, chunk: {:?}, looking at: {:?} ({:.2?}), \
+             visible chunks: {}/{}, visible instances: {}/{}",
+            self.chunk_count,
+            self.block_count,
+            self.estimated_memory as f32 / (1024. * 1024.),
+            self.camera_position.x,
+            self.camera_position.y,
+            self.camera_position.z,
+            self.camera_yaw.0,
+            self.camera_pitch.0,
+            self.camera_fov.0,
+            self.current_chunk,
+            self.looking_at.map(|p| (p.x, p.y, p.z)),
+            self.looking_at_distance,
+            self.render_stats.visible_chunks,
+            self.render_stats.chunks,
+            self.render_stats.visible_instances,
+            self.render_stats.instances,
+        )
+    }
+}
+
+/// The blocks that can be selected for placing with the number keys
+const HOTBAR: [BlockType; 4] = [
+    BlockType::Grass,
+    BlockType::Dirt,
+    BlockType::Stone,
+    BlockType::Water,
+];
+
+/// Distance added to the load radius for the far plane, so the far edges of
+/// the loaded chunks and the blocks above and below the camera are not cut
+const FAR_PLANE_MARGIN: f32 = 48.;
+
+/// Line list of the 12 edges of the [`Cube`] mesh
+const CUBE_EDGES: [u32; 24] = [
+    0, 1, // front t
+    1, 3, // front r
+    0, 2, // front l
+    2, 3, // front b
+    //
+    4, 5, // back t
+    5, 7, // back r
+    4, 6, // back l
+    6, 7, // back b
+    //
+    1, 5, // right t
+    3, 7, // right b
+    //
+    0, 4, // left t
+    2, 6, // left b
+];
+
+/// The vertices of the [`Cube`] mesh without face colors, for the lines that
+/// only use their instance color
+fn cube_line_vertices() -> Vec<Vertex> {
+    Cube::mesh()
+        .0
+        .into_iter()
+        .map(|vertex| Vertex {
+            face: NO_FACE,
+            ..vertex
+        })
+        .collect()
+}
+
+/// Number of block edits that can be undone
+const HISTORY_LIMIT: usize = 256;
+
+/// The default time a newly loaded chunk takes to fade in
+const CHUNK_FADE_DURATION: Duration = Duration::from_millis(400);
+
+/// Where `Ctrl+S` saves the world
+const QUICK_SAVE_PATH: &str = "world.sav";
+
+/// Number of colors in `BlockColors` of the cubes vertex shader, one for each
+/// face of every block type. The array in the shader must have this length
+const FACE_COLORS_LEN: usize = BlockType::ALL.len() * BlockFace::ALL.len();
+
+// fails to build if a block type is added without growing the shader array
+const _: () = assert!(
+    std::mem::size_of::<cubes_vs::ty::BlockColors>() == FACE_COLORS_LEN * 16,
+    "`face_colors` in cubes.vert.glsl must have `FACE_COLORS_LEN` colors"
+);
+
+/// Format of the depth attachment of the render pass
+const DEPTH_FORMAT: Format = Format::D32_SFLOAT;
+
+/// The attributes of an attachment image that must match the render pass
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct AttachmentDesc {
+    size: [u32; 2],
+    format: Format,
+    samples: SampleCount,
+}
+
+impl AttachmentDesc {
+    fn of(image: &AttachmentImage) -> Self {
+        Self {
+            size: image.dimensions().width_height(),
+            format: image.format(),
+            samples: image.samples(),
+        }
+    }
+}
+
+/// Returns `true` if an attachment described by `current` (`None` if it
+/// wasn't created yet) can't be used where `wanted` is needed
+fn needs_recreation(current: Option<AttachmentDesc>, wanted: AttachmentDesc) -> bool {
+    current != Some(wanted)
+}
+
+/// Moves `velocity` towards `target` by `acceleration` per second while there
+/// is input (`target` isn't zero), or by `friction` per second without it.
+/// A rate of `0` reaches the target at once
+fn eased_velocity(
+    velocity: Vector3<f32>,
+    target: Vector3<f32>,
+    acceleration: f32,
+    friction: f32,
+    delta: Duration,
+) -> Vector3<f32> {
+    let rate = if target.magnitude2() > 0. {
+        acceleration
+    } else {
+        friction
+    };
+    let step = rate * delta.as_secs_f32();
+    let difference = target - velocity;
+    if rate == 0. || difference.magnitude() <= step {
+        target
+    } else {
+        velocity + difference.normalize() * step
+    }
+}
+
+/// Draws every mesh with `draw`, the meshes whose buffers can't be allocated
+/// are skipped so the others are still drawn. Returns the last error
+fn draw_each<M>(
+    meshes: impl IntoIterator<Item = M>,
+    mut draw: impl FnMut(M) -> Result<(), DeviceMemoryAllocationError>,
+) -> Option<DeviceMemoryAllocationError> {
+    meshes
+        .into_iter()
+        .filter_map(|mesh| draw(mesh).err())
+        .last()
+}
+
+/// Copies of `instances` with their alpha multiplied by `fade`
+fn faded_instances(instances: &[Instance], fade: f32) -> Vec<Instance> {
+    instances
+        .iter()
+        .map(|&instance| Instance {
+            color: [
+                instance.color[0],
+                instance.color[1],
+                instance.color[2],
+                instance.color[3] * fade,
+            ],
+            ..instance
+        })
+        .collect()
+}
+
+/// Number of frames used to average the frame time
+const FRAME_TIMES_COUNT: usize = 30;
+
+/// Options for creating the [`Engine`]
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct EngineConfig {
+    /// chunks are loaded in this number of chunks around the player
+    pub chunk_radius: u32,
+    /// the average height of the generated terrain
+    pub base_height: i32,
+    /// the seed of the generated terrain
+    pub seed: u32,
+    /// number of cubes in the `y` axis of the world
+    pub world_height: i32,
+    /// number of msaa samples per pixel (`1`, `2`, `4` or `8`), `1` disables msaa
+    pub samples: u32,
+    /// where the camera starts
+    pub start_position: Point3<f32>,
+    pub start_yaw: Deg<f32>,
+    pub start_pitch: Deg<f32>,
+    /// the field of view the camera starts with, and is reset to
+    pub fov: Deg<f32>,
+    /// how depth is stored, reversed depth is more precise far away
+    pub depth_mode: DepthMode,
+    /// chunks further than this many blocks from the camera are drawn with
+    /// only their surface, `None` draws all the chunks in full detail
+    pub lod_distance: Option<f32>,
+}
+
+impl Default for EngineConfig {
+    fn default() -> Self {
+        Self {
+            chunk_radius: 4,
+            base_height: 60,
+            seed: 0,
+            world_height: world::DEFAULT_HEIGHT,
+            samples: 1,
+            start_position: Point3::new(0., 125., -25.),
+            start_yaw: Deg(0.),
+            start_pitch: Deg(0.),
+            fov: Deg(45.),
+            depth_mode: DepthMode::Reversed,
+            lod_distance: None,
+        }
+    }
+}
+
+/// The smallest outline scale, below this the outline is too close to the
+/// block faces and z-fights with them
+const MIN_OUTLINE_SCALE: f32 = 1.005;
+
+/// How the outline of the block we are looking at is drawn
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct OutlineStyle {
+    pub color: [f32; 4],
+    /// scale of the outline cube relative to a block, clamped to [`MIN_OUTLINE_SCALE`]
+    pub scale: f32,
+}
+
+impl Default for OutlineStyle {
+    fn default() -> Self {
+        Self {
+            color: [1., 1., 1., 1.],
+            scale: 1.012,
+        }
+    }
+}
+
+/// The shape of the crosshair in the middle of the screen
+#[allow(dead_code)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum CrosshairShape {
+    Cross,
+    /// a filled square
+    Dot,
+    None,
+}
+
+/// How the crosshair is drawn
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct CrosshairStyle {
+    pub shape: CrosshairShape,
+    /// width and height in pixels
+    pub size: f32,
+    pub color: [f32; 4],
+}
+
+impl Default for CrosshairStyle {
+    fn default() -> Self {
+        Self {
+            shape: CrosshairShape::Cross,
+            size: 20.,
+            color: [1., 1., 1., 1.],
+        }
+    }
+}
+
+/// A camera path being replayed, and the time since it started
+struct PlayingCameraPath {
+    path: CameraPath,
+    elapsed: Duration,
+}
+
+/// A camera path being recorded with `F7`
+struct RecordingCameraPath {
+    path: CameraPath,
+    elapsed: Duration,
+    since_keyframe: Duration,
+}
+
+/// Replayed camera paths move by this much every frame, whatever the frame
+/// time is, so every run draws the same frames
+const CAMERA_PATH_STEP: Duration = Duration::from_micros(16_667);
+/// Time between the keyframes of a recorded camera path
+const CAMERA_PATH_KEYFRAME_INTERVAL: Duration = Duration::from_millis(250);
+/// Where `F7` saves the recorded camera path
+const RECORDED_CAMERA_PATH: &str = "camera_path.txt";
+
+/// The most particles alive at the same time
+const MAX_PARTICLES: usize = 1024;
+
+/// A frame copied into a buffer, waiting for the gpu to finish
+struct PendingScreenshot {
+    buffer: Arc<CpuAccessibleBuffer<[u8]>>,
+    size: [u32; 2],
+}
+
+/// The objects made with the device, everything else in [`Engine`] is
+/// kept when switching to another gpu
+struct GpuResources {
     queue: Arc<Queue>,
+    // the format of the images we render into
+    image_format: Format,
 
     render_pass: Arc<RenderPass>,
+    // draws the ui on top of the upscaled scene
+    ui_render_pass: Arc<RenderPass>,
     cubes_graphics_pipeline: Arc<GraphicsPipeline>,
+    // draws transparent blocks without writing depth, and waves the surface
+    // of liquids (all the transparent blocks are liquids)
+    cubes_transparent_graphics_pipeline: Arc<GraphicsPipeline>,
     cubes_line_graphics_pipeline: Arc<GraphicsPipeline>,
+    // only writes depth, used to hide the lines behind faces in wireframe
+    cubes_depth_graphics_pipeline: Arc<GraphicsPipeline>,
+    // with the depth prepass, the opaque faces fill the depth buffer with the
+    // first pipeline, then only the closest faces are shaded with the second
+    cubes_prepass_graphics_pipeline: Arc<GraphicsPipeline>,
+    cubes_equal_graphics_pipeline: Arc<GraphicsPipeline>,
     ui_graphics_pipeline: Arc<GraphicsPipeline>,
+    // draws the sky gradient behind the scene
+    sky_graphics_pipeline: Arc<GraphicsPipeline>,
+    // draws `Square`s facing the camera, for the particles
+    billboard_graphics_pipeline: Arc<GraphicsPipeline>,
     uniform_buffer_pool: CpuBufferPool<cubes_vs::ty::UniformData>,
+    point_lights_buffer_pool: CpuBufferPool<cubes_fs::ty::PointLights>,
+    // the face colors of all block types, they never change
+    block_colors_buffer: Arc<CpuAccessibleBuffer<cubes_vs::ty::BlockColors>>,
     descriptor_set_pool: SingleLayoutDescSetPool,
+    atlas_descriptor_set: Arc<PersistentDescriptorSet>,
+
+    // created with the size of the first frame
+    depth_buffer: Option<Arc<ImageView<AttachmentImage>>>,
+    // msaa samples of the scene color and depth attachments
+    samples: SampleCount,
+    // the depth compare and clear value of the pipelines match this
+    depth_mode: DepthMode,
+    // the multisampled color attachment, resolved into `scene_image`
+    multisampled_image: Option<Arc<ImageView<AttachmentImage>>>,
+    // the scene is rendered here with `render_scale`, then blitted into the output image
+    scene_image: Arc<ImageView<AttachmentImage>>,
+
+    vertex_buffer_pool: CpuBufferPool<Vertex>,
+    instance_buffer_pool: CpuBufferPool<Instance>,
+    index_buffer_pool: CpuBufferPool<u32>,
+}
 
-    depth_buffer: Arc<ImageView<AttachmentImage>>,
+/// Minecraft engine and renderer (for now)
+pub(crate) struct Engine {
+    // recreated when switching to another gpu
+    gpu: GpuResources,
+    // the scene is rendered at this scale of the output size
+    render_scale: f32,
 
     // current mouse position for placing a block
     mouse_position: [f32; 2],
+    modifiers: ModifiersState,
     holding_cursor: bool,
+    // the cursor is grabbed and hidden, and any mouse motion rotates the camera
+    mouse_captured: bool,
+    control_config: CameraControlConfig,
+    // `None` if the controller input failed to start
+    #[cfg(feature = "gamepad")]
+    gamepad: Option<GamepadInput>,
+    key_bindings: KeyBindings,
     // viewport saved size for placing a block
     viewport_size: [f32; 2],
     // collecting of blocks
     world: World,
-
-    vertex_buffer_pool: CpuBufferPool<Vertex>,
-    instance_buffer_pool: CpuBufferPool<Instance>,
-    index_buffer_pool: CpuBufferPool<u32>,
+    // chunks are loaded in this distance around the player
+    load_radius: f32,
 
     moving_direction: Vector3<f32>,
-
-    camera: Camera,
+    // blocks per second
+    move_speed: f32,
+    sprinting: bool,
+    sprint_multiplier: f32,
+    // the fov is widened by this while sprinting
+    sprint_fov: Deg<f32>,
+    crouch_multiplier: f32,
+    // current movement in blocks per second, eased towards the input
+    velocity: Vector3<f32>,
+    // blocks per second squared when speeding up and slowing down,
+    // `0` changes the velocity instantly
+    acceleration: f32,
+    friction: f32,
+
+    player: Player,
+    // overrides the input movement of the player until it ends
+    camera_path: Option<PlayingCameraPath>,
+    recording_camera_path: Option<RecordingCameraPath>,
     looking_at_cube: Option<CubeLookAt>,
+    // blocks further than this are not highlighted
+    look_radius: f32,
+    // blocks can only be placed and removed within this distance
+    reach_distance: f32,
+    outline_style: OutlineStyle,
+    crosshair_style: CrosshairStyle,
+    // the block placed with the middle button
+    active_block: BlockType,
+    active_shape: BlockShape,
+    // the blocks placed and removed by the player, for undo and redo
+    history: EditHistory,
+
+    // direction to the sun, normalized
+    sun_direction: Vector3<f32>,
+    // how strong the sun light is, between `0` and `1`
+    sun_intensity: f32,
+    ambient_light: f32,
+    // the fog color is the sky color
+    fog_density: f32,
+    fog_start: f32,
+    sky: Sky,
+    // darken the faces based on their direction, so they can be told apart
+    face_shading: bool,
+    // darken the corners of faces next to other blocks
+    ambient_occlusion: bool,
+    // at most `MAX_POINT_LIGHTS`
+    point_lights: Vec<PointLight>,
+    // `0` is midnight, `0.5` is noon
+    time_of_day: f32,
+    // seconds since the engine started, animates the water
+    time: f32,
+    // how much `time_of_day` advances every second
+    day_speed: f32,
+
+    // holding the left button keeps removing blocks every `mining_interval`
+    mining: bool,
+    mining_timer: Duration,
+    mining_interval: Duration,
+
+    // the last few frame times, used for the fps counter
+    frame_times: VecDeque<Duration>,
+    show_debug_overlay: bool,
+    // the chunks and instances in the last rendered frame
+    render_stats: RenderStats,
+    minimap: Minimap,
+    show_minimap: bool,
+    // render the chunks as the outlines of their faces
+    wireframe: bool,
+    // draw the depth of the opaque faces first, so hidden faces aren't shaded
+    depth_prepass: bool,
+    // a translucent block where the active block would be placed
+    show_placement_preview: bool,
+    show_chunk_borders: bool,
+
+    // a screenshot is taken in the next `render`
+    screenshot_requested: bool,
+    // the copy of a rendered frame, saved when the gpu is done with it
+    pending_screenshot: Option<PendingScreenshot>,
+    // a world save being written in the background, and where to
+    pending_save: Option<(PathBuf, PendingSave)>,
+
+    // the memory usage is logged every `memory_report_interval` if set
+    memory_report_interval: Option<Duration>,
+    since_memory_report: Duration,
+
+    // streamed chunks fade in over this, instead of popping in
+    chunk_fade_duration: Duration,
+
+    particles: ParticleSystem,
+
+    // used again when switching to another gpu
+    config: EngineConfig,
+    // only the first instances of every chunk mesh are drawn, for debugging
+    max_instances_per_chunk: Option<usize>,
 }
 
-impl Engine {
-    pub fn new(queue: Arc<Queue>, image_format: Format) -> Self {
+impl GpuResources {
+    fn new(queue: Arc<Queue>, image_format: Format, config: &EngineConfig) -> Self {
+        let samples = Engine::supported_samples(&queue, config.samples);
+
         // a render pass with color and reversed depth attachments (near is 1, far is 0)
         // which allows for high precision depth testing
-        let render_pass = vulkano::single_pass_renderpass!(
+        let render_pass = if samples == SampleCount::Sample1 {
+            vulkano::single_pass_renderpass!(
+                queue.device().clone(),
+                attachments: {
+                    color: {
+                        load: Clear,
+                        store: Store,
+                        format: image_format,
+                        samples: 1,
+                    },
+                    depth:  {
+                        load: Clear,
+                        store: DontCare,
+                        format: DEPTH_FORMAT,
+                        samples: 1,
+                    }
+                },
+                pass: {
+                    color: [color],
+                    depth_stencil: {depth}
+                }
+            )
+            .unwrap()
+        } else {
+            // same as above, but multisampled and resolved into `resolve`
+            vulkano::single_pass_renderpass!(
+                queue.device().clone(),
+                attachments: {
+                    color: {
+                        load: Clear,
+                        store: DontCare,
+                        format: image_format,
+                        samples: samples as u32,
+                    },
+                    depth:  {
+                        load: Clear,
+                        store: DontCare,
+                        format: DEPTH_FORMAT,
+                        samples: samples as u32,
+                    },
+                    resolve: {
+                        load: DontCare,
+                        store: Store,
+                        format: image_format,
+                        samples: 1,
+                    }
+                },
+                pass: {
+                    color: [color],
+                    depth_stencil: {depth},
+                    resolve: [resolve]
+                }
+            )
+            .unwrap()
+        };
+
+        // the ui is drawn directly on the output image, on top of the scene
+        let ui_render_pass = vulkano::single_pass_renderpass!(
             queue.device().clone(),
             attachments: {
                 color: {
-                    load: Clear,
+                    load: Load,
                     store: Store,
                     format: image_format,
                     samples: 1,
-                },
-                depth:  {
-                    load: Clear,
-                    store: DontCare,
-                    format: Format::D32_SFLOAT,
-                    samples: 1,
                 }
             },
             pass: {
                 color: [color],
-                depth_stencil: {depth}
+                depth_stencil: {}
             }
         )
         .unwrap();
@@ -148,6 +744,15 @@ impl Engine {
         let vs_ui = ui_vs::load(queue.device().clone()).unwrap();
         let fs_ui = ui_fs::load(queue.device().clone()).unwrap();
 
+        let vs_sky = sky_vs::load(queue.device().clone()).unwrap();
+        let vs_billboard = billboard_vs::load(queue.device().clone()).unwrap();
+
+        // closer faces have a bigger depth with reversed depth
+        let (closer, closer_or_equal, bias_direction) = match config.depth_mode {
+            DepthMode::Reversed => (CompareOp::Greater, CompareOp::GreaterOrEqual, -1.),
+            DepthMode::Standard => (CompareOp::Less, CompareOp::LessOrEqual, 1.),
+        };
+
         let cubes_graphics_pipeline = GraphicsPipeline::start()
             .vertex_input_state(
                 BuffersDefinition::new()
@@ -164,7 +769,7 @@ impl Engine {
             .depth_stencil_state(DepthStencilState {
                 depth: Some(DepthState {
                     enable_dynamic: false,
-                    compare_op: StateMode::Fixed(CompareOp::Greater), // inverse operation
+                    compare_op: StateMode::Fixed(closer),
                     write_enable: StateMode::Fixed(true),
                 }),
                 ..Default::default()
@@ -174,6 +779,40 @@ impl Engine {
             .build(queue.device().clone())
             .unwrap();
 
+        // all the cube pipelines share the layout of the first one, so the
+        // descriptor sets bound once per frame stay valid when switching
+        // between them, even for the shaders that don't use all the bindings
+        let cubes_layout = cubes_graphics_pipeline.layout().clone();
+
+        let cubes_transparent_graphics_pipeline = GraphicsPipeline::start()
+            .vertex_input_state(
+                BuffersDefinition::new()
+                    .vertex::<Vertex>()
+                    .instance::<Instance>(),
+            )
+            .input_assembly_state(InputAssemblyState {
+                topology: PartialStateMode::Fixed(PrimitiveTopology::TriangleList),
+                primitive_restart_enable: StateMode::Fixed(false),
+            })
+            .vertex_shader(
+                vs_cubes.entry_point("main").unwrap(),
+                cubes_vs::SpecializationConstants { WAVES: 1 },
+            )
+            .viewport_state(ViewportState::viewport_dynamic_scissor_irrelevant())
+            .fragment_shader(fs_cubes.entry_point("main").unwrap(), ())
+            .depth_stencil_state(DepthStencilState {
+                depth: Some(DepthState {
+                    enable_dynamic: false,
+                    compare_op: StateMode::Fixed(closer),
+                    write_enable: StateMode::Fixed(false),
+                }),
+                ..Default::default()
+            })
+            .color_blend_state(ColorBlendState::new(1).blend_alpha())
+            .render_pass(Subpass::from(render_pass.clone(), 0).unwrap())
+            .with_pipeline_layout(queue.device().clone(), cubes_layout.clone())
+            .unwrap();
+
         let cubes_line_graphics_pipeline = GraphicsPipeline::start()
             .vertex_input_state(
                 BuffersDefinition::new()
@@ -190,13 +829,108 @@ impl Engine {
             .depth_stencil_state(DepthStencilState {
                 depth: Some(DepthState {
                     enable_dynamic: false,
-                    compare_op: StateMode::Fixed(CompareOp::GreaterOrEqual), // inverse operation
+                    compare_op: StateMode::Fixed(closer_or_equal),
                     write_enable: StateMode::Fixed(false),
                 }),
                 ..Default::default()
             })
             .render_pass(Subpass::from(render_pass.clone(), 0).unwrap())
-            .build(queue.device().clone())
+            .with_pipeline_layout(queue.device().clone(), cubes_layout.clone())
+            .unwrap();
+
+        let cubes_depth_graphics_pipeline = GraphicsPipeline::start()
+            .vertex_input_state(
+                BuffersDefinition::new()
+                    .vertex::<Vertex>()
+                    .instance::<Instance>(),
+            )
+            .input_assembly_state(InputAssemblyState {
+                topology: PartialStateMode::Fixed(PrimitiveTopology::TriangleList),
+                primitive_restart_enable: StateMode::Fixed(false),
+            })
+            .vertex_shader(vs_cubes.entry_point("main").unwrap(), ())
+            .viewport_state(ViewportState::viewport_dynamic_scissor_irrelevant())
+            .fragment_shader(fs_cubes_no_light.entry_point("main").unwrap(), ())
+            // push the faces a bit away so that the lines on their edges
+            // pass the depth test
+            .rasterization_state(RasterizationState {
+                depth_bias: Some(DepthBiasState {
+                    enable_dynamic: false,
+                    bias: StateMode::Fixed(DepthBias {
+                        constant_factor: bias_direction,
+                        clamp: 0.,
+                        slope_factor: bias_direction,
+                    }),
+                }),
+                ..Default::default()
+            })
+            .depth_stencil_state(DepthStencilState {
+                depth: Some(DepthState {
+                    enable_dynamic: false,
+                    compare_op: StateMode::Fixed(closer),
+                    write_enable: StateMode::Fixed(true),
+                }),
+                ..Default::default()
+            })
+            .color_blend_state(ColorBlendState::new(1).color_write_mask(ColorComponents::none()))
+            .render_pass(Subpass::from(render_pass.clone(), 0).unwrap())
+            .with_pipeline_layout(queue.device().clone(), cubes_layout.clone())
+            .unwrap();
+
+        // same as the depth pipeline, but without bias, so the depth matches
+        // the color pass exactly
+        let cubes_prepass_graphics_pipeline = GraphicsPipeline::start()
+            .vertex_input_state(
+                BuffersDefinition::new()
+                    .vertex::<Vertex>()
+                    .instance::<Instance>(),
+            )
+            .input_assembly_state(InputAssemblyState {
+                topology: PartialStateMode::Fixed(PrimitiveTopology::TriangleList),
+                primitive_restart_enable: StateMode::Fixed(false),
+            })
+            .vertex_shader(vs_cubes.entry_point("main").unwrap(), ())
+            .viewport_state(ViewportState::viewport_dynamic_scissor_irrelevant())
+            .fragment_shader(fs_cubes_no_light.entry_point("main").unwrap(), ())
+            .depth_stencil_state(DepthStencilState {
+                depth: Some(DepthState {
+                    enable_dynamic: false,
+                    compare_op: StateMode::Fixed(closer),
+                    write_enable: StateMode::Fixed(true),
+                }),
+                ..Default::default()
+            })
+            .color_blend_state(ColorBlendState::new(1).color_write_mask(ColorComponents::none()))
+            .render_pass(Subpass::from(render_pass.clone(), 0).unwrap())
+            .with_pipeline_layout(queue.device().clone(), cubes_layout.clone())
+            .unwrap();
+
+        // only the faces that won the prepass are shaded, `Equal` works the
+        // same for both depth modes
+        let cubes_equal_graphics_pipeline = GraphicsPipeline::start()
+            .vertex_input_state(
+                BuffersDefinition::new()
+                    .vertex::<Vertex>()
+                    .instance::<Instance>(),
+            )
+            .input_assembly_state(InputAssemblyState {
+                topology: PartialStateMode::Fixed(PrimitiveTopology::TriangleList),
+                primitive_restart_enable: StateMode::Fixed(false),
+            })
+            .vertex_shader(vs_cubes.entry_point("main").unwrap(), ())
+            .viewport_state(ViewportState::viewport_dynamic_scissor_irrelevant())
+            .fragment_shader(fs_cubes.entry_point("main").unwrap(), ())
+            .depth_stencil_state(DepthStencilState {
+                depth: Some(DepthState {
+                    enable_dynamic: false,
+                    compare_op: StateMode::Fixed(CompareOp::Equal),
+                    write_enable: StateMode::Fixed(false),
+                }),
+                ..Default::default()
+            })
+            .color_blend_state(ColorBlendState::new(1).blend_alpha())
+            .render_pass(Subpass::from(render_pass.clone(), 0).unwrap())
+            .with_pipeline_layout(queue.device().clone(), cubes_layout.clone())
             .unwrap();
 
         let ui_graphics_pipeline = GraphicsPipeline::start()
@@ -212,46 +946,122 @@ impl Engine {
             .vertex_shader(vs_ui.entry_point("main").unwrap(), ())
             .viewport_state(ViewportState::viewport_dynamic_scissor_irrelevant())
             .fragment_shader(fs_ui.entry_point("main").unwrap(), ())
+            .render_pass(Subpass::from(ui_render_pass.clone(), 0).unwrap())
+            .build(queue.device().clone())
+            .unwrap();
+
+        // the vertices are generated in the shader, and the ui fragment
+        // shader just outputs the color
+        let sky_graphics_pipeline = GraphicsPipeline::start()
+            .vertex_input_state(BuffersDefinition::new())
+            .input_assembly_state(InputAssemblyState::new())
+            .vertex_shader(vs_sky.entry_point("main").unwrap(), ())
+            .viewport_state(ViewportState::viewport_dynamic_scissor_irrelevant())
+            .fragment_shader(fs_ui.entry_point("main").unwrap(), ())
+            .render_pass(Subpass::from(render_pass.clone(), 0).unwrap())
+            .build(queue.device().clone())
+            .unwrap();
+
+        let billboard_graphics_pipeline = GraphicsPipeline::start()
+            .vertex_input_state(
+                BuffersDefinition::new()
+                    .vertex::<Vertex>()
+                    .instance::<Instance>(),
+            )
+            .input_assembly_state(InputAssemblyState::new())
+            .vertex_shader(vs_billboard.entry_point("main").unwrap(), ())
+            .viewport_state(ViewportState::viewport_dynamic_scissor_irrelevant())
+            .fragment_shader(fs_ui.entry_point("main").unwrap(), ())
+            // the particles are blended, so they don't hide each other
+            .depth_stencil_state(DepthStencilState {
+                depth: Some(DepthState {
+                    enable_dynamic: false,
+                    compare_op: StateMode::Fixed(closer),
+                    write_enable: StateMode::Fixed(false),
+                }),
+                ..Default::default()
+            })
+            .color_blend_state(ColorBlendState::new(1).blend_alpha())
             .render_pass(Subpass::from(render_pass.clone(), 0).unwrap())
             .build(queue.device().clone())
             .unwrap();
 
         let uniform_buffer_pool =
             CpuBufferPool::new(queue.device().clone(), BufferUsage::uniform_buffer());
-        let descriptor_set_pool = SingleLayoutDescSetPool::new(
-            cubes_graphics_pipeline
-                .layout()
-                .set_layouts()
-                .get(0)
-                .unwrap()
+        let point_lights_buffer_pool =
+            CpuBufferPool::new(queue.device().clone(), BufferUsage::uniform_buffer());
+        let mut block_colors = cubes_vs::ty::BlockColors {
+            face_colors: [[0.; 4]; FACE_COLORS_LEN],
+        };
+        for block in BlockType::ALL {
+            for (i, face) in BlockFace::ALL.into_iter().enumerate() {
+                let index = block.texture_index() as usize * BlockFace::ALL.len() + i;
+                block_colors.face_colors[index] = block.face_color(face);
+            }
+        }
+        let block_colors_buffer = CpuAccessibleBuffer::from_data(
+            queue.device().clone(),
+            BufferUsage::uniform_buffer(),
+            false,
+            block_colors,
+        )
+        .unwrap();
+        let descriptor_set_pool = SingleLayoutDescSetPool::new(
+            cubes_graphics_pipeline
+                .layout()
+                .set_layouts()
+                .get(0)
+                .unwrap()
                 .clone(),
         );
 
-        let depth_buffer = ImageView::new_default(
-            AttachmentImage::transient(queue.device().clone(), [1, 1], Format::D32_SFLOAT).unwrap(),
+        let [atlas_width, atlas_height] = atlas::atlas_size();
+        let (atlas_image, atlas_future) = ImmutableImage::from_iter(
+            atlas::atlas_pixels(),
+            ImageDimensions::Dim2d {
+                width: atlas_width,
+                height: atlas_height,
+                array_layers: 1,
+            },
+            MipmapsCount::One,
+            Format::R8G8B8A8_UNORM,
+            queue.clone(),
+        )
+        .unwrap();
+        // the atlas is only uploaded once, so just wait for it here
+        atlas_future
+            .then_signal_fence_and_flush()
+            .unwrap()
+            .wait(None)
+            .unwrap();
+        let atlas_sampler = Sampler::new(
+            queue.device().clone(),
+            SamplerCreateInfo {
+                // keep the pixelated look
+                mag_filter: Filter::Nearest,
+                min_filter: Filter::Nearest,
+                address_mode: [SamplerAddressMode::ClampToEdge; 3],
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        // the atlas is in its own set, as the line pipeline doesn't use it
+        let atlas_descriptor_set = PersistentDescriptorSet::new(
+            cubes_graphics_pipeline
+                .layout()
+                .set_layouts()
+                .get(1)
+                .unwrap()
+                .clone(),
+            [WriteDescriptorSet::image_view_sampler(
+                0,
+                ImageView::new_default(atlas_image).unwrap(),
+                atlas_sampler,
+            )],
         )
         .unwrap();
 
-        let mut world = World::default();
-
-        // create many chunks
-        let x_size = 3;
-        let y_size = 3;
-        for x in 0..x_size {
-            for y in 0..y_size {
-                world.create_chunk(
-                    x * 16,
-                    60,
-                    y * 16,
-                    [
-                        x as f32 / x_size as f32,
-                        y as f32 / y_size as f32,
-                        (x + y) as f32 / (x_size + y_size) as f32,
-                        1.,
-                    ],
-                );
-            }
-        }
+        let scene_image = Engine::create_scene_image(queue.clone(), image_format, [1, 1]);
 
         let vertex_buffer_pool =
             CpuBufferPool::new(queue.device().clone(), BufferUsage::vertex_buffer());
@@ -262,26 +1072,142 @@ impl Engine {
 
         Self {
             queue,
+            image_format,
             render_pass,
+            ui_render_pass,
             cubes_graphics_pipeline,
+            cubes_transparent_graphics_pipeline,
             cubes_line_graphics_pipeline,
+            cubes_depth_graphics_pipeline,
+            cubes_prepass_graphics_pipeline,
+            cubes_equal_graphics_pipeline,
             ui_graphics_pipeline,
+            sky_graphics_pipeline,
+            billboard_graphics_pipeline,
             uniform_buffer_pool,
+            point_lights_buffer_pool,
+            block_colors_buffer,
             descriptor_set_pool,
+            atlas_descriptor_set,
+
+            depth_buffer: None,
+            samples,
+            depth_mode: config.depth_mode,
+            multisampled_image: None,
+            scene_image,
+            vertex_buffer_pool,
+            instance_buffer_pool,
+            index_buffer_pool,
+        }
+    }
+}
+
+impl Engine {
+    pub fn new(queue: Arc<Queue>, image_format: Format, config: EngineConfig) -> Self {
+        let gpu = GpuResources::new(queue, image_format, &config);
 
-            depth_buffer,
+        // chunks are loaded around the player in `update`
+        let mut world = World::default();
+        world.set_height(config.world_height);
+        world.set_seed(config.seed);
+        world.set_base_height(config.base_height);
+
+        let mut engine = Self {
+            gpu,
+            render_scale: 1.,
 
             mouse_position: [0., 0.],
+            modifiers: ModifiersState::empty(),
             holding_cursor: false,
+            mouse_captured: false,
+            control_config: CameraControlConfig::default(),
+            #[cfg(feature = "gamepad")]
+            gamepad: GamepadInput::new(),
+            key_bindings: KeyBindings::default(),
             viewport_size: [0., 0.],
             world,
-            vertex_buffer_pool,
-            instance_buffer_pool,
-            index_buffer_pool,
+            load_radius: 0.,
             moving_direction: Vector3::new(0., 0., 0.),
-            camera: Camera::new(Deg(45.), 0.0, 0.1, 100., [0., 125., -25.].into()),
+            move_speed: 50.,
+            sprinting: false,
+            sprint_multiplier: 1.6,
+            sprint_fov: Deg(5.),
+            crouch_multiplier: 0.3,
+            velocity: Vector3::new(0., 0., 0.),
+            acceleration: 0.,
+            friction: 0.,
+            player: Player::new(Camera::new(
+                config.fov,
+                0.0,
+                0.1,
+                100.,
+                config.start_position,
+            )),
+            camera_path: None,
+            recording_camera_path: None,
             looking_at_cube: None,
-        }
+            look_radius: 100.,
+            reach_distance: 5.,
+            outline_style: OutlineStyle::default(),
+            crosshair_style: CrosshairStyle::default(),
+            active_block: HOTBAR[0],
+            active_shape: BlockShape::Cube,
+            history: EditHistory::new(HISTORY_LIMIT),
+            sun_direction: Vector3::new(1., 3., -2.).normalize(),
+            sun_intensity: 1.,
+            ambient_light: 0.2,
+            // no fog unless it's set with `set_fog`
+            fog_density: 0.,
+            // set with the chunk radius
+            fog_start: 0.,
+            sky: Sky::DayCycle,
+            // off unless it's set with `set_face_shading`
+            face_shading: false,
+            // off unless it's set with `set_ambient_occlusion`
+            ambient_occlusion: false,
+            point_lights: Vec::new(),
+            time_of_day: 0.,
+            time: 0.,
+            // a full day every 10 minutes
+            day_speed: 1. / 600.,
+            mining: false,
+            mining_timer: Duration::ZERO,
+            mining_interval: Duration::from_millis(250),
+            frame_times: VecDeque::with_capacity(FRAME_TIMES_COUNT),
+            show_debug_overlay: false,
+            render_stats: RenderStats::default(),
+            minimap: Minimap {
+                size: 150.,
+                corner: Corner::TopRight,
+                // all the loaded chunks fit in the map
+                range: ((config.chunk_radius + 1) * CHUNK_SIZE as u32) as f32,
+            },
+            show_minimap: false,
+            wireframe: false,
+            depth_prepass: false,
+            show_placement_preview: true,
+            show_chunk_borders: false,
+            screenshot_requested: false,
+            pending_screenshot: None,
+            pending_save: None,
+            memory_report_interval: None,
+            since_memory_report: Duration::ZERO,
+            chunk_fade_duration: CHUNK_FADE_DURATION,
+            particles: ParticleSystem::new(MAX_PARTICLES),
+            config,
+            max_instances_per_chunk: None,
+        };
+
+        engine.player.camera_mut().set_depth_mode(config.depth_mode);
+        engine.set_chunk_radius(config.chunk_radius);
+        engine
+            .player
+            .camera_mut()
+            .set_orientation(config.start_yaw, config.start_pitch);
+        // start in the morning
+        engine.set_time_of_day(0.35);
+
+        engine
     }
 
     pub fn handle_events(&mut self, event: Event<()>) {
@@ -302,6 +1228,15 @@ impl Engine {
                     self.holding_cursor = false;
                 }
             },
+            Event::WindowEvent {
+                event:
+                    WindowEvent::MouseInput {
+                        button: MouseButton::Left,
+                        state,
+                        ..
+                    },
+                ..
+            } => self.handle_action(Action::Remove, state == ElementState::Pressed),
             Event::WindowEvent {
                 event:
                     WindowEvent::MouseInput {
@@ -311,12 +1246,7 @@ impl Engine {
                     },
                 ..
             } => match button {
-                MouseButton::Left => {
-                    self.remove_looking_at();
-                }
-                MouseButton::Middle => {
-                    self.place_at_looking_at();
-                }
+                MouseButton::Middle => self.handle_action(Action::Place, true),
                 _ => unreachable!(),
             },
             Event::WindowEvent {
@@ -326,24 +1256,30 @@ impl Engine {
                 // unfortunately, we can't get the position inside a button
                 // click event, so we have to keep track of it.
                 let mouse_position: [f32; 2] = position.into();
-                let angles = [
-                    // movement in x direction in display moves the camera
-                    // around the y axis (yaw)
+                let (pitch, yaw) = self.control_config.rotation_deltas(
                     mouse_position[0] - self.mouse_position[0],
-                    // movement in y direction in display moves the camera
-                    // around the x axis (pitch)
-                    //
-                    // because the `y` axis is inverted, we have to negate
-                    // the angle here
-                    -(mouse_position[1] - self.mouse_position[1]),
-                ];
+                    mouse_position[1] - self.mouse_position[1],
+                );
                 self.mouse_position = mouse_position;
 
-                if self.holding_cursor {
-                    self.camera
-                        .rotate_camera(Deg(angles[1] * 0.10), Deg(angles[0] * 0.1));
+                if self.holding_cursor && !self.mouse_captured {
+                    self.player.camera_mut().rotate_camera(pitch, yaw);
                 }
             }
+            // the cursor doesn't move while captured, so the raw motion is used
+            Event::DeviceEvent {
+                event: DeviceEvent::MouseMotion { delta: (dx, dy) },
+                ..
+            } if self.mouse_captured => {
+                let (pitch, yaw) = self.control_config.rotation_deltas(dx as f32, dy as f32);
+                self.player.camera_mut().rotate_camera(pitch, yaw);
+            }
+            Event::WindowEvent {
+                event: WindowEvent::Focused(false),
+                ..
+            } => {
+                self.mouse_captured = false;
+            }
             Event::WindowEvent {
                 event:
                     WindowEvent::MouseWheel {
@@ -352,7 +1288,13 @@ impl Engine {
                     },
                 ..
             } => {
-                self.camera.zoom(Deg(y as f32 * 1.));
+                self.player.camera_mut().zoom(Deg(y as f32 * 1.));
+            }
+            Event::WindowEvent {
+                event: WindowEvent::ModifiersChanged(modifiers),
+                ..
+            } => {
+                self.modifiers = modifiers;
             }
             Event::WindowEvent {
                 event:
@@ -368,24 +1310,71 @@ impl Engine {
                 ..
             } => {
                 let pressed = state == ElementState::Pressed;
-                if pressed {
-                    match keycode {
-                        VirtualKeyCode::W => self.moving_direction.z = 1.,
-                        VirtualKeyCode::S => self.moving_direction.z = -1.,
-                        VirtualKeyCode::D => self.moving_direction.x = 1.,
-                        VirtualKeyCode::A => self.moving_direction.x = -1.,
-                        VirtualKeyCode::Space => self.moving_direction.y = 1.,
-                        VirtualKeyCode::LShift => self.moving_direction.y = -1.,
-                        _ => {}
-                    }
-                } else {
+                if pressed && self.modifiers.ctrl() && self.handle_ctrl_shortcut(keycode) {
+                    // the shortcuts come first, as their keys can be bound to
+                    // actions too, like `S` moving back
+                } else if let Some(action) = self.key_bindings.action(keycode) {
+                    self.handle_action(action, pressed);
+                } else if pressed {
                     match keycode {
-                        VirtualKeyCode::W | VirtualKeyCode::S => self.moving_direction.z = 0.,
-                        VirtualKeyCode::D | VirtualKeyCode::A => self.moving_direction.x = 0.,
-                        VirtualKeyCode::Space | VirtualKeyCode::LShift => {
-                            self.moving_direction.y = 0.
+                        VirtualKeyCode::F => {
+                            let mode = match self.player.camera().movement_mode() {
+                                MovementMode::Grounded => MovementMode::Fly,
+                                MovementMode::Fly => MovementMode::Grounded,
+                            };
+                            self.player.camera_mut().set_movement_mode(mode);
+                        }
+                        VirtualKeyCode::B => {
+                            self.active_shape = match self.active_shape {
+                                BlockShape::Cube => BlockShape::Slab,
+                                BlockShape::Slab => BlockShape::Cube,
+                            };
+                            println!("Selected shape: {:?}", self.active_shape);
+                        }
+                        VirtualKeyCode::C => {
+                            let free_look = !self.player.camera().free_look();
+                            self.player.camera_mut().set_free_look(free_look);
+                        }
+                        VirtualKeyCode::R => self.regenerate_world(),
+                        VirtualKeyCode::G => {
+                            let enabled = !self.player.physics_enabled();
+                            self.player.set_physics_enabled(enabled);
+                        }
+                        VirtualKeyCode::N => {
+                            let noclip = !self.player.noclip();
+                            self.player.set_noclip(noclip);
+                            println!("Noclip: {noclip}");
+                        }
+                        // a torch-like light where the camera is
+                        VirtualKeyCode::L => {
+                            let light = PointLight {
+                                position: *self.player.camera().position(),
+                                color: [1.5, 1.2, 0.8],
+                                radius: 12.,
+                            };
+                            if !self.add_point_light(light) {
+                                println!("Can't add more than {MAX_POINT_LIGHTS} lights");
+                            }
+                        }
+                        VirtualKeyCode::Tab => self.mouse_captured = !self.mouse_captured,
+                        VirtualKeyCode::Escape => self.mouse_captured = false,
+                        VirtualKeyCode::F2 => self.screenshot_requested = true,
+                        VirtualKeyCode::F3 => {
+                            self.set_show_debug_overlay(!self.show_debug_overlay);
+                        }
+                        VirtualKeyCode::Numpad0 => self.player.camera_mut().reset_fov(),
+                        VirtualKeyCode::F4 => self.wireframe = !self.wireframe,
+                        VirtualKeyCode::F5 => self.show_chunk_borders = !self.show_chunk_borders,
+                        VirtualKeyCode::M => self.show_minimap = !self.show_minimap,
+                        VirtualKeyCode::F6 => println!("{}", self.debug_snapshot()),
+                        VirtualKeyCode::F7 => self.toggle_camera_path_recording(),
+                        _ => {
+                            if let Some(block) = number_key(keycode).and_then(|n| HOTBAR.get(n - 1))
+                            {
+                                self.active_block = *block;
+                                println!("Selected block: {:?}", block);
+                            }
                         }
-                        _ => {}
                     }
                 }
             }
@@ -393,302 +1382,1909 @@ impl Engine {
         }
     }
 
-    pub fn update(&mut self, delta: Duration) {
-        self.camera
-            .move_camera(self.moving_direction * delta.as_secs_f32() * 50.);
+    /// Sets the direction the sun light is coming from (pointing towards the sun),
+    /// this will be overridden when the time of day advances
+    #[allow(dead_code)]
+    pub fn set_sun_direction(&mut self, direction: Vector3<f32>) {
+        self.sun_direction = direction.normalize();
+    }
 
-        const DELETE_RADIUS: f32 = 10.;
-        const LOOK_RADIUS: f32 = 100.;
-
-        self.world.chunks_around_mut_callback(
-            Point2::new(
-                self.camera.position().x as i32,
-                self.camera.position().z as i32,
-            ),
-            DELETE_RADIUS,
-            |chunk| {
-                for cube in chunk
-                    .cubes_around(self.camera.position().cast::<i32>().unwrap(), DELETE_RADIUS)
-                    .collect::<Vec<_>>()
-                {
-                    chunk.remove_cube(cube);
-                }
-            },
-        );
+    /// Sets the time of day in the range `[0, 1)`, `0` is midnight and `0.5` is noon,
+    /// and updates the sun light accordingly
+    pub fn set_time_of_day(&mut self, time_of_day: f32) {
+        self.time_of_day = time_of_day.rem_euclid(1.);
 
-        let result = self.world.cube_looking_at(
-            self.camera.position(),
-            self.camera.direction(),
-            LOOK_RADIUS,
-        );
-        self.looking_at_cube = result.result_cube;
+        let daylight = sky::daylight(self.time_of_day);
+        self.sun_direction = sky::sun_direction(self.time_of_day);
+        self.sun_intensity = daylight;
+        self.ambient_light = 0.05 + 0.15 * daylight;
     }
 
-    pub fn render<Fin>(&mut self, image: Arc<dyn ImageAccess>, future: Fin) -> Box<dyn GpuFuture>
-    where
-        Fin: GpuFuture + 'static,
-    {
-        let img_size = image.dimensions().width_height();
-        // save for later
-        self.viewport_size = [img_size[0] as f32, img_size[1] as f32];
+    /// How much of a full day passes every second, `0` stops the time
+    #[allow(dead_code)]
+    pub fn set_day_speed(&mut self, day_speed: f32) {
+        self.day_speed = day_speed;
+    }
 
-        // only resize when needed
-        if self.depth_buffer.image().dimensions() != image.dimensions() {
-            self.depth_buffer = ImageView::new_default(
-                AttachmentImage::transient(
-                    self.queue.device().clone(),
-                    img_size,
-                    Format::D32_SFLOAT,
-                )
-                .unwrap(),
-            )
-            .unwrap();
-        }
+    /// Renders the scene at `scale` of the output resolution and upscales
+    /// it, the ui is not affected. `scale` is clamped to `[0.1, 1]`
+    pub fn set_render_scale(&mut self, scale: f32) {
+        self.render_scale = scale.clamp(0.1, 1.);
+    }
 
-        let image_view = ImageView::new_default(image).unwrap();
+    /// Sets how the outline of the block we are looking at is drawn
+    pub fn set_outline_style(&mut self, style: OutlineStyle) {
+        self.outline_style = OutlineStyle {
+            scale: style.scale.max(MIN_OUTLINE_SCALE),
+            ..style
+        };
+    }
 
-        let framebuffer = Framebuffer::new(
-            self.render_pass.clone(),
-            FramebufferCreateInfo {
-                attachments: vec![image_view, self.depth_buffer.clone()],
-                ..Default::default()
-            },
-        )
-        .unwrap();
+    /// Draws the depth of the opaque faces before shading them, so every
+    /// pixel is shaded once. This helps when many faces are behind each other,
+    /// at the cost of drawing the opaque geometry twice
+    pub fn set_depth_prepass(&mut self, enabled: bool) {
+        self.depth_prepass = enabled;
+    }
 
-        let mut builder = AutoCommandBufferBuilder::primary(
-            self.queue.device().clone(),
-            self.queue.family(),
-            CommandBufferUsage::OneTimeSubmit,
-        )
-        .unwrap();
+    /// Shows a translucent block where the active block would be placed
+    pub fn set_show_placement_preview(&mut self, show: bool) {
+        self.show_placement_preview = show;
+    }
 
-        builder
-            .begin_render_pass(
-                framebuffer,
-                SubpassContents::Inline,
-                vec![
-                    // blue sky color
-                    ClearValue::Float([0., 0.7, 1., 1.0]),
-                    ClearValue::Depth(0.0),
-                ],
-            )
-            .unwrap();
+    /// Sets the shape, size and color of the crosshair
+    #[allow(dead_code)]
+    pub fn set_crosshair(&mut self, style: CrosshairStyle) {
+        self.crosshair_style = style;
+    }
 
-        let mesh = self.world.mesh();
+    /// Draws only the first `max` instances of every chunk mesh, `None` draws
+    /// all of them. Useful to find if large meshes cause issues, and as a
+    /// crude level of detail
+    pub fn set_max_instances_per_chunk(&mut self, max: Option<usize>) {
+        self.max_instances_per_chunk = max;
+    }
 
-        if !mesh.is_empty() {
-            let index_buffer = self
-                .index_buffer_pool
-                .chunk(mesh.indices().iter().cloned())
-                .unwrap();
+    /// Draws the chunks further than `distance` blocks from the camera with
+    /// only their surface, `None` draws all the chunks in full detail
+    pub fn set_lod_distance(&mut self, distance: Option<f32>) {
+        self.config.lod_distance = distance;
+    }
 
-            let vertex_buffer = self
-                .vertex_buffer_pool
-                .chunk(mesh.vertices().iter().cloned())
-                .unwrap();
+    /// The level of detail to draw `chunk` with, for [`Chunk::mesh_lod`],
+    /// from the horizontal distance between the camera and the chunk
+    fn chunk_lod(&self, chunk: &Chunk, camera_position: Point3<f32>) -> u32 {
+        let lod_distance = match self.config.lod_distance {
+            Some(distance) => distance,
+            None => return 0,
+        };
 
-            let instance_buffer = self
-                .instance_buffer_pool
-                .chunk(mesh.instances().iter().cloned())
-                .unwrap();
+        let bounds = chunk.bounding_box();
+        let dx = (bounds.min.x - camera_position.x).max(camera_position.x - bounds.max.x);
+        let dz = (bounds.min.z - camera_position.z).max(camera_position.z - bounds.max.z);
+        let distance = Vector2::new(dx.max(0.), dz.max(0.)).magnitude();
 
-            self.camera
-                .set_aspect(self.viewport_size[0] / self.viewport_size[1]);
+        (distance > lod_distance) as u32
+    }
 
-            let uniform_subbuffer = self
-                .uniform_buffer_pool
-                .next(cubes_vs::ty::UniformData {
-                    perspective: self.camera.reversed_depth_perspective().into(),
-                    view: self.camera.view().into(),
-                })
-                .unwrap();
-            let descriptor_set = self
-                .descriptor_set_pool
-                .next([WriteDescriptorSet::buffer(0, uniform_subbuffer)])
-                .unwrap();
+    /// `mesh` with its instances cut to `max_instances_per_chunk`, the
+    /// vertices and indices are shared by all the instances, so they are kept
+    fn limit_instances<'a>(&self, mesh: MeshView<'a>) -> MeshView<'a> {
+        match self.max_instances_per_chunk {
+            Some(max) if mesh.instances.len() > max => MeshView {
+                instances: &mesh.instances[..max],
+                ..mesh
+            },
+            _ => mesh,
+        }
+    }
 
-            builder
-                .set_viewport(
-                    0,
-                    [Viewport {
-                        origin: [0.0, 0.0],
-                        dimensions: self.viewport_size,
-                        depth_range: 0.0..1.0,
-                    }],
-                )
-                .bind_descriptor_sets(
-                    PipelineBindPoint::Graphics,
-                    self.cubes_graphics_pipeline.layout().clone(),
-                    0,
-                    descriptor_set,
-                );
+    /// Recreates all the gpu resources with `queue`, after the display moved
+    /// to another device. The world, the player and the settings are kept.
+    pub fn switch_queue(&mut self, queue: Arc<Queue>, image_format: Format) {
+        // the resources of the old device are freed when they are dropped
+        self.gpu = GpuResources::new(queue, image_format, &self.config);
+        // the copy of the frame is in a buffer of the old device
+        self.pending_screenshot = None;
+    }
 
-            builder
-                .bind_index_buffer(index_buffer.clone())
-                .bind_vertex_buffers(0, (vertex_buffer, instance_buffer.clone()))
-                .bind_pipeline_graphics(self.cubes_graphics_pipeline.clone())
-                .draw_indexed(
-                    index_buffer.len() as u32,
-                    instance_buffer.len() as u32,
-                    0,
-                    0,
-                    0,
-                )
-                .unwrap();
+    /// Sets the number of chunks loaded around the player, the far plane
+    /// and the fog follow it, so all the loaded chunks are visible
+    pub fn set_chunk_radius(&mut self, chunk_radius: u32) {
+        self.load_radius = (chunk_radius * CHUNK_SIZE as u32) as f32;
+        self.player
+            .camera_mut()
+            .set_far(self.load_radius + FAR_PLANE_MARGIN);
+        // hide the edge where the chunks stop
+        self.fog_start = self.load_radius / 2.;
+    }
+
+    /// Moves the camera along `path` instead of the input, starting from its
+    /// first keyframe, the input controls the camera again when it ends.
+    ///
+    /// The path moves by [`CAMERA_PATH_STEP`] every rendered frame, so the
+    /// same frames are drawn on every run, however long they take
+    pub fn play_camera_path(&mut self, path: CameraPath) {
+        self.camera_path = Some(PlayingCameraPath {
+            path,
+            elapsed: Duration::ZERO,
+        });
+    }
+
+    /// Moves the playing camera path to the next frame
+    fn step_camera_path(&mut self) {
+        if let Some(playing) = &mut self.camera_path {
+            match playing.path.sample(playing.elapsed) {
+                Some(keyframe) => {
+                    let camera = self.player.camera_mut();
+                    camera.set_position(keyframe.position);
+                    camera.set_orientation(keyframe.yaw, keyframe.pitch);
+                }
+                // back to the normal input
+                None => self.camera_path = None,
+            }
+            if let Some(playing) = &mut self.camera_path {
+                playing.elapsed += CAMERA_PATH_STEP;
+            }
         }
+    }
 
-        self.render_looking_at(&mut builder);
-        self.render_ui(img_size, &mut builder);
+    /// Starts recording the camera into a path, which can be replayed with
+    /// [`Engine::play_camera_path`]
+    pub fn start_recording_camera_path(&mut self) {
+        self.recording_camera_path = Some(RecordingCameraPath {
+            path: CameraPath::new(Vec::new()),
+            elapsed: Duration::ZERO,
+            // the current camera is the first keyframe
+            since_keyframe: CAMERA_PATH_KEYFRAME_INTERVAL,
+        });
+    }
 
-        builder.end_render_pass().unwrap();
+    /// Stops recording and returns the recorded path, `None` if it wasn't recording
+    pub fn stop_recording_camera_path(&mut self) -> Option<CameraPath> {
+        let mut recording = self.recording_camera_path.take()?;
+        // the path ends where the camera is now
+        let keyframe = Keyframe::from_camera(self.player.camera(), recording.elapsed);
+        recording.path.push(keyframe);
+        Some(recording.path)
+    }
 
-        let command_buffer = builder.build().unwrap();
+    /// `F7` starts recording, and saves the path when pressed again
+    fn toggle_camera_path_recording(&mut self) {
+        match self.stop_recording_camera_path() {
+            Some(path) => match path.save_to_path(Path::new(RECORDED_CAMERA_PATH)) {
+                Ok(()) => println!(
+                    "Saved {} keyframes to {RECORDED_CAMERA_PATH}, replay with --camera-path",
+                    path.len()
+                ),
+                Err(e) => eprintln!("Failed to save the camera path: {e}"),
+            },
+            None => {
+                self.start_recording_camera_path();
+                println!("Recording the camera path, press F7 to stop");
+            }
+        }
+    }
 
-        future
-            .then_execute(self.queue.clone(), command_buffer)
-            .unwrap()
-            .boxed()
+    /// Makes the top faces of the blocks the brightest, the sides darker and
+    /// the bottom the darkest, regardless of the sun. It's off by default
+    pub fn set_face_shading(&mut self, face_shading: bool) {
+        self.face_shading = face_shading;
     }
 
-    fn render_looking_at(
-        &mut self,
+    /// Sets the movement speed in blocks per second, and how it's scaled
+    /// while sprinting and crouching
+    pub fn set_move_speed(&mut self, speed: f32, sprint_multiplier: f32, crouch_multiplier: f32) {
+        self.move_speed = speed;
+        self.sprint_multiplier = sprint_multiplier;
+        self.crouch_multiplier = crouch_multiplier;
+    }
 
-        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
-    ) {
-        if let Some(CubeLookAt { cube, .. }) = self.looking_at_cube {
-            let cube_vertices = Cube::mesh().0;
-            let indices = [
-                0, 1, // front t
-                1, 3, // front r
-                0, 2, // front l
-                2, 3, // front b
-                //
-                4, 5, // back t
-                5, 7, // back r
-                4, 6, // back l
-                6, 7, // back b
-                //
-                1, 5, // right t
-                3, 7, // right b
-                //
-                0, 4, // left t
-                2, 6, // left b
-            ];
-            let instances = [Instance {
-                color: [1., 1., 1., 1.],
-                translation: cube.cast::<f32>().unwrap().into(),
-                // scale a bit outward so that it doesn't collide with the block
-                // itself and draw glitched cube (because of depth collision)
-                scale: 1.012,
-                ..Default::default()
-            }];
-            let vertex_buffer = self.vertex_buffer_pool.chunk(cube_vertices).unwrap();
-            let instance_buffer = self.instance_buffer_pool.chunk(instances).unwrap();
-            let index_buffer = self.index_buffer_pool.chunk(indices).unwrap();
+    /// Sets how fast the movement speeds up towards the input and slows down
+    /// without input, in blocks per second squared, `0` makes it instant
+    pub fn set_acceleration(&mut self, acceleration: f32, friction: f32) {
+        self.acceleration = acceleration.max(0.);
+        self.friction = friction.max(0.);
+    }
 
-            builder
-                .bind_vertex_buffers(0, (vertex_buffer, instance_buffer.clone()))
-                .bind_pipeline_graphics(self.cubes_line_graphics_pipeline.clone())
-                .bind_index_buffer(index_buffer.clone())
-                .draw_indexed(
-                    index_buffer.len() as u32,
-                    instance_buffer.len() as u32,
-                    0,
-                    0,
-                    0,
-                )
-                .unwrap();
-        }
+    /// Sets how much the fov widens while sprinting, `0` disables it
+    pub fn set_sprint_fov<F: Into<Deg<f32>>>(&mut self, fov: F) {
+        self.sprint_fov = fov.into();
+        self.update_sprint_fov();
     }
 
-    fn render_ui(
-        &mut self,
-        img_size: [u32; 2],
-        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
-    ) {
-        // create a cross of 20 pixels in size
-        let vertices = [
-            Vertex {
-                pos: [0., 10., 0.],
-                normal: [0., 0., 0.],
-            },
-            Vertex {
-                pos: [0., -10., 0.],
-                normal: [0., 0., 0.],
-            },
-        ];
+    fn update_sprint_fov(&mut self) {
+        let offset = if self.sprinting {
+            self.sprint_fov
+        } else {
+            Deg(0.)
+        };
+        self.player.camera_mut().set_fov_offset(offset);
+    }
 
-        let vertex_buffer = CpuAccessibleBuffer::from_iter(
-            self.queue.device().clone(),
-            BufferUsage::vertex_buffer(),
-            false,
-            vertices.iter().cloned(),
-        )
-        .unwrap();
+    /// Sets the sky behind the blocks to a fixed color, instead of
+    /// following the time of day
+    pub fn set_sky_color(&mut self, color: [f32; 4]) {
+        self.sky = Sky::Solid(color);
+    }
 
-        let instances = [
-            // vertical
-            Instance {
-                color: [1., 1., 1., 1.],
-                translation: [img_size[0] as f32 / 2., img_size[1] as f32 / 2., 0.],
-                ..Default::default()
-            },
-            // horizontal (rotated)
-            Instance {
-                color: [1., 1., 1., 1.],
-                rotation: [0., 0., PI / 2.],
-                translation: [img_size[0] as f32 / 2., img_size[1] as f32 / 2., 0.],
-                ..Default::default()
-            },
-        ];
+    /// Sets how the sky is drawn, the fog takes the color of the bottom
+    /// of the gradient
+    pub fn set_sky(&mut self, sky: Sky) {
+        self.sky = sky;
+    }
 
-        let instance_buffer = CpuAccessibleBuffer::from_iter(
-            self.queue.device().clone(),
-            BufferUsage::vertex_buffer(),
-            false,
-            instances.iter().cloned(),
-        )
-        .unwrap();
+    /// The cursor should be grabbed and hidden by the window
+    pub fn mouse_captured(&self) -> bool {
+        self.mouse_captured
+    }
 
-        builder
-            .bind_vertex_buffers(0, (vertex_buffer.clone(), instance_buffer.clone()))
-            .bind_pipeline_graphics(self.ui_graphics_pipeline.clone())
-            .push_constants(
-                self.ui_graphics_pipeline.layout().clone(),
-                0,
-                ui_vs::ty::PushConstants {
-                    display_size: img_size,
-                },
-            )
-            .draw(
-                vertex_buffer.len() as u32,
-                instance_buffer.len() as u32,
-                0,
-                0,
-            )
-            .unwrap();
+    /// Darkens the corners of the faces touching other blocks, this is only
+    /// computed for the greedy meshes, not with the `per_cube_mesh` feature.
+    /// It's off by default
+    pub fn set_ambient_occlusion(&mut self, ambient_occlusion: bool) {
+        self.ambient_occlusion = ambient_occlusion;
     }
-}
 
-impl Engine {
-    /// place a random block at the current looking block
-    fn place_at_looking_at(&mut self) {
-        if let Some(cube) = &self.looking_at_cube {
-            // we use the direction to know where the ray is coming from
-            let new_cube = cube.cube + cube.direction;
-
-            self.world.push_cube(Cube {
-                center: new_cube.cast().unwrap(),
-                color: [1., 0.5, 1.0, 1.],
-                rotation: [0., 0., 0.],
-            })
+    /// Adds a light that lights up the cubes within its radius, returns
+    /// `false` if there are already `MAX_POINT_LIGHTS` lights
+    pub fn add_point_light(&mut self, light: PointLight) -> bool {
+        if self.point_lights.len() >= MAX_POINT_LIGHTS {
+            return false;
         }
+        self.point_lights.push(light);
+        true
     }
 
-    fn remove_looking_at(&mut self) {
-        if let Some(cube) = &self.looking_at_cube {
-            self.world.remove_cube(cube.cube);
-        }
+    /// Removes all the point lights
+    pub fn clear_point_lights(&mut self) {
+        self.point_lights.clear();
+    }
+
+    /// Throws away all the chunks and the edits to them, and generates the
+    /// chunks around the camera again
+    pub fn regenerate_world(&mut self) {
+        self.world.clear();
+        self.history.clear();
+        self.looking_at_cube = None;
+        self.world
+            .load_all_chunks_around(*self.player.camera().position(), self.load_radius);
+    }
+
+    /// Replaces the world with terrain from the heightmap image at `path`, see
+    /// [`World::generate_from_heightmap`]
+    pub fn load_heightmap(&mut self, path: &Path, scale: f32, max_height: i32) -> io::Result<()> {
+        self.world
+            .generate_from_heightmap(path, scale, max_height)?;
+        self.regenerate_world();
+        Ok(())
+    }
+
+    /// Pastes the schematic file at `path` with its origin at `origin`, see
+    /// [`World::paste_schematic`]
+    pub fn paste_schematic(&mut self, path: &Path, origin: Point3<i32>) -> io::Result<()> {
+        let schematic = Schematic::load_from_path(path)?;
+        self.world.paste_schematic(origin, &schematic);
+        Ok(())
+    }
+
+    /// Saves the blocks in the box between `a` and `b` (inclusive) into the
+    /// schematic file at `path`
+    pub fn export_schematic(&self, path: &Path, a: Point3<i32>, b: Point3<i32>) -> io::Result<()> {
+        Schematic::from_world(&self.world, a, b).save_to_path(path)
+    }
+
+    /// Replaces the world with flat terrain, see [`World::generate_superflat`]
+    pub fn generate_superflat(&mut self, layers: &[(BlockType, u32)]) {
+        self.world.generate_superflat(layers);
+        self.regenerate_world();
+    }
+
+    /// Saves the world and the current viewpoint into `path`
+    #[allow(dead_code)]
+    pub fn save_world(&self, path: &Path) -> io::Result<()> {
+        self.world
+            .save_to_path(path, Some(&self.player.camera().state()))
+    }
+
+    /// Like [`Engine::save_world`], but writes the file in the background so
+    /// the frame isn't blocked, the result is printed when it finishes.
+    ///
+    /// Only one save is written at a time, returns `false` if one is still in
+    /// progress.
+    pub fn save_world_in_background(&mut self, path: PathBuf) -> bool {
+        if self.pending_save.is_some() {
+            return false;
+        }
+
+        let pending = self
+            .world
+            .save_to_path_in_background(path.clone(), Some(&self.player.camera().state()));
+        self.pending_save = Some((path, pending));
+        true
+    }
+
+    /// Replaces the world with the one saved in `path`, and restores the
+    /// viewpoint if it was saved with one
+    #[allow(dead_code)]
+    pub fn load_world(&mut self, path: &Path) -> io::Result<()> {
+        let (mut world, camera) = World::load_from_path(path)?;
+        // the generation settings are not saved, new chunks keep using ours
+        world.take_generation_settings(&mut self.world);
+
+        self.world = world;
+        self.history.clear();
+        self.looking_at_cube = None;
+        if let Some(camera) = camera {
+            self.player.teleport(camera.position);
+            self.player.camera_mut().apply_state(&camera);
+        }
+        Ok(())
+    }
+
+    /// Moves the camera to `position`, the chunks around it are loaded in
+    /// the next updates
+    pub fn teleport(&mut self, position: Point3<f32>) {
+        self.player.teleport(position);
+    }
+
+    pub fn camera_position(&self) -> Point3<f32> {
+        *self.player.camera().position()
+    }
+
+    /// The `(yaw, pitch)` of the camera
+    pub fn camera_orientation(&self) -> (Deg<f32>, Deg<f32>) {
+        let camera = self.player.camera();
+        (camera.yaw().into(), camera.pitch().into())
+    }
+
+    /// Sets the fog in front of distant blocks, it starts at `start` blocks
+    /// from the camera and gets thicker with `density`, `0` disables it.
+    /// The fog is disabled by default
+    pub fn set_fog(&mut self, density: f32, start: f32) {
+        self.fog_density = density.max(0.);
+        self.fog_start = start.max(0.);
+    }
+
+    /// Sets the size in pixels and the corner of the minimap
+    pub fn set_minimap(&mut self, size: f32, corner: Corner) {
+        self.minimap.size = size;
+        self.minimap.corner = corner;
+    }
+
+    /// Shows the fps and frame time at the top left of the screen
+    pub fn set_show_debug_overlay(&mut self, show: bool) {
+        self.show_debug_overlay = show;
+    }
+
+    /// Records how long the last frame took, for the fps counter
+    pub fn record_frame_time(&mut self, frame_time: Duration) {
+        if self.frame_times.len() == FRAME_TIMES_COUNT {
+            self.frame_times.pop_front();
+        }
+        self.frame_times.push_back(frame_time);
+    }
+
+    /// The average time of the last few frames
+    fn average_frame_time(&self) -> Option<Duration> {
+        if self.frame_times.is_empty() {
+            None
+        } else {
+            Some(self.frame_times.iter().sum::<Duration>() / self.frame_times.len() as u32)
+        }
+    }
+
+    /// Sets how far blocks are highlighted, and how far they can be placed
+    /// and removed, the reach is limited to the look radius
+    pub fn set_reach(&mut self, look_radius: f32, reach_distance: f32) {
+        self.look_radius = look_radius;
+        self.reach_distance = reach_distance.min(look_radius);
+    }
+
+    /// How often a block is removed while holding the left mouse button
+    #[allow(dead_code)]
+    pub fn set_mining_interval(&mut self, interval: Duration) {
+        self.mining_interval = interval;
+    }
+
+    #[allow(dead_code)]
+    pub fn set_control_config(&mut self, config: CameraControlConfig) {
+        self.control_config = config;
+    }
+
+    /// Binds `key` to `action`, replacing any action previously bound to it
+    #[allow(dead_code)]
+    pub fn rebind(&mut self, action: Action, key: VirtualKeyCode) {
+        self.key_bindings.rebind(action, key);
+    }
+
+    /// Logs the memory used by the world and the buffer pools every `interval`,
+    /// `None` stops logging
+    pub fn set_memory_report_interval(&mut self, interval: Option<Duration>) {
+        self.memory_report_interval = interval;
+        self.since_memory_report = Duration::ZERO;
+    }
+
+    /// Prints [`World::memory_report`] and the capacity of the buffer pools
+    /// in `key=value` pairs, the sizes are in KiB
+    fn log_memory_report(&self) {
+        fn pool_size<T>(pool: &CpuBufferPool<T>) -> u64
+        where
+            [T]: BufferContents,
+        {
+            pool.capacity() * std::mem::size_of::<T>() as u64
+        }
+
+        let report = self.world.memory_report();
+        let buffer_pools = pool_size(&self.gpu.vertex_buffer_pool)
+            + pool_size(&self.gpu.instance_buffer_pool)
+            + pool_size(&self.gpu.index_buffer_pool)
+            + pool_size(&self.gpu.uniform_buffer_pool)
+            + pool_size(&self.gpu.point_lights_buffer_pool);
+
+        println!(
+            "memory: chunks={} unloaded_chunks={} instances={} cubes_kib={} \
+             instances_kib={} buffer_pools_kib={}",
+            report.chunks,
+            report.unloaded_chunks,
+            report.instances,
+            report.cubes_memory / 1024,
+            report.instances_memory / 1024,
+            buffer_pools / 1024,
+        );
+    }
+
+    /// Makes chunks loaded while moving fade in over `duration` instead of
+    /// appearing at once, zero disables the fade
+    #[allow(dead_code)]
+    pub fn set_chunk_fade_duration(&mut self, duration: Duration) {
+        self.chunk_fade_duration = duration;
+    }
+
+    /// The alpha multiplier of `chunk` at `now`, from `0` when it was loaded
+    /// to `1` after the fade duration
+    fn chunk_fade(&self, chunk: &Chunk, now: Instant) -> f32 {
+        match chunk.load_time() {
+            Some(load_time) if !self.chunk_fade_duration.is_zero() => {
+                let elapsed = now.saturating_duration_since(load_time);
+                (elapsed.as_secs_f32() / self.chunk_fade_duration.as_secs_f32()).min(1.)
+            }
+            _ => 1.,
+        }
+    }
+
+    /// Gathers the world and camera state in one place
+    pub fn debug_snapshot(&self) -> DebugSnapshot {
+        DebugSnapshot::new(
+            &self.world,
+            self.camera_position(),
+            self.camera_orientation(),
+            self.player.camera().fov().into(),
+            self.looking_at_cube.as_ref(),
+            self.render_stats,
+        )
+    }
+
+    /// Handles the `Ctrl+<key>` shortcuts, returns `false` if `keycode` is not
+    /// one of them
+    fn handle_ctrl_shortcut(&mut self, keycode: VirtualKeyCode) -> bool {
+        match keycode {
+            VirtualKeyCode::Z => self.undo(),
+            VirtualKeyCode::Y => self.redo(),
+            VirtualKeyCode::S => {
+                if !self.save_world_in_background(PathBuf::from(QUICK_SAVE_PATH)) {
+                    println!("A save is already in progress");
+                }
+            }
+            VirtualKeyCode::L => self.clear_point_lights(),
+            _ => return false,
+        }
+        true
+    }
+
+    /// Applies an action pressed or released on any input device
+    fn handle_action(&mut self, action: Action, pressed: bool) {
+        let value = if pressed { 1. } else { 0. };
+        match action {
+            Action::MoveForward => self.moving_direction.z = value,
+            Action::MoveBack => self.moving_direction.z = -value,
+            Action::StrafeRight => self.moving_direction.x = value,
+            Action::StrafeLeft => self.moving_direction.x = -value,
+            Action::MoveUp => self.moving_direction.y = value,
+            Action::MoveDown => self.moving_direction.y = -value,
+            // holding the key repeats the press event
+            Action::Sprint => {
+                self.sprinting = pressed;
+                self.update_sprint_fov();
+            }
+            Action::Place if pressed => self.place_at_looking_at(),
+            Action::Place => {}
+            // the first block is removed right away, then every `mining_interval`
+            Action::Remove if pressed && !self.mining => {
+                self.remove_looking_at();
+                self.mining = true;
+                self.mining_timer = Duration::ZERO;
+            }
+            Action::Remove => self.mining = pressed,
+        }
+    }
+
+    /// Applies the controller buttons and right stick, and returns the
+    /// analog movement of the left stick
+    #[cfg(feature = "gamepad")]
+    fn update_gamepad(&mut self, delta: Duration) -> Vector2<f32> {
+        // degrees per second with the stick fully tilted
+        const LOOK_SPEED: f32 = 180.;
+
+        let (actions, movement, look) = match &mut self.gamepad {
+            Some(gamepad) => (gamepad.poll_actions(), gamepad.movement(), gamepad.look()),
+            None => return Vector2::new(0., 0.),
+        };
+
+        for (action, pressed) in actions {
+            self.handle_action(action, pressed);
+        }
+
+        let step = LOOK_SPEED * delta.as_secs_f32();
+        self.player
+            .camera_mut()
+            .rotate_camera(Deg(look.y * step), Deg(look.x * step));
+
+        movement
+    }
+
+    pub fn update(&mut self, delta: Duration) {
+        self.time += delta.as_secs_f32();
+        if self.day_speed != 0. {
+            self.set_time_of_day(self.time_of_day + self.day_speed * delta.as_secs_f32());
+        }
+
+        let mut moving_direction = self.moving_direction;
+        // the stick is added to the keys, so both can be used at the same time
+        #[cfg(feature = "gamepad")]
+        {
+            let movement = self.update_gamepad(delta);
+            moving_direction.x = (moving_direction.x + movement.x).clamp(-1., 1.);
+            moving_direction.z = (moving_direction.z + movement.y).clamp(-1., 1.);
+        }
+        if self.player.physics_enabled() {
+            // with physics, going up is jumping, and going down is crouching
+            if moving_direction.y > 0. {
+                self.player.jump();
+            }
+            self.player
+                .set_crouching(&self.world, moving_direction.y < 0.);
+            moving_direction.y = 0.;
+        }
+
+        let mut speed = self.move_speed;
+        if self.sprinting {
+            speed *= self.sprint_multiplier;
+        }
+        if self.player.crouching() {
+            speed *= self.crouch_multiplier;
+        }
+
+        if let Some((path, pending)) = &self.pending_save {
+            if let Some(result) = pending.poll() {
+                match result {
+                    Ok(()) => println!("Saved world to {}", path.display()),
+                    Err(e) => eprintln!("Failed to save world to {}: {}", path.display(), e),
+                }
+                self.pending_save = None;
+            }
+        }
+
+        self.particles.update(delta);
+
+        if let Some(recording) = &mut self.recording_camera_path {
+            recording.since_keyframe += delta;
+            if recording.since_keyframe >= CAMERA_PATH_KEYFRAME_INTERVAL {
+                recording.since_keyframe = Duration::ZERO;
+                let keyframe = Keyframe::from_camera(self.player.camera(), recording.elapsed);
+                recording.path.push(keyframe);
+            }
+            recording.elapsed += delta;
+        }
+
+        if let Some(interval) = self.memory_report_interval {
+            self.since_memory_report += delta;
+            if self.since_memory_report >= interval {
+                self.since_memory_report = Duration::ZERO;
+                self.log_memory_report();
+            }
+        }
+
+        self.player.camera_mut().update(delta);
+        // the playing camera path is moved in `render`
+        if self.camera_path.is_some() {
+            self.velocity = Vector3::new(0., 0., 0.);
+        } else {
+            self.velocity = eased_velocity(
+                self.velocity,
+                moving_direction * speed,
+                self.acceleration,
+                self.friction,
+                delta,
+            );
+
+            self.player
+                .move_player(&self.world, self.velocity * delta.as_secs_f32());
+            self.player.apply_physics(&self.world, delta);
+        }
+
+        const DELETE_RADIUS: f32 = 10.;
+
+        self.world
+            .update_loaded_chunks(*self.player.camera().position(), self.load_radius);
+
+        // dig through the world while flying, this is disabled with physics
+        // as we would keep falling through the ground
+        if !self.player.physics_enabled() {
+            let position = *self.player.camera().position();
+            let cubes = self
+                .world
+                .chunks_around(
+                    Point2::new(position.x as i32, position.z as i32),
+                    DELETE_RADIUS,
+                )
+                .flat_map(|chunk| {
+                    chunk.cubes_around(position.cast::<i32>().unwrap(), DELETE_RADIUS)
+                })
+                .collect::<Vec<_>>();
+
+            for cube in cubes {
+                // the cubes are taken from the chunks, so they are in bounds
+                let _ = self.world.remove_cube(cube);
+            }
+        }
+
+        let result = self.world.cube_looking_at(
+            self.player.camera().position(),
+            self.player.camera().direction(),
+            self.look_radius,
+        );
+        self.looking_at_cube = result.result_cube;
+
+        if self.mining && self.looking_at_cube_in_reach().is_some() {
+            self.mining_timer += delta;
+            if self.mining_timer >= self.mining_interval {
+                self.mining_timer = Duration::ZERO;
+                self.remove_looking_at();
+            }
+        } else {
+            // start counting again when we look at a new cube
+            self.mining_timer = Duration::ZERO;
+        }
+    }
+
+    /// Returns the depth buffer, it is recreated first if its size, format
+    /// or sample count don't match the given ones
+    fn ensure_depth_buffer(
+        &mut self,
+        size: [u32; 2],
+        format: Format,
+        samples: SampleCount,
+    ) -> Arc<ImageView<AttachmentImage>> {
+        let wanted = AttachmentDesc {
+            size,
+            format,
+            samples,
+        };
+        let current = self
+            .gpu
+            .depth_buffer
+            .as_ref()
+            .map(|depth_buffer| AttachmentDesc::of(depth_buffer.image()));
+
+        if needs_recreation(current, wanted) {
+            self.gpu.depth_buffer = Some(
+                ImageView::new_default(
+                    AttachmentImage::transient_multisampled(
+                        self.gpu.queue.device().clone(),
+                        size,
+                        samples,
+                        format,
+                    )
+                    .unwrap(),
+                )
+                .unwrap(),
+            );
+        }
+
+        self.gpu.depth_buffer.clone().unwrap()
+    }
+
+    pub fn render<Fin>(&mut self, image: Arc<dyn ImageAccess>, future: Fin) -> Box<dyn GpuFuture>
+    where
+        Fin: GpuFuture + 'static,
+    {
+        self.step_camera_path();
+
+        let img_size = image.dimensions().width_height();
+        // save for later
+        self.viewport_size = [img_size[0] as f32, img_size[1] as f32];
+        let scene_size = [
+            ((img_size[0] as f32 * self.render_scale).round() as u32).max(1),
+            ((img_size[1] as f32 * self.render_scale).round() as u32).max(1),
+        ];
+
+        let depth_buffer = self.ensure_depth_buffer(scene_size, DEPTH_FORMAT, self.gpu.samples);
+        // only resize when needed
+        if self.gpu.scene_image.image().dimensions().width_height() != scene_size {
+            self.gpu.scene_image =
+                Self::create_scene_image(self.gpu.queue.clone(), self.gpu.image_format, scene_size);
+
+            if self.gpu.samples != SampleCount::Sample1 {
+                self.gpu.multisampled_image = Some(
+                    ImageView::new_default(
+                        AttachmentImage::transient_multisampled(
+                            self.gpu.queue.device().clone(),
+                            scene_size,
+                            self.gpu.samples,
+                            self.gpu.image_format,
+                        )
+                        .unwrap(),
+                    )
+                    .unwrap(),
+                );
+            }
+        }
+
+        self.save_pending_screenshot();
+
+        let framebuffer = Framebuffer::new(
+            self.gpu.render_pass.clone(),
+            FramebufferCreateInfo {
+                attachments: match &self.gpu.multisampled_image {
+                    Some(multisampled_image) => vec![
+                        multisampled_image.clone(),
+                        depth_buffer,
+                        self.gpu.scene_image.clone(),
+                    ],
+                    None => vec![self.gpu.scene_image.clone(), depth_buffer],
+                },
+                ..Default::default()
+            },
+        )
+        .unwrap();
+
+        let mut builder = AutoCommandBufferBuilder::primary(
+            self.gpu.queue.device().clone(),
+            self.gpu.queue.family(),
+            CommandBufferUsage::OneTimeSubmit,
+        )
+        .unwrap();
+
+        let sky_color = match self.sky {
+            Sky::DayCycle => sky::sky_color(self.time_of_day),
+            Sky::Solid(color) => color,
+            // the fog blends into the horizon
+            Sky::Gradient { bottom, .. } => bottom,
+        };
+        let mut clear_values = vec![
+            ClearValue::Float(sky_color),
+            ClearValue::Depth(self.gpu.depth_mode.far_depth()),
+        ];
+        if self.gpu.multisampled_image.is_some() {
+            // the resolve attachment is not cleared
+            clear_values.push(ClearValue::None);
+        }
+
+        builder
+            .begin_render_pass(framebuffer, SubpassContents::Inline, clear_values)
+            .unwrap();
+
+        self.world.update_meshes();
+
+        self.player
+            .camera_mut()
+            .set_aspect(self.viewport_size[0] / self.viewport_size[1]);
+
+        if let Err(e) = self.render_scene(&mut builder, scene_size, sky_color) {
+            eprintln!("WARN: failed to allocate the frame uniforms, the scene was skipped: {e}");
+        }
+
+        builder.end_render_pass().unwrap();
+
+        // upscale the scene into the output image
+        builder
+            .blit_image(
+                self.gpu.scene_image.image().clone(),
+                [0, 0, 0],
+                [scene_size[0] as i32, scene_size[1] as i32, 1],
+                0,
+                0,
+                image.clone(),
+                [0, 0, 0],
+                [img_size[0] as i32, img_size[1] as i32, 1],
+                0,
+                0,
+                1,
+                Filter::Linear,
+            )
+            .unwrap();
+
+        let ui_framebuffer = Framebuffer::new(
+            self.gpu.ui_render_pass.clone(),
+            FramebufferCreateInfo {
+                attachments: vec![ImageView::new_default(image.clone()).unwrap()],
+                ..Default::default()
+            },
+        )
+        .unwrap();
+
+        // the ui is always at the native resolution
+        builder
+            .begin_render_pass(
+                ui_framebuffer,
+                SubpassContents::Inline,
+                vec![ClearValue::None],
+            )
+            .unwrap()
+            .set_viewport(
+                0,
+                [Viewport {
+                    origin: [0.0, 0.0],
+                    dimensions: self.viewport_size,
+                    depth_range: 0.0..1.0,
+                }],
+            );
+        if let Err(e) = self.render_ui(img_size, &mut builder) {
+            eprintln!("WARN: failed to allocate the ui buffers: {e}");
+        }
+
+        builder.end_render_pass().unwrap();
+
+        if self.screenshot_requested {
+            self.screenshot_requested = false;
+
+            let buffer = CpuAccessibleBuffer::from_iter(
+                self.gpu.queue.device().clone(),
+                BufferUsage::transfer_destination(),
+                false,
+                (0..img_size[0] * img_size[1] * 4).map(|_| 0u8),
+            )
+            .unwrap();
+            builder.copy_image_to_buffer(image, buffer.clone()).unwrap();
+            self.pending_screenshot = Some(PendingScreenshot {
+                buffer,
+                size: img_size,
+            });
+        }
+
+        let command_buffer = builder.build().unwrap();
+
+        future
+            .then_execute(self.gpu.queue.clone(), command_buffer)
+            .unwrap()
+            .boxed()
+    }
+
+    /// Draws the sky, the chunks and everything in the world into the scene
+    /// image. Fails if the uniforms of the frame can't be allocated, the
+    /// chunks whose buffers can't be allocated are skipped
+    fn render_scene(
+        &mut self,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+        scene_size: [u32; 2],
+        sky_color: [f32; 4],
+    ) -> Result<(), DeviceMemoryAllocationError> {
+        let perspective = self.player.camera_mut().perspective();
+        let view = self.player.camera_mut().view();
+        let uniform_subbuffer = self
+            .gpu
+            .uniform_buffer_pool
+            .next(cubes_vs::ty::UniformData {
+                perspective: perspective.into(),
+                view: view.into(),
+                light_direction: (self.sun_direction * self.sun_intensity).into(),
+                ambient: self.ambient_light,
+                fog_color: [sky_color[0], sky_color[1], sky_color[2]],
+                fog_density: self.fog_density,
+                fog_start: self.fog_start,
+                face_shading: if self.face_shading { 1. } else { 0. },
+                ambient_occlusion: if self.ambient_occlusion { 1. } else { 0. },
+                time: self.time,
+            })?;
+        let mut point_lights = cubes_fs::ty::PointLights {
+            positions: [[0.; 4]; MAX_POINT_LIGHTS],
+            colors: [[0.; 4]; MAX_POINT_LIGHTS],
+            count: self.point_lights.len() as u32,
+        };
+        for (i, light) in self.point_lights.iter().enumerate() {
+            let position = light.position;
+            let [r, g, b] = light.color;
+            point_lights.positions[i] = [position.x, position.y, position.z, light.radius];
+            point_lights.colors[i] = [r, g, b, 0.];
+        }
+        let point_lights_subbuffer = self.gpu.point_lights_buffer_pool.next(point_lights)?;
+        let descriptor_set = self
+            .gpu
+            .descriptor_set_pool
+            .next([
+                WriteDescriptorSet::buffer(0, uniform_subbuffer),
+                WriteDescriptorSet::buffer(1, point_lights_subbuffer),
+                WriteDescriptorSet::buffer(2, self.gpu.block_colors_buffer.clone()),
+            ])
+            .unwrap();
+
+        builder.set_viewport(
+            0,
+            [Viewport {
+                origin: [0.0, 0.0],
+                dimensions: [scene_size[0] as f32, scene_size[1] as f32],
+                depth_range: 0.0..1.0,
+            }],
+        );
+
+        if let Sky::Gradient { top, bottom } = self.sky {
+            builder
+                .bind_pipeline_graphics(self.gpu.sky_graphics_pipeline.clone())
+                .push_constants(
+                    self.gpu.sky_graphics_pipeline.layout().clone(),
+                    0,
+                    sky_vs::ty::PushConstants { top, bottom },
+                )
+                .draw(3, 1, 0, 0)
+                .unwrap();
+        }
+
+        builder
+            .bind_descriptor_sets(
+                PipelineBindPoint::Graphics,
+                self.gpu.cubes_graphics_pipeline.layout().clone(),
+                0,
+                (descriptor_set, self.gpu.atlas_descriptor_set.clone()),
+            )
+            .bind_pipeline_graphics(self.gpu.cubes_graphics_pipeline.clone());
+
+        let frustum = self.player.camera_mut().frustum_planes();
+        let camera_position = *self.player.camera().position();
+
+        // the builder is passed in, so the pipeline can be changed between draws
+        let render_mesh = |builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+                           vertices: &[Vertex],
+                           indices: &[u32],
+                           instances: &[Instance]|
+         -> Result<(), DeviceMemoryAllocationError> {
+            let index_buffer = self.gpu.index_buffer_pool.chunk(indices.iter().cloned())?;
+
+            let vertex_buffer = self
+                .gpu
+                .vertex_buffer_pool
+                .chunk(vertices.iter().cloned())?;
+
+            let instance_buffer = self
+                .gpu
+                .instance_buffer_pool
+                .chunk(instances.iter().cloned())?;
+
+            builder
+                .bind_index_buffer(index_buffer.clone())
+                .bind_vertex_buffers(0, (vertex_buffer, instance_buffer.clone()))
+                .draw_indexed(
+                    index_buffer.len() as u32,
+                    instance_buffer.len() as u32,
+                    0,
+                    0,
+                    0,
+                )
+                .unwrap();
+
+            Ok(())
+        };
+
+        // allocating the buffers can fail for very large meshes, in that case
+        // the chunk is skipped for this frame instead of crashing
+        let mut allocation_error = None;
+
+        let visible_chunks = self
+            .world
+            .chunks()
+            // skip chunks that are fully outside the view
+            .filter(|chunk| chunk.bounding_box().intersects_frustum(&frustum))
+            .collect::<Vec<_>>();
+        let now = Instant::now();
+        let fades = visible_chunks
+            .iter()
+            .map(|chunk| self.chunk_fade(chunk, now))
+            .collect::<Vec<_>>();
+        let lods = visible_chunks
+            .iter()
+            .map(|chunk| self.chunk_lod(chunk, camera_position))
+            .collect::<Vec<_>>();
+
+        // slabs are not transparent, so they are drawn with the opaque meshes
+        let mut opaque_meshes = Vec::new();
+        // the opaque meshes of chunks that are fading in, with their alpha lowered
+        let mut fading_meshes = Vec::new();
+        for ((chunk, &fade), &lod) in visible_chunks.iter().zip(&fades).zip(&lods) {
+            // far chunks only have their surface
+            let meshes = if lod == 0 {
+                vec![chunk.mesh().view(), chunk.slab_mesh().view()]
+            } else {
+                vec![chunk.mesh_lod(lod).view()]
+            };
+            for mesh in meshes {
+                let mesh = self.limit_instances(mesh);
+                if mesh.instances.is_empty() {
+                    continue;
+                }
+                if fade < 1. {
+                    let instances = faded_instances(mesh.instances, fade);
+                    fading_meshes.push((mesh, instances));
+                } else {
+                    opaque_meshes.push(mesh);
+                }
+            }
+        }
+        let transparent_meshes = visible_chunks
+            .iter()
+            .zip(&fades)
+            .zip(&lods)
+            // the surface mesh already has the transparent blocks
+            .filter(|(_, &lod)| lod == 0)
+            .map(|(chunk_fade, _)| chunk_fade)
+            .filter(|(chunk, _)| !chunk.transparent_mesh().is_empty())
+            .map(|(chunk, &fade)| (self.limit_instances(chunk.transparent_mesh().view()), fade))
+            // all the instances may be cut
+            .filter(|(mesh, _)| !mesh.instances.is_empty())
+            .collect::<Vec<_>>();
+
+        // counted from the meshes that are drawn below
+        let visible_instances = opaque_meshes
+            .iter()
+            .chain(fading_meshes.iter().map(|(mesh, _)| mesh))
+            .chain(transparent_meshes.iter().map(|(mesh, _)| mesh))
+            .map(|mesh| mesh.instances.len())
+            .sum();
+        self.render_stats = RenderStats {
+            chunks: self.world.chunk_count(),
+            visible_chunks: visible_chunks.len(),
+            instances: self.world.instance_count(),
+            visible_instances,
+        };
+
+        if self.wireframe {
+            let all_meshes = opaque_meshes
+                .iter()
+                .chain(fading_meshes.iter().map(|(mesh, _)| mesh))
+                .chain(transparent_meshes.iter().map(|(mesh, _)| mesh));
+
+            // fill the depth first, so only the visible edges are drawn
+            builder.bind_pipeline_graphics(self.gpu.cubes_depth_graphics_pipeline.clone());
+            allocation_error = draw_each(all_meshes.clone(), |mesh| {
+                render_mesh(builder, mesh.vertices, mesh.indices, mesh.instances)
+            })
+            .or(allocation_error);
+
+            builder.bind_pipeline_graphics(self.gpu.cubes_line_graphics_pipeline.clone());
+            allocation_error = draw_each(all_meshes, |mesh| {
+                render_mesh(builder, mesh.vertices, mesh.outline_indices, mesh.instances)
+            })
+            .or(allocation_error);
+        } else {
+            if self.depth_prepass {
+                builder.bind_pipeline_graphics(self.gpu.cubes_prepass_graphics_pipeline.clone());
+                allocation_error = draw_each(&opaque_meshes, |mesh| {
+                    render_mesh(builder, mesh.vertices, mesh.indices, mesh.instances)
+                })
+                .or(allocation_error);
+                builder.bind_pipeline_graphics(self.gpu.cubes_equal_graphics_pipeline.clone());
+            }
+
+            allocation_error = draw_each(&opaque_meshes, |mesh| {
+                render_mesh(builder, mesh.vertices, mesh.indices, mesh.instances)
+            })
+            .or(allocation_error);
+
+            // blended over the rest of the world, they still write the depth so
+            // only their closest faces are seen through. Not in the prepass, as
+            // the world behind them must be drawn
+            if !fading_meshes.is_empty() {
+                builder.bind_pipeline_graphics(self.gpu.cubes_graphics_pipeline.clone());
+                allocation_error = draw_each(&fading_meshes, |(mesh, instances)| {
+                    render_mesh(builder, mesh.vertices, mesh.indices, instances)
+                })
+                .or(allocation_error);
+            }
+
+            // transparent faces are blended with what is behind them, so they
+            // are drawn last, from the furthest to the closest
+            if let Some((mesh, _)) = transparent_meshes.first() {
+                let distance = |instance: &Instance| {
+                    (Point3::from(instance.translation) - camera_position).magnitude2()
+                };
+                let mut instances = transparent_meshes
+                    .iter()
+                    .flat_map(|(mesh, fade)| faded_instances(mesh.instances, *fade))
+                    .collect::<Vec<_>>();
+                instances.sort_by(|a, b| distance(b).total_cmp(&distance(a)));
+
+                // all chunk meshes share the same vertices and indices
+                builder
+                    .bind_pipeline_graphics(self.gpu.cubes_transparent_graphics_pipeline.clone());
+                if let Err(e) = render_mesh(builder, mesh.vertices, mesh.indices, &instances) {
+                    allocation_error = Some(e);
+                }
+            }
+        }
+
+        if let Some(e) = allocation_error {
+            eprintln!("WARN: failed to allocate chunk buffers, some chunks were skipped: {e}");
+        }
+
+        if let Err(e) = self.render_particles(builder, perspective, view) {
+            eprintln!("WARN: failed to allocate the particles buffers: {e}");
+        }
+
+        if let Err(e) = self.render_looking_at(builder) {
+            eprintln!("WARN: failed to allocate the outline buffers: {e}");
+        }
+
+        if self.show_chunk_borders {
+            if let Err(e) = self.render_chunk_borders(builder) {
+                eprintln!("WARN: failed to allocate the chunk borders buffers: {e}");
+            }
+        }
+
+        // last, so the lines above are not hidden behind it
+        if self.show_placement_preview {
+            if let Err(e) = self.render_placement_preview(builder) {
+                eprintln!("WARN: failed to allocate the placement preview buffers: {e}");
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Returns the requested msaa `samples` if the device supports it for both
+    /// color and depth attachments, otherwise no multisampling is used
+    fn supported_samples(queue: &Queue, samples: u32) -> SampleCount {
+        let properties = queue.device().physical_device().properties();
+
+        match SampleCount::try_from(samples) {
+            Ok(count)
+                if properties.framebuffer_color_sample_counts.contains(count)
+                    && properties.framebuffer_depth_sample_counts.contains(count) =>
+            {
+                count
+            }
+            _ => {
+                eprintln!("WARN: {samples} msaa samples are not supported, disabling msaa");
+                SampleCount::Sample1
+            }
+        }
+    }
+
+    fn create_scene_image(
+        queue: Arc<Queue>,
+        format: Format,
+        size: [u32; 2],
+    ) -> Arc<ImageView<AttachmentImage>> {
+        ImageView::new_default(
+            AttachmentImage::with_usage(
+                queue.device().clone(),
+                size,
+                format,
+                ImageUsage {
+                    color_attachment: true,
+                    transfer_source: true,
+                    ..ImageUsage::none()
+                },
+            )
+            .unwrap(),
+        )
+        .unwrap()
+    }
+
+    /// Draws the particles as squares facing the camera
+    fn render_particles(
+        &self,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+        perspective: Matrix4<f32>,
+        view: Matrix4<f32>,
+    ) -> Result<(), DeviceMemoryAllocationError> {
+        if self.particles.is_empty() {
+            return Ok(());
+        }
+
+        let instances = self.particles.squares().map(|square| square.to_instance());
+
+        let (vertices, indices) = Square::mesh();
+        let vertex_buffer = self.gpu.vertex_buffer_pool.chunk(vertices)?;
+        let instance_buffer = self.gpu.instance_buffer_pool.chunk(instances)?;
+        let index_buffer = self.gpu.index_buffer_pool.chunk(indices)?;
+
+        builder
+            .bind_pipeline_graphics(self.gpu.billboard_graphics_pipeline.clone())
+            .push_constants(
+                self.gpu.billboard_graphics_pipeline.layout().clone(),
+                0,
+                billboard_vs::ty::PushConstants {
+                    perspective: perspective.into(),
+                    view: view.into(),
+                },
+            )
+            .bind_vertex_buffers(0, (vertex_buffer, instance_buffer.clone()))
+            .bind_index_buffer(index_buffer.clone())
+            .draw_indexed(
+                index_buffer.len() as u32,
+                instance_buffer.len() as u32,
+                0,
+                0,
+                0,
+            )
+            .unwrap();
+
+        Ok(())
+    }
+
+    fn render_looking_at(
+        &mut self,
+
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+    ) -> Result<(), DeviceMemoryAllocationError> {
+        if let Some(CubeLookAt { cube, .. }) = self.looking_at_cube {
+            let cube_vertices = cube_line_vertices();
+            let instances = [Instance {
+                color: self.outline_style.color,
+                translation: cube.cast::<f32>().unwrap().into(),
+                // scale a bit outward so that it doesn't collide with the block
+                // itself and draw glitched cube (because of depth collision)
+                scale: self.outline_style.scale,
+                ..Default::default()
+            }];
+            let vertex_buffer = self.gpu.vertex_buffer_pool.chunk(cube_vertices)?;
+            let instance_buffer = self.gpu.instance_buffer_pool.chunk(instances)?;
+            let index_buffer = self.gpu.index_buffer_pool.chunk(CUBE_EDGES)?;
+
+            builder
+                .bind_vertex_buffers(0, (vertex_buffer, instance_buffer.clone()))
+                .bind_pipeline_graphics(self.gpu.cubes_line_graphics_pipeline.clone())
+                .bind_index_buffer(index_buffer.clone())
+                .draw_indexed(
+                    index_buffer.len() as u32,
+                    instance_buffer.len() as u32,
+                    0,
+                    0,
+                    0,
+                )
+                .unwrap();
+        }
+
+        Ok(())
+    }
+
+    /// Draws the active block, translucent, where it would be placed. Nothing
+    /// is drawn if it can't be placed there, or it would be inside the player
+    fn render_placement_preview(
+        &self,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+    ) -> Result<(), DeviceMemoryAllocationError> {
+        /// The alpha the block colors are multiplied by
+        const PREVIEW_ALPHA: f32 = 0.4;
+
+        let pos = match self.looking_at_cube_in_reach() {
+            Some(cube) => cube.cube + cube.face.normal(),
+            None => return Ok(()),
+        };
+        let center = pos.cast::<f32>().unwrap();
+        let cube_box = Aabb::new(
+            center - Vector3::new(0.5, 0.5, 0.5),
+            center + Vector3::new(0.5, 0.5, 0.5),
+        );
+        if !(0..self.world.height()).contains(&pos.y) || cube_box.intersects(&self.player.aabb()) {
+            return Ok(());
+        }
+
+        // the color is multiplied with the face colors of the block
+        let color = [1., 1., 1., PREVIEW_ALPHA];
+        let texture_index = self.active_block.texture_index();
+        let (vertices, indices, instance) = match self.active_shape {
+            BlockShape::Cube => {
+                let (vertices, indices) = Cube::mesh();
+                let cube = Cube {
+                    center,
+                    color,
+                    rotation: [0.; 3],
+                    texture_index,
+                    wave: false,
+                };
+                (vertices, indices, cube.to_instance())
+            }
+            BlockShape::Slab => {
+                let (vertices, indices) = Slab::mesh();
+                let slab = Slab {
+                    center,
+                    color,
+                    rotation: [0.; 3],
+                    texture_index,
+                };
+                (vertices, indices, slab.to_instance())
+            }
+        };
+
+        let vertex_buffer = self.gpu.vertex_buffer_pool.chunk(vertices)?;
+        let instance_buffer = self.gpu.instance_buffer_pool.chunk([instance])?;
+        let index_buffer = self.gpu.index_buffer_pool.chunk(indices)?;
+
+        // the faces touching other blocks are at the same depth as theirs, and
+        // are not drawn, since only closer faces pass the depth test
+        builder
+            .bind_vertex_buffers(0, (vertex_buffer, instance_buffer.clone()))
+            .bind_pipeline_graphics(self.gpu.cubes_graphics_pipeline.clone())
+            .bind_index_buffer(index_buffer.clone())
+            .draw_indexed(
+                index_buffer.len() as u32,
+                instance_buffer.len() as u32,
+                0,
+                0,
+                0,
+            )
+            .unwrap();
+
+        Ok(())
+    }
+
+    /// Draws the outlines of the loaded chunks, from the bottom to the top
+    /// of the world
+    fn render_chunk_borders(
+        &self,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+    ) -> Result<(), DeviceMemoryAllocationError> {
+        let height = self.world.height() as f32;
+        let instances = self
+            .world
+            .loaded_chunk_ids()
+            .map(|(x, z)| Instance {
+                color: [1., 1., 0., 1.],
+                // cubes are centered on their position
+                translation: [
+                    x as f32 + (CHUNK_SIZE - 1) as f32 / 2.,
+                    height / 2. - 0.5,
+                    z as f32 + (CHUNK_SIZE - 1) as f32 / 2.,
+                ],
+                size: [CHUNK_SIZE as f32, height, CHUNK_SIZE as f32],
+                ..Default::default()
+            })
+            .collect::<Vec<_>>();
+        if instances.is_empty() {
+            return Ok(());
+        }
+
+        let vertex_buffer = self.gpu.vertex_buffer_pool.chunk(cube_line_vertices())?;
+        let instance_buffer = self.gpu.instance_buffer_pool.chunk(instances)?;
+        let index_buffer = self.gpu.index_buffer_pool.chunk(CUBE_EDGES)?;
+
+        builder
+            .bind_vertex_buffers(0, (vertex_buffer, instance_buffer.clone()))
+            .bind_pipeline_graphics(self.gpu.cubes_line_graphics_pipeline.clone())
+            .bind_index_buffer(index_buffer.clone())
+            .draw_indexed(
+                index_buffer.len() as u32,
+                instance_buffer.len() as u32,
+                0,
+                0,
+                0,
+            )
+            .unwrap();
+
+        Ok(())
+    }
+
+    fn render_ui(
+        &mut self,
+        img_size: [u32; 2],
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+    ) -> Result<(), DeviceMemoryAllocationError> {
+        // a vertical line of 20 pixels in size
+        let vertices = [
+            Vertex {
+                pos: [0., 10., 0.],
+                normal: [0., 0., 0.],
+                tex_coord: [0., 0.],
+                face: NO_FACE,
+            },
+            Vertex {
+                pos: [0., -10., 0.],
+                normal: [0., 0., 0.],
+                tex_coord: [0., 0.],
+                face: NO_FACE,
+            },
+        ];
+
+        let vertex_buffer = CpuAccessibleBuffer::from_iter(
+            self.gpu.queue.device().clone(),
+            BufferUsage::vertex_buffer(),
+            false,
+            vertices.iter().cloned(),
+        )?;
+
+        // the active block as a filled 20x20 square at the bottom, made of
+        // horizontal lines since the ui pipeline only draws lines
+        let color = self.active_block.color();
+        let instances = (0..20).map(|i| Instance {
+            color: [color[0], color[1], color[2], 1.],
+            rotation: [0., 0., PI / 2.],
+            translation: [
+                img_size[0] as f32 / 2.,
+                img_size[1] as f32 - 30. + i as f32,
+                0.,
+            ],
+            ..Default::default()
+        });
+
+        let instance_buffer = CpuAccessibleBuffer::from_iter(
+            self.gpu.queue.device().clone(),
+            BufferUsage::vertex_buffer(),
+            false,
+            instances,
+        )?;
+
+        builder
+            .bind_vertex_buffers(0, (vertex_buffer.clone(), instance_buffer.clone()))
+            .bind_pipeline_graphics(self.gpu.ui_graphics_pipeline.clone())
+            .push_constants(
+                self.gpu.ui_graphics_pipeline.layout().clone(),
+                0,
+                ui_vs::ty::PushConstants {
+                    display_size: img_size,
+                },
+            )
+            .draw(
+                vertex_buffer.len() as u32,
+                instance_buffer.len() as u32,
+                0,
+                0,
+            )
+            .unwrap();
+
+        let crosshair = self.crosshair_lines(img_size);
+        if !crosshair.is_empty() {
+            self.draw_ui_lines(builder, crosshair, self.crosshair_style.color)?;
+        }
+
+        if self.show_debug_overlay {
+            if let Some(frame_time) = self.average_frame_time() {
+                let frame_time = frame_time.as_secs_f32();
+                let mut lines =
+                    text::text_lines(&format!("FPS {:.1}", 1. / frame_time), [10., 10.], 16.);
+                // frame time in milliseconds
+                lines.extend(text::text_lines(
+                    &format!("t {:.2}", frame_time * 1000.),
+                    [10., 36.],
+                    16.,
+                ));
+                // visible chunks and instances
+                lines.extend(text::text_lines(
+                    &format!(
+                        "C {} {}",
+                        self.render_stats.visible_chunks, self.render_stats.visible_instances
+                    ),
+                    [10., 62.],
+                    16.,
+                ));
+                // compass heading and the chunk the camera is in
+                let camera = self.player.camera();
+                let heading = camera.heading();
+                let (chunk_x, chunk_z) = chunk_id(camera.position().map(|a| a.floor() as i32));
+                lines.extend(text::text_lines(
+                    &format!(
+                        "{:.0} {} C {} {}",
+                        heading,
+                        compass_direction(heading),
+                        chunk_x,
+                        chunk_z
+                    ),
+                    [10., 88.],
+                    16.,
+                ));
+                if let Some((cube, face, block)) = self.looking_at_block() {
+                    lines.extend(text::text_lines(
+                        &format!(
+                            "{} {} {} {} {}",
+                            cube.x,
+                            cube.y,
+                            cube.z,
+                            face.axis_name(),
+                            block.name()
+                        ),
+                        [10., 114.],
+                        16.,
+                    ));
+                }
+
+                self.draw_ui_lines(builder, lines, [1., 1., 1., 1.])?;
+            }
+        }
+
+        if self.show_minimap {
+            let position = self.player.camera().position();
+            let direction = self.player.camera().direction();
+
+            let chunk_lines = self.minimap.chunk_lines(
+                img_size,
+                Point2::new(position.x, position.z),
+                self.world.loaded_chunk_ids(),
+            );
+            self.draw_ui_lines(builder, chunk_lines, [0.8, 0.8, 0.8, 1.])?;
+
+            let player_lines = self
+                .minimap
+                .player_lines(img_size, Vector2::new(direction.x, direction.z).normalize());
+            self.draw_ui_lines(builder, player_lines, [1., 0.2, 0.2, 1.])?;
+        }
+
+        Ok(())
+    }
+
+    /// The lines of the crosshair in the middle of the screen, in pixels
+    fn crosshair_lines(&self, img_size: [u32; 2]) -> Vec<[f32; 2]> {
+        let [x, y] = [img_size[0] as f32 / 2., img_size[1] as f32 / 2.];
+        let size = self.crosshair_style.size;
+        let half = size / 2.;
+
+        match self.crosshair_style.shape {
+            CrosshairShape::Cross => {
+                vec![[x, y - half], [x, y + half], [x - half, y], [x + half, y]]
+            }
+            // filled with horizontal lines, one for every pixel row
+            CrosshairShape::Dot => (0..size.ceil().max(1.) as u32)
+                .flat_map(|i| {
+                    let row = y - half + i as f32 + 0.5;
+                    [[x - half, row], [x + half, row]]
+                })
+                .collect(),
+            CrosshairShape::None => Vec::new(),
+        }
+    }
+
+    /// Draws `lines` (pairs of points in pixels) with `color`, the ui
+    /// pipeline must be bound
+    fn draw_ui_lines(
+        &self,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+        lines: Vec<[f32; 2]>,
+        color: [f32; 4],
+    ) -> Result<(), DeviceMemoryAllocationError> {
+        let vertex_buffer = self
+            .gpu
+            .vertex_buffer_pool
+            .chunk(lines.into_iter().map(|[x, y]| Vertex {
+                pos: [x, y, 0.],
+                normal: [0., 0., 0.],
+                tex_coord: [0., 0.],
+                face: NO_FACE,
+            }))?;
+        // the lines are already in display coordinates
+        let instance_buffer = self.gpu.instance_buffer_pool.chunk([Instance {
+            color,
+            ..Default::default()
+        }])?;
+
+        builder
+            .bind_vertex_buffers(0, (vertex_buffer.clone(), instance_buffer.clone()))
+            .draw(
+                vertex_buffer.len() as u32,
+                instance_buffer.len() as u32,
+                0,
+                0,
+            )
+            .unwrap();
+
+        Ok(())
+    }
+}
+
+impl Engine {
+    /// Renders a single frame from `camera` into an off-screen image of `size`,
+    /// and returns its pixels in RGBA8.
+    ///
+    /// All the chunks around the camera are loaded and meshed before rendering,
+    /// so the result doesn't depend on timing.
+    pub fn render_to_image(
+        &mut self,
+        size: [u32; 2],
+        mut camera: Camera,
+    ) -> Result<Vec<u8>, UnsupportedFormat> {
+        let device = self.gpu.queue.device().clone();
+
+        let image = AttachmentImage::with_usage(
+            device.clone(),
+            size,
+            self.gpu.image_format,
+            ImageUsage {
+                color_attachment: true,
+                transfer_source: true,
+                // the scene is blitted into it
+                transfer_destination: true,
+                ..ImageUsage::none()
+            },
+        )
+        .unwrap();
+
+        camera.set_depth_mode(self.gpu.depth_mode);
+        let previous_camera = std::mem::replace(self.player.camera_mut(), camera);
+
+        self.world
+            .load_all_chunks_around(*self.player.camera().position(), self.load_radius);
+        self.world.update_meshes();
+        self.world.wait_for_meshes();
+
+        let future = self.render(image.clone(), sync::now(device.clone()));
+
+        *self.player.camera_mut() = previous_camera;
+
+        let buffer = CpuAccessibleBuffer::from_iter(
+            device.clone(),
+            BufferUsage::transfer_destination(),
+            false,
+            (0..size[0] * size[1] * 4).map(|_| 0u8),
+        )
+        .unwrap();
+
+        let mut builder = AutoCommandBufferBuilder::primary(
+            device,
+            self.gpu.queue.family(),
+            CommandBufferUsage::OneTimeSubmit,
+        )
+        .unwrap();
+        builder.copy_image_to_buffer(image, buffer.clone()).unwrap();
+
+        future
+            .then_execute(self.gpu.queue.clone(), builder.build().unwrap())
+            .unwrap()
+            .then_signal_fence_and_flush()
+            .unwrap()
+            .wait(None)
+            .unwrap();
+
+        let pixels = buffer.read().unwrap().to_vec();
+        screenshot::to_rgba8(self.gpu.image_format, pixels)
+    }
+}
+
+impl Engine {
+    /// Saves the screenshot copied in a previous frame, if the gpu finished
+    /// writing it
+    fn save_pending_screenshot(&mut self) {
+        let pixels = match &self.pending_screenshot {
+            // fails if the gpu is still using the buffer
+            Some(screenshot) => match screenshot.buffer.read() {
+                Ok(pixels) => pixels.to_vec(),
+                Err(_) => return,
+            },
+            None => return,
+        };
+        let size = self.pending_screenshot.take().unwrap().size;
+        let pixels = match screenshot::to_rgba8(self.gpu.image_format, pixels) {
+            Ok(pixels) => pixels,
+            Err(e) => {
+                eprintln!("WARN: skipping the screenshot, {e}");
+                return;
+            }
+        };
+
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis();
+        let path = PathBuf::from(format!("screenshot-{}.png", timestamp));
+
+        // encoding can take a while, don't block the frame
+        std::thread::spawn(move || match screenshot::save_png(&path, size, &pixels) {
+            Ok(()) => println!("Saved screenshot to {}", path.display()),
+            Err(e) => eprintln!("Failed to save screenshot: {}", e),
+        });
+    }
+
+    /// place the active block at the current looking block
+    fn place_at_looking_at(&mut self) {
+        if let Some(cube) = self.looking_at_cube_in_reach() {
+            // place on the face we are looking at
+            let new_cube = cube.cube + cube.face.normal();
+
+            self.edit_block(new_cube, Some((self.active_block, self.active_shape)));
+        }
+    }
+
+    fn remove_looking_at(&mut self) {
+        if let Some(cube) = self.looking_at_cube_in_reach() {
+            let pos = cube.cube;
+            if let Ok(Some(removed)) = self.world.remove_cube(pos) {
+                self.history.record(Edit {
+                    pos,
+                    previous: Some((removed.block(), removed.shape())),
+                    new: None,
+                });
+                self.particles.spawn_block_break(pos, removed.block());
+            }
+        }
+    }
+
+    /// The position, face and type of the highlighted block
+    pub fn looking_at_block(&self) -> Option<(Point3<i32>, BlockFace, BlockType)> {
+        let looking_at = self.looking_at_cube.as_ref()?;
+        let block = self.world.block_at(looking_at.cube)?;
+        Some((looking_at.cube, looking_at.face, block))
+    }
+
+    /// The highlighted cube, if it's close enough to be placed on or removed
+    fn looking_at_cube_in_reach(&self) -> Option<&CubeLookAt> {
+        self.looking_at_cube
+            .as_ref()
+            .filter(|cube| cube.t <= self.reach_distance)
+    }
+
+    /// Changes the block at `pos` and records it in the history, nothing
+    /// happens if `pos` is above or below the world
+    fn edit_block(&mut self, pos: Point3<i32>, block: Option<(BlockType, BlockShape)>) {
+        let previous = self.world.block_at(pos).zip(self.world.shape_at(pos));
+        if self.set_block(pos, block).is_ok() {
+            self.history.record(Edit {
+                pos,
+                previous,
+                new: block,
+            });
+        }
+    }
+
+    fn set_block(
+        &mut self,
+        pos: Point3<i32>,
+        block: Option<(BlockType, BlockShape)>,
+    ) -> Result<(), OutOfBounds> {
+        match block {
+            Some((block, shape)) => self.world.push_cube_with_shape(pos, block, shape),
+            None => self.world.remove_cube(pos).map(|_| ()),
+        }
+    }
+
+    fn undo(&mut self) {
+        if let Some(edit) = self.history.undo() {
+            // the edits were in bounds when they were recorded
+            let _ = self.set_block(edit.pos, edit.previous);
+        }
+    }
+
+    fn redo(&mut self) {
+        if let Some(edit) = self.history.redo() {
+            let _ = self.set_block(edit.pos, edit.new);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn attachments_are_recreated_when_anything_changes() {
+        let desc = AttachmentDesc {
+            size: [800, 600],
+            format: DEPTH_FORMAT,
+            samples: SampleCount::Sample1,
+        };
+
+        assert!(needs_recreation(None, desc));
+        assert!(!needs_recreation(Some(desc), desc));
+        for changed in [
+            AttachmentDesc {
+                size: [800, 601],
+                ..desc
+            },
+            AttachmentDesc {
+                format: Format::D16_UNORM,
+                ..desc
+            },
+            AttachmentDesc {
+                samples: SampleCount::Sample4,
+                ..desc
+            },
+        ] {
+            assert!(needs_recreation(Some(desc), changed));
+        }
+    }
+
+    #[test]
+    fn velocity_eases_towards_the_input() {
+        let delta = Duration::from_millis(100);
+        let target = Vector3::new(0., 0., 10.);
+        let stopped = Vector3::new(0., 0., 0.);
+
+        // instant without acceleration
+        assert_eq!(eased_velocity(stopped, target, 0., 0., delta), target);
+
+        let mut velocity = stopped;
+        for expected in [4., 8., 10., 10.] {
+            velocity = eased_velocity(velocity, target, 40., 20., delta);
+            assert!((velocity.z - expected).abs() < 1e-5);
+        }
+        // friction slows down without input, and stops exactly at zero
+        for expected in [8., 6., 4., 2., 0., 0.] {
+            velocity = eased_velocity(velocity, stopped, 40., 20., delta);
+            assert!((velocity.z - expected).abs() < 1e-5);
+        }
+        assert_eq!(velocity, stopped);
+    }
+
+    #[test]
+    fn meshes_that_fail_to_allocate_are_skipped() {
+        // a pool that can't give a buffer for more instances than this
+        const POOL_CAPACITY: usize = 1 << 16;
+        let small = vec![Instance::default(); 100];
+        let large = vec![Instance::default(); POOL_CAPACITY * 16];
+
+        let mut drawn = 0;
+        let error = draw_each([&small, &large, &small], |instances| {
+            if instances.len() > POOL_CAPACITY {
+                return Err(DeviceMemoryAllocationError::OomError(
+                    vulkano::OomError::OutOfDeviceMemory,
+                ));
+            }
+            drawn += instances.len();
+            Ok(())
+        });
+
+        assert!(matches!(
+            error,
+            Some(DeviceMemoryAllocationError::OomError(_))
+        ));
+        // the meshes after the large one are still drawn
+        assert_eq!(drawn, small.len() * 2);
+        assert!(draw_each([&small], |_| Ok(())).is_none());
+    }
+
+    #[test]
+    fn debug_snapshot_has_the_world_and_camera_state() {
+        let mut world = World::empty();
+        world.create_chunk(0, 2, 0);
+        world
+            .push_cube(Point3::new(-1, 5, CHUNK_SIZE), BlockType::Stone)
+            .unwrap();
+        let position = Point3::new(-0.5, 10., 3.);
+        let orientation = (Deg(30.), Deg(-20.));
+        let looking_at = CubeLookAt {
+            cube: Point3::new(2, 1, 3),
+            direction: Vector3::new(0, 1, 0),
+            face: BlockFace::Top,
+            t: 8.5,
+        };
+        let render_stats = RenderStats {
+            chunks: 2,
+            visible_chunks: 1,
+            instances: 40,
+            visible_instances: 12,
+        };
+
+        let snapshot = DebugSnapshot::new(
+            &world,
+            position,
+            orientation,
+            Deg(70.),
+            Some(&looking_at),
+            render_stats,
+        );
+        assert_eq!(snapshot.chunk_count, 2);
+        assert_eq!(
+            snapshot.block_count,
+            (CHUNK_SIZE * CHUNK_SIZE * 2) as usize + 1
+        );
+        assert!(snapshot.estimated_memory > 0);
+        assert_eq!(snapshot.camera_position, Point3::new(-0.5, 10., 3.));
+        assert_eq!(snapshot.camera_yaw, Deg(30.));
+        assert_eq!(snapshot.camera_pitch, Deg(-20.));
+        assert_eq!(snapshot.camera_fov, Deg(70.));
+        assert_eq!(snapshot.current_chunk, chunk_id(Point3::new(-1, 10, 3)));
+        assert_eq!(snapshot.looking_at, Some(Point3::new(2, 1, 3)));
+        assert_eq!(snapshot.looking_at_distance, Some(8.5));
+        assert_eq!(snapshot.render_stats.visible_instances, 12);
+
+        let nothing =
+            DebugSnapshot::new(&world, position, orientation, Deg(70.), None, render_stats);
+        assert_eq!(nothing.looking_at, None);
+        assert_eq!(nothing.looking_at_distance, None);
     }
 }
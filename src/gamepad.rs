@@ -0,0 +1,93 @@
+//! Controller input with `gilrs`, only built with the `gamepad` feature.
+//!
+//! The buttons trigger the same [`Action`]s as the keyboard, and the sticks
+//! are read every update so their analog values can be used for movement.
+
+use cgmath::Vector2;
+use gilrs::{Axis, Button, EventType, GamepadId, Gilrs};
+
+use crate::input::Action;
+
+/// Stick values below this are ignored, as sticks rarely rest at exactly `0`
+const DEAD_ZONE: f32 = 0.15;
+
+/// The action triggered by a controller button, if any
+const fn button_action(button: Button) -> Option<Action> {
+    match button {
+        Button::South => Some(Action::MoveUp),
+        Button::East => Some(Action::MoveDown),
+        Button::LeftThumb => Some(Action::Sprint),
+        Button::RightTrigger2 => Some(Action::Remove),
+        Button::LeftTrigger2 => Some(Action::Place),
+        _ => None,
+    }
+}
+
+pub(crate) struct GamepadInput {
+    gilrs: Gilrs,
+    // the sticks are read from the last controller that was used
+    active: Option<GamepadId>,
+}
+
+impl GamepadInput {
+    /// Starts listening to controllers, `None` if the platform isn't supported
+    pub fn new() -> Option<Self> {
+        match Gilrs::new() {
+            Ok(gilrs) => Some(Self {
+                gilrs,
+                active: None,
+            }),
+            Err(e) => {
+                eprintln!("Failed to initialize gamepad input: {e}");
+                None
+            }
+        }
+    }
+
+    /// The actions pressed (`true`) and released (`false`) since the last call
+    pub fn poll_actions(&mut self) -> Vec<(Action, bool)> {
+        let mut actions = Vec::new();
+
+        while let Some(event) = self.gilrs.next_event() {
+            self.active = Some(event.id);
+
+            match event.event {
+                EventType::ButtonPressed(button, _) => {
+                    actions.extend(button_action(button).map(|action| (action, true)));
+                }
+                EventType::ButtonReleased(button, _) => {
+                    actions.extend(button_action(button).map(|action| (action, false)));
+                }
+                EventType::Disconnected if self.active == Some(event.id) => {
+                    self.active = None;
+                }
+                _ => {}
+            }
+        }
+
+        actions
+    }
+
+    /// The left stick, `x` is to the right and `y` is forward
+    pub fn movement(&self) -> Vector2<f32> {
+        self.stick(Axis::LeftStickX, Axis::LeftStickY)
+    }
+
+    /// The right stick, `x` is to the right and `y` is up
+    pub fn look(&self) -> Vector2<f32> {
+        self.stick(Axis::RightStickX, Axis::RightStickY)
+    }
+
+    fn stick(&self, x: Axis, y: Axis) -> Vector2<f32> {
+        let gamepad = match self.active.and_then(|id| self.gilrs.connected_gamepad(id)) {
+            Some(gamepad) => gamepad,
+            None => return Vector2::new(0., 0.),
+        };
+
+        let apply_dead_zone = |value: f32| if value.abs() < DEAD_ZONE { 0. } else { value };
+        Vector2::new(
+            apply_dead_zone(gamepad.value(x)),
+            apply_dead_zone(gamepad.value(y)),
+        )
+    }
+}
@@ -0,0 +1,130 @@
+//! Terrain heights read from a grayscale image, used instead of noise by
+//! [`World::generate_from_heightmap`](crate::world::World::generate_from_heightmap).
+
+use std::{fs::File, io, io::BufReader, path::Path};
+
+/// Brightness of every pixel of an image, from `0` (black) to `255` (white)
+pub(crate) struct Heightmap {
+    width: u32,
+    height: u32,
+    values: Vec<u8>,
+}
+
+impl Heightmap {
+    /// `values` are in rows, `width * height` of them
+    pub fn from_gray(width: u32, height: u32, values: Vec<u8>) -> Self {
+        assert_eq!(values.len(), (width * height) as usize);
+        assert!(width > 0 && height > 0, "the heightmap can't be empty");
+
+        Self {
+            width,
+            height,
+            values,
+        }
+    }
+
+    /// Loads a PNG image, colored images are converted to grayscale
+    pub fn load_from_path(path: &Path) -> io::Result<Self> {
+        let mut decoder = png::Decoder::new(BufReader::new(File::open(path)?));
+        // palettes, low and 16 bit depths all become 8 bits per channel
+        decoder.set_transformations(png::Transformations::normalize_to_color8());
+        let mut reader = decoder.read_info().map_err(io::Error::other)?;
+
+        let mut data = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut data).map_err(io::Error::other)?;
+        if info.width == 0 || info.height == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "the heightmap image is empty",
+            ));
+        }
+
+        let channels = match info.color_type {
+            png::ColorType::Grayscale | png::ColorType::Indexed => 1,
+            png::ColorType::GrayscaleAlpha => 2,
+            png::ColorType::Rgb => 3,
+            png::ColorType::Rgba => 4,
+        };
+        let values = data
+            .chunks_exact(info.line_size)
+            .take(info.height as usize)
+            .flat_map(|line| line.chunks_exact(channels).take(info.width as usize))
+            .map(|pixel| match channels {
+                // the alpha is ignored
+                1 | 2 => pixel[0],
+                _ => ((pixel[0] as u32 + pixel[1] as u32 + pixel[2] as u32) / 3) as u8,
+            })
+            .collect();
+
+        Ok(Self::from_gray(info.width, info.height, values))
+    }
+
+    /// The brightness of the pixel at `(x, y)` in `[0, 1]`, the image is
+    /// repeated in all directions, so any position is valid
+    pub fn sample(&self, x: i32, y: i32) -> f32 {
+        let x = x.rem_euclid(self.width as i32) as u32;
+        let y = y.rem_euclid(self.height as i32) as u32;
+
+        self.values[(x + y * self.width) as usize] as f32 / 255.
+    }
+}
+
+/// A [`Heightmap`] placed in the world, with its first pixel at `(0, 0)`
+pub(crate) struct HeightmapTerrain {
+    pub heightmap: Heightmap,
+    /// the width of a pixel in blocks
+    pub scale: f32,
+    /// the height of the columns of white pixels, black pixels are a single block
+    pub max_height: i32,
+}
+
+impl HeightmapTerrain {
+    /// The number of blocks in the column at `(x, z)`
+    pub fn height_at(&self, x: i32, z: i32) -> i32 {
+        let value = self.heightmap.sample(
+            (x as f32 / self.scale).floor() as i32,
+            (z as f32 / self.scale).floor() as i32,
+        );
+
+        ((value * self.max_height as f32).round() as i32).max(1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn from_gray_samples_the_rows_and_repeats() {
+        let heightmap = Heightmap::from_gray(3, 2, vec![0, 51, 255, 102, 153, 204]);
+
+        assert_eq!(heightmap.sample(0, 0), 0.);
+        assert_eq!(heightmap.sample(2, 0), 1.);
+        assert_eq!(heightmap.sample(1, 1), 0.6);
+        // the image is repeated in all directions
+        assert_eq!(heightmap.sample(3, 0), heightmap.sample(0, 0));
+        assert_eq!(heightmap.sample(-1, -1), heightmap.sample(2, 1));
+        assert_eq!(heightmap.sample(4, 3), heightmap.sample(1, 1));
+    }
+
+    #[test]
+    #[should_panic]
+    fn from_gray_rejects_the_wrong_number_of_values() {
+        Heightmap::from_gray(2, 2, vec![0; 3]);
+    }
+
+    #[test]
+    fn terrain_height_follows_the_pixels() {
+        let terrain = HeightmapTerrain {
+            heightmap: Heightmap::from_gray(2, 1, vec![0, 255]),
+            scale: 4.,
+            max_height: 30,
+        };
+
+        // black pixels are a single block
+        assert_eq!(terrain.height_at(0, 0), 1);
+        assert_eq!(terrain.height_at(3, 0), 1);
+        assert_eq!(terrain.height_at(4, 0), 30);
+        assert_eq!(terrain.height_at(-1, 5), 30);
+    }
+}
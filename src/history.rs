@@ -0,0 +1,62 @@
+use std::collections::VecDeque;
+
+use cgmath::Point3;
+
+use crate::block::{BlockShape, BlockType};
+
+/// A single change of a block in the world, `None` is an empty cell
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Edit {
+    pub pos: Point3<i32>,
+    pub previous: Option<(BlockType, BlockShape)>,
+    pub new: Option<(BlockType, BlockShape)>,
+}
+
+/// The edits done by the player, which can be undone and redone.
+///
+/// Only the last `limit` edits are kept.
+pub(crate) struct EditHistory {
+    undo: VecDeque<Edit>,
+    redo: Vec<Edit>,
+    limit: usize,
+}
+
+impl EditHistory {
+    pub fn new(limit: usize) -> Self {
+        Self {
+            undo: VecDeque::with_capacity(limit),
+            redo: Vec::new(),
+            limit,
+        }
+    }
+
+    /// Records a new edit, the undone edits can't be redone after this
+    pub fn record(&mut self, edit: Edit) {
+        self.redo.clear();
+
+        if self.undo.len() == self.limit {
+            self.undo.pop_front();
+        }
+        self.undo.push_back(edit);
+    }
+
+    /// Returns the edit to undo, the caller should apply `previous` to the world
+    pub fn undo(&mut self) -> Option<Edit> {
+        let edit = self.undo.pop_back()?;
+        self.redo.push(edit);
+        Some(edit)
+    }
+
+    /// Returns the edit to redo, the caller should apply `new` to the world
+    pub fn redo(&mut self) -> Option<Edit> {
+        let edit = self.redo.pop()?;
+        self.undo.push_back(edit);
+        Some(edit)
+    }
+
+    /// Forgets all the edits
+    pub fn clear(&mut self) {
+        self.undo.clear();
+        self.redo.clear();
+    }
+}
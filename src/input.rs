@@ -0,0 +1,161 @@
+use std::collections::HashMap;
+
+use cgmath::Deg;
+use winit::event::VirtualKeyCode;
+
+/// Controls how mouse movement is translated into camera rotation
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct CameraControlConfig {
+    /// degrees of yaw per pixel of horizontal mouse movement
+    pub sensitivity_x: f32,
+    /// degrees of pitch per pixel of vertical mouse movement
+    pub sensitivity_y: f32,
+    /// if `true`, moving the mouse up will make the camera look down
+    pub invert_y: bool,
+}
+
+impl Default for CameraControlConfig {
+    fn default() -> Self {
+        Self {
+            sensitivity_x: 0.1,
+            sensitivity_y: 0.1,
+            invert_y: false,
+        }
+    }
+}
+
+impl CameraControlConfig {
+    /// Converts a mouse movement in display pixels into `(pitch, yaw)` deltas
+    pub fn rotation_deltas(&self, dx: f32, dy: f32) -> (Deg<f32>, Deg<f32>) {
+        // movement in x direction in display moves the camera
+        // around the y axis (yaw)
+        let yaw = dx * self.sensitivity_x;
+        // movement in y direction in display moves the camera
+        // around the x axis (pitch)
+        //
+        // because the `y` axis is inverted, we have to negate
+        // the angle here (unless the user wants it inverted)
+        let pitch = if self.invert_y { dy } else { -dy } * self.sensitivity_y;
+
+        (Deg(pitch), Deg(yaw))
+    }
+}
+
+/// Actions that can be bound to a key, or triggered by a controller button
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub(crate) enum Action {
+    MoveForward,
+    MoveBack,
+    StrafeLeft,
+    StrafeRight,
+    MoveUp,
+    /// descends while flying, and crouches while walking with physics
+    MoveDown,
+    Sprint,
+    /// places the active block, on the middle mouse button by default
+    Place,
+    /// removes blocks while held, on the left mouse button by default
+    Remove,
+}
+
+/// Maps actions to the keys that trigger them, a key can only be bound
+/// to a single action
+pub(crate) struct KeyBindings {
+    bindings: HashMap<Action, VirtualKeyCode>,
+}
+
+impl Default for KeyBindings {
+    fn default() -> Self {
+        Self {
+            bindings: HashMap::from([
+                (Action::MoveForward, VirtualKeyCode::W),
+                (Action::MoveBack, VirtualKeyCode::S),
+                (Action::StrafeLeft, VirtualKeyCode::A),
+                (Action::StrafeRight, VirtualKeyCode::D),
+                (Action::MoveUp, VirtualKeyCode::Space),
+                (Action::MoveDown, VirtualKeyCode::LShift),
+                (Action::Sprint, VirtualKeyCode::LControl),
+            ]),
+        }
+    }
+}
+
+impl KeyBindings {
+    /// Returns the action bound to `key` if any
+    pub fn action(&self, key: VirtualKeyCode) -> Option<Action> {
+        self.bindings
+            .iter()
+            .find(|(_, &k)| k == key)
+            .map(|(&action, _)| action)
+    }
+
+    #[allow(dead_code)]
+    pub fn key(&self, action: Action) -> Option<VirtualKeyCode> {
+        self.bindings.get(&action).copied()
+    }
+
+    /// Binds `key` to `action`, if `key` was bound to another action,
+    /// that action will be left unbound (the most recent binding wins)
+    pub fn rebind(&mut self, action: Action, key: VirtualKeyCode) {
+        self.bindings.retain(|_, &mut k| k != key);
+        self.bindings.insert(action, key);
+    }
+}
+
+/// Returns the number on the key if it's one of the number keys `1` to `9`
+pub(crate) const fn number_key(key: VirtualKeyCode) -> Option<usize> {
+    match key {
+        VirtualKeyCode::Key1 => Some(1),
+        VirtualKeyCode::Key2 => Some(2),
+        VirtualKeyCode::Key3 => Some(3),
+        VirtualKeyCode::Key4 => Some(4),
+        VirtualKeyCode::Key5 => Some(5),
+        VirtualKeyCode::Key6 => Some(6),
+        VirtualKeyCode::Key7 => Some(7),
+        VirtualKeyCode::Key8 => Some(8),
+        VirtualKeyCode::Key9 => Some(9),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rotation_deltas_follow_the_sensitivity_and_invert_y() {
+        let mut config = CameraControlConfig {
+            sensitivity_x: 0.2,
+            sensitivity_y: 0.5,
+            invert_y: false,
+        };
+        // moving the mouse down looks down
+        assert_eq!(config.rotation_deltas(10., 4.), (Deg(-2.), Deg(2.)));
+
+        config.invert_y = true;
+        assert_eq!(config.rotation_deltas(10., 4.), (Deg(2.), Deg(2.)));
+        assert_eq!(config.rotation_deltas(0., 0.), (Deg(0.), Deg(0.)));
+    }
+
+    #[test]
+    fn rebinding_a_key_unbinds_its_old_action() {
+        let mut bindings = KeyBindings::default();
+        assert_eq!(
+            bindings.action(VirtualKeyCode::W),
+            Some(Action::MoveForward)
+        );
+        assert_eq!(bindings.action(VirtualKeyCode::Up), None);
+
+        bindings.rebind(Action::MoveForward, VirtualKeyCode::Up);
+        assert_eq!(
+            bindings.action(VirtualKeyCode::Up),
+            Some(Action::MoveForward)
+        );
+        assert_eq!(bindings.action(VirtualKeyCode::W), None);
+
+        // the most recent binding of a key wins
+        bindings.rebind(Action::Sprint, VirtualKeyCode::Up);
+        assert_eq!(bindings.action(VirtualKeyCode::Up), Some(Action::Sprint));
+        assert_eq!(bindings.key(Action::MoveForward), None);
+    }
+}
@@ -0,0 +1,16 @@
+//! Point lights, lighting up the cubes around them in addition to the sun.
+
+use cgmath::Point3;
+
+/// How many point lights can be active at the same time, must match the
+/// size of the arrays in `cubes.frag.glsl`
+pub(crate) const MAX_POINT_LIGHTS: usize = 8;
+
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct PointLight {
+    pub position: Point3<f32>,
+    /// Can be above `1` for brighter lights
+    pub color: [f32; 3],
+    /// The light fades out completely at this distance
+    pub radius: f32,
+}
@@ -1,32 +1,335 @@
+mod atlas;
+mod block;
 mod camera;
+mod camera_path;
 mod display;
 mod engine;
+#[cfg(feature = "gamepad")]
+mod gamepad;
+mod heightmap;
+mod history;
+mod input;
+mod light;
+mod math;
+mod minimap;
 mod object;
+mod particles;
+mod player;
+mod schematic;
+mod screenshot;
+mod sky;
+mod terrain;
+mod text;
+mod worker;
 mod world;
 
-use std::time::Instant;
+use std::{
+    path::Path,
+    time::{Duration, Instant},
+};
 
-use display::Display;
-use engine::Engine;
-use vulkano::image::ImageUsage;
+use block::BlockType;
+use camera::{Camera, DepthMode};
+use camera_path::CameraPath;
+use cgmath::{Deg, Point3};
+use display::{Display, DisplayBuilder};
+use engine::{Engine, EngineConfig, OutlineStyle};
+use minimap::Corner;
+use sky::Sky;
+use vulkano::{format::Format, image::ImageUsage, swapchain::PresentMode};
 use winit::{
-    event::{Event, WindowEvent},
+    event::{ElementState, Event, KeyboardInput, VirtualKeyCode, WindowEvent},
     event_loop::{ControlFlow, EventLoop},
 };
 
+/// How often frames are drawn while the window is not focused
+#[derive(Debug, Clone, Copy)]
+enum UnfocusedFrameRate {
+    /// at most this many frames per second
+    Limited(f32),
+    /// no frames are drawn until the window is focused again
+    Paused,
+}
+
+/// Parses comma separated numbers like `1,0.5,0`, `None` unless there are
+/// exactly `N` of them
+fn parse_floats<const N: usize>(s: &str) -> Option<[f32; N]> {
+    let values = s
+        .split(',')
+        .map(|value| value.trim().parse().ok())
+        .collect::<Option<Vec<f32>>>()?;
+    values.try_into().ok()
+}
+
+/// Renders a single frame without a window and saves it to `path`
+fn render_headless(path: &Path) {
+    const SIZE: [u32; 2] = [1280, 720];
+
+    let mut engine = Engine::new(
+        display::headless_queue(),
+        Format::R8G8B8A8_SRGB,
+        EngineConfig::default(),
+    );
+
+    let camera = Camera::new(Deg(45.), 0.0, 0.1, 100., [0., 125., -25.].into());
+    match engine.render_to_image(SIZE, camera) {
+        Ok(pixels) => {
+            screenshot::save_png(path, SIZE, &pixels).unwrap();
+            println!("Saved frame to {}", path.display());
+        }
+        Err(e) => eprintln!("Failed to render frame: {e}"),
+    }
+}
+
+/// Renders the next frame to the window, `last_frame` is when the previous
+/// frame finished
+fn draw_frame(display: &mut Display, engine: &mut Engine, last_frame: &mut Instant) {
+    match display.begin_frame() {
+        Ok(future) => {
+            let current_image = display.current_image();
+
+            let future = engine.render(current_image, future);
+
+            display.end_frame(future);
+
+            engine.record_frame_time(last_frame.elapsed());
+            *last_frame = Instant::now();
+        }
+        Err(e) => eprintln!("Error on begin frame: {e}"),
+    }
+}
+
 fn main() {
+    // `--headless <output.png>` renders a single frame without a window
+    let args = std::env::args().collect::<Vec<_>>();
+    if let Some(i) = args.iter().position(|arg| arg == "--headless") {
+        let path = args.get(i + 1).map(String::as_str).unwrap_or("frame.png");
+        render_headless(Path::new(path));
+        return;
+    }
+
     let event_loop = EventLoop::new();
-    let mut display = Display::new(
-        &event_loop,
-        ImageUsage {
-            color_attachment: true,
-            transfer_destination: true,
-            ..ImageUsage::none()
-        },
-    );
-    let mut engine = Engine::new(display.queue(), display.swapchain_image_format());
+    let mut display_builder = DisplayBuilder::new(ImageUsage {
+        color_attachment: true,
+        transfer_destination: true,
+        // for screenshots
+        transfer_source: true,
+        ..ImageUsage::none()
+    });
+    // `--device <name>` or `--device-index <index>` picks the GPU to use, the
+    // available ones are printed on startup
+    if let Some(i) = args.iter().position(|arg| arg == "--device") {
+        if let Some(name) = args.get(i + 1) {
+            display_builder = display_builder.prefer_device_name(name);
+        }
+    }
+    if let Some(i) = args.iter().position(|arg| arg == "--device-index") {
+        if let Some(index) = args.get(i + 1).and_then(|s| s.parse().ok()) {
+            display_builder = display_builder.device_index(index);
+        }
+    }
+    let mut display = display_builder.build(&event_loop);
+    let mut config = EngineConfig::default();
+    // `--msaa <samples>` enables anti-aliasing
+    if let Some(i) = args.iter().position(|arg| arg == "--msaa") {
+        if let Some(samples) = args.get(i + 1).and_then(|s| s.parse().ok()) {
+            config.samples = samples;
+        }
+    }
+    // `--standard-depth` stores near as `0` and far as `1`, for comparing
+    // against other renderers
+    if args.iter().any(|arg| arg == "--standard-depth") {
+        config.depth_mode = DepthMode::Standard;
+    }
+    let mut engine = Engine::new(display.queue(), display.swapchain_image_format(), config);
+    // `--lod-distance <blocks>` draws only the surface of the chunks further
+    // than this from the camera
+    if let Some(i) = args.iter().position(|arg| arg == "--lod-distance") {
+        if let Some(distance) = args.get(i + 1).and_then(|s| s.parse().ok()) {
+            engine.set_lod_distance(Some(distance));
+        }
+    }
+    // `--render-scale <scale>` renders the world below the window resolution
+    if let Some(i) = args.iter().position(|arg| arg == "--render-scale") {
+        if let Some(scale) = args.get(i + 1).and_then(|s| s.parse().ok()) {
+            engine.set_render_scale(scale);
+        }
+    }
+    // `--outline-color <r,g,b,a>` and `--outline-scale <scale>` change the
+    // outline of the block we are looking at
+    let mut outline = OutlineStyle::default();
+    if let Some(i) = args.iter().position(|arg| arg == "--outline-color") {
+        if let Some(color) = args.get(i + 1).and_then(|s| parse_floats(s)) {
+            outline.color = color;
+        }
+    }
+    if let Some(i) = args.iter().position(|arg| arg == "--outline-scale") {
+        if let Some(scale) = args.get(i + 1).and_then(|s| s.parse().ok()) {
+            outline.scale = scale;
+        }
+    }
+    engine.set_outline_style(outline);
+    // `--fog <density,start>` adds fog in the sky color, starting at `start`
+    // blocks from the camera
+    if let Some(i) = args.iter().position(|arg| arg == "--fog") {
+        if let Some([density, start]) = args.get(i + 1).and_then(|s| parse_floats(s)) {
+            engine.set_fog(density, start);
+        }
+    }
+    // `--face-shading` darkens the sides and bottom of the blocks
+    if args.iter().any(|arg| arg == "--face-shading") {
+        engine.set_face_shading(true);
+    }
+    // `--ambient-occlusion` darkens the corners of faces next to other blocks
+    if args.iter().any(|arg| arg == "--ambient-occlusion") {
+        engine.set_ambient_occlusion(true);
+    }
+    // `--move-speed <speed,sprint,crouch>` sets the speed in blocks per second,
+    // and how it's multiplied while sprinting and crouching
+    if let Some(i) = args.iter().position(|arg| arg == "--move-speed") {
+        if let Some([speed, sprint, crouch]) = args.get(i + 1).and_then(|s| parse_floats(s)) {
+            engine.set_move_speed(speed, sprint, crouch);
+        }
+    }
+    // `--acceleration <acceleration,friction>` eases the movement in and out,
+    // in blocks per second squared
+    if let Some(i) = args.iter().position(|arg| arg == "--acceleration") {
+        if let Some([acceleration, friction]) = args.get(i + 1).and_then(|s| parse_floats(s)) {
+            engine.set_acceleration(acceleration, friction);
+        }
+    }
+    // `--sprint-fov <degrees>` sets how much the fov widens while sprinting
+    if let Some(i) = args.iter().position(|arg| arg == "--sprint-fov") {
+        if let Some(fov) = args.get(i + 1).and_then(|s| s.parse().ok()) {
+            engine.set_sprint_fov(Deg::<f32>(fov));
+        }
+    }
+    // `--sky-color <r,g,b,a>` or `--sky-gradient <r,g,b,a> <r,g,b,a>` (top
+    // then bottom) replace the sky that follows the time of day
+    if let Some(i) = args.iter().position(|arg| arg == "--sky-color") {
+        if let Some(color) = args.get(i + 1).and_then(|s| parse_floats(s)) {
+            engine.set_sky_color(color);
+        }
+    }
+    if let Some(i) = args.iter().position(|arg| arg == "--sky-gradient") {
+        let top = args.get(i + 1).and_then(|s| parse_floats(s));
+        let bottom = args.get(i + 2).and_then(|s| parse_floats(s));
+        if let (Some(top), Some(bottom)) = (top, bottom) {
+            engine.set_sky(Sky::Gradient { top, bottom });
+        }
+    }
+    // `--minimap <size> <corner>` places the minimap (toggled with `M`) in
+    // `top-left`, `top-right`, `bottom-left` or `bottom-right`
+    if let Some(i) = args.iter().position(|arg| arg == "--minimap") {
+        let size = args.get(i + 1).and_then(|s| s.parse().ok());
+        let corner = args.get(i + 2).and_then(|s| Corner::from_name(s));
+        if let (Some(size), Some(corner)) = (size, corner) {
+            engine.set_minimap(size, corner);
+        }
+    }
+    // `--reach <look_radius,reach>` sets how far blocks are highlighted, and
+    // how far they can be placed and removed
+    if let Some(i) = args.iter().position(|arg| arg == "--reach") {
+        if let Some([look_radius, reach]) = args.get(i + 1).and_then(|s| parse_floats(s)) {
+            engine.set_reach(look_radius, reach);
+        }
+    }
+    // `--heightmap <path>` generates the terrain from a grayscale PNG image
+    if let Some(i) = args.iter().position(|arg| arg == "--heightmap") {
+        if let Some(path) = args.get(i + 1) {
+            if let Err(e) = engine.load_heightmap(Path::new(path), 1., 128) {
+                eprintln!("Failed to load heightmap {path}: {e}");
+            }
+        }
+    }
+    // `--superflat` generates flat terrain for building
+    if args.iter().any(|arg| arg == "--superflat") {
+        engine.generate_superflat(&[
+            (BlockType::Stone, 1),
+            (BlockType::Dirt, 3),
+            (BlockType::Grass, 1),
+        ]);
+    }
+    // `--teleport <x,y,z>` moves the camera, the chunks around it are
+    // loaded as it starts
+    if let Some(i) = args.iter().position(|arg| arg == "--teleport") {
+        if let Some([x, y, z]) = args.get(i + 1).and_then(|s| parse_floats(s)) {
+            engine.teleport([x, y, z].into());
+        }
+    }
+    // `--schematic <path> <x,y,z>` pastes the blocks of a schematic file, after
+    // the terrain is generated
+    if let Some(i) = args.iter().position(|arg| arg == "--schematic") {
+        let origin = args.get(i + 2).and_then(|s| parse_floats(s));
+        if let (Some(path), Some(origin)) = (args.get(i + 1), origin) {
+            let origin = Point3::from(origin).map(|v: f32| v.round() as i32);
+            if let Err(e) = engine.paste_schematic(Path::new(path), origin) {
+                eprintln!("Failed to load schematic {path}: {e}");
+            }
+        }
+    }
+    // `--export-schematic <path> <x,y,z> <x,y,z>` saves the blocks in a box of
+    // the starting world as a schematic file
+    if let Some(i) = args.iter().position(|arg| arg == "--export-schematic") {
+        let a = args.get(i + 2).and_then(|s| parse_floats(s));
+        let b = args.get(i + 3).and_then(|s| parse_floats(s));
+        if let (Some(path), Some(a), Some(b)) = (args.get(i + 1), a, b) {
+            let [a, b] = [a, b].map(|p| Point3::from(p).map(|v: f32| v.round() as i32));
+            if let Err(e) = engine.export_schematic(Path::new(path), a, b) {
+                eprintln!("Failed to save schematic {path}: {e}");
+            }
+        }
+    }
+    // `--no-placement-preview` hides the translucent block shown where the
+    // active block would be placed
+    if args.iter().any(|arg| arg == "--no-placement-preview") {
+        engine.set_show_placement_preview(false);
+    }
+    // `--max-instances <count>` draws only the first instances of every chunk
+    if let Some(i) = args.iter().position(|arg| arg == "--max-instances") {
+        if let Some(max) = args.get(i + 1).and_then(|s| s.parse().ok()) {
+            engine.set_max_instances_per_chunk(Some(max));
+        }
+    }
+    // `--depth-prepass` draws the depth before shading, compare the frame
+    // times with and without it using `--camera-path`
+    if args.iter().any(|arg| arg == "--depth-prepass") {
+        engine.set_depth_prepass(true);
+    }
+    // `--memory-report <seconds>` logs the memory usage periodically
+    if let Some(i) = args.iter().position(|arg| arg == "--memory-report") {
+        if let Some(seconds) = args.get(i + 1).and_then(|s| s.parse::<f32>().ok()) {
+            if seconds > 0. {
+                engine.set_memory_report_interval(Some(Duration::from_secs_f32(seconds)));
+            }
+        }
+    }
+    // `--camera-path <path>` replays a recorded camera path, for profiling
+    if let Some(i) = args.iter().position(|arg| arg == "--camera-path") {
+        if let Some(path) = args.get(i + 1) {
+            match CameraPath::load_from_path(Path::new(path)) {
+                Ok(camera_path) => engine.play_camera_path(camera_path),
+                Err(e) => eprintln!("Failed to load camera path {path}: {e}"),
+            }
+        }
+    }
+
+    // `--unfocused-fps <fps>` limits the frame rate while the window is not
+    // focused, `0` stops drawing completely
+    let mut unfocused_frame_rate = UnfocusedFrameRate::Limited(10.);
+    if let Some(i) = args.iter().position(|arg| arg == "--unfocused-fps") {
+        if let Some(fps) = args.get(i + 1).and_then(|s| s.parse::<f32>().ok()) {
+            unfocused_frame_rate = if fps > 0. {
+                UnfocusedFrameRate::Limited(fps)
+            } else {
+                UnfocusedFrameRate::Paused
+            };
+        }
+    }
 
     let mut t = Instant::now();
+    let mut last_frame = Instant::now();
+    let mut focused = true;
     event_loop.run(move |event, _, control_flow: &mut ControlFlow| {
         match event {
             Event::WindowEvent {
@@ -41,27 +344,79 @@ fn main() {
             } => {
                 display.resize();
             }
-            Event::RedrawEventsCleared => {
-                let future = display.begin_frame();
-
-                match future {
-                    Ok(future) => {
-                        let current_image = display.current_image();
-
-                        let future = engine.render(current_image, future);
-
-                        display.end_frame(future);
-                    }
-                    Err(e) => {
-                        eprintln!("Error on begin frame: {e}");
-                        return;
+            Event::WindowEvent {
+                event: WindowEvent::Focused(is_focused),
+                ..
+            } => {
+                focused = is_focused;
+            }
+            // `V` toggles vsync, preferring `Mailbox` over `Immediate` when off
+            // as it doesn't tear
+            Event::WindowEvent {
+                event:
+                    WindowEvent::KeyboardInput {
+                        input:
+                            KeyboardInput {
+                                state: ElementState::Pressed,
+                                virtual_keycode: Some(VirtualKeyCode::V),
+                                ..
+                            },
+                        ..
+                    },
+                ..
+            } => {
+                let present_mode = if display.present_mode() != PresentMode::Fifo {
+                    PresentMode::Fifo
+                } else if display.supports_present_mode(PresentMode::Mailbox) {
+                    PresentMode::Mailbox
+                } else {
+                    PresentMode::Immediate
+                };
+                if display.set_present_mode(present_mode) {
+                    println!("Using present mode: {present_mode:?}");
+                }
+            }
+            // `F9` moves the rendering to the next gpu, for comparing them on
+            // machines with more than one
+            Event::WindowEvent {
+                event:
+                    WindowEvent::KeyboardInput {
+                        input:
+                            KeyboardInput {
+                                state: ElementState::Pressed,
+                                virtual_keycode: Some(VirtualKeyCode::F9),
+                                ..
+                            },
+                        ..
+                    },
+                ..
+            } => {
+                let index = (display.device_index() + 1) % display.device_count();
+                display.switch_device(index);
+                engine.switch_queue(display.queue(), display.swapchain_image_format());
+            }
+            // any event wakes the loop, so input is still handled right away
+            Event::RedrawEventsCleared if !focused => match unfocused_frame_rate {
+                UnfocusedFrameRate::Paused => *control_flow = ControlFlow::Wait,
+                UnfocusedFrameRate::Limited(fps) => {
+                    let next_frame = last_frame + Duration::from_secs_f32(1. / fps);
+                    *control_flow = ControlFlow::WaitUntil(next_frame);
+                    if Instant::now() >= next_frame {
+                        draw_frame(&mut display, &mut engine, &mut last_frame);
                     }
                 }
+            },
+            Event::RedrawEventsCleared => {
+                *control_flow = ControlFlow::Poll;
+                draw_frame(&mut display, &mut engine, &mut last_frame);
             }
             _ => (),
         }
 
         engine.handle_events(event);
+        if engine.mouse_captured() != display.cursor_captured() {
+            display.set_cursor_captured(engine.mouse_captured());
+        }
         engine.update(t.elapsed());
         t = Instant::now();
     });
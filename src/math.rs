@@ -0,0 +1,138 @@
+use cgmath::{InnerSpace, Matrix, Matrix4, Point3, Vector3, Vector4};
+
+/// A plane in the form `normal . p + distance = 0`, points where the
+/// result is positive are in front of the plane
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Plane {
+    pub normal: Vector3<f32>,
+    pub distance: f32,
+}
+
+impl Plane {
+    fn from_vec4(v: Vector4<f32>) -> Self {
+        let normal = v.truncate();
+        let len = normal.magnitude();
+
+        Self {
+            normal: normal / len,
+            distance: v.w / len,
+        }
+    }
+
+    pub fn signed_distance(&self, point: Point3<f32>) -> f32 {
+        self.normal.dot(point - Point3::new(0., 0., 0.)) + self.distance
+    }
+}
+
+/// Extracts the 6 frustum planes (left, right, bottom, top, near, far)
+/// from a `projection * view` matrix, all normals point inside the frustum.
+///
+/// This expects vulkan clip space where `0 <= z <= w`, and works for
+/// both standard and reversed depth, the only difference is that near and
+/// far will be swapped for reversed depth.
+pub(crate) fn frustum_planes(view_projection: &Matrix4<f32>) -> [Plane; 6] {
+    let r0 = view_projection.row(0);
+    let r1 = view_projection.row(1);
+    let r2 = view_projection.row(2);
+    let r3 = view_projection.row(3);
+
+    [
+        Plane::from_vec4(r3 + r0),
+        Plane::from_vec4(r3 - r0),
+        Plane::from_vec4(r3 + r1),
+        Plane::from_vec4(r3 - r1),
+        Plane::from_vec4(r3 - r2),
+        Plane::from_vec4(r2),
+    ]
+}
+
+/// Axis aligned bounding box
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Aabb {
+    pub min: Point3<f32>,
+    pub max: Point3<f32>,
+}
+
+impl Aabb {
+    pub fn new(min: Point3<f32>, max: Point3<f32>) -> Self {
+        Self { min, max }
+    }
+
+    pub fn translate(&self, offset: Vector3<f32>) -> Self {
+        Self {
+            min: self.min + offset,
+            max: self.max + offset,
+        }
+    }
+
+    /// Returns `true` if the boxes overlap, touching boxes don't count
+    pub fn intersects(&self, other: &Aabb) -> bool {
+        (0..3).all(|axis| self.min[axis] < other.max[axis] && other.min[axis] < self.max[axis])
+    }
+
+    /// Returns `false` only if the box is fully outside one of the planes,
+    /// so it may return `true` for some boxes outside the frustum near the
+    /// corners, which is fine for culling.
+    pub fn intersects_frustum(&self, planes: &[Plane; 6]) -> bool {
+        planes.iter().all(|plane| {
+            // the corner furthest along the plane normal
+            let positive = Point3::new(
+                if plane.normal.x >= 0. {
+                    self.max.x
+                } else {
+                    self.min.x
+                },
+                if plane.normal.y >= 0. {
+                    self.max.y
+                } else {
+                    self.min.y
+                },
+                if plane.normal.z >= 0. {
+                    self.max.z
+                } else {
+                    self.min.z
+                },
+            );
+
+            plane.signed_distance(positive) >= 0.
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cgmath::Deg;
+
+    use super::*;
+    use crate::camera::{Camera, DepthMode};
+
+    fn unit_box_at(x: f32, y: f32, z: f32) -> Aabb {
+        Aabb::new(
+            Point3::new(x - 0.5, y - 0.5, z - 0.5),
+            Point3::new(x + 0.5, y + 0.5, z + 0.5),
+        )
+    }
+
+    #[test]
+    fn frustum_culls_boxes_outside_the_view() {
+        for depth_mode in [DepthMode::Reversed, DepthMode::Standard] {
+            // looking at `+z` with a 90 degree fov
+            let mut camera = Camera::new(Deg(90.), 1., 0.1, 100., Point3::new(0., 0., 0.));
+            camera.set_depth_mode(depth_mode);
+            let planes = camera.frustum_planes();
+
+            assert!(unit_box_at(0., 0., 10.).intersects_frustum(&planes));
+            assert!(unit_box_at(8., -8., 10.).intersects_frustum(&planes));
+            // behind, beside, and past the far plane
+            assert!(!unit_box_at(0., 0., -10.).intersects_frustum(&planes));
+            assert!(!unit_box_at(15., 0., 10.).intersects_frustum(&planes));
+            assert!(!unit_box_at(0., -15., 10.).intersects_frustum(&planes));
+            assert!(!unit_box_at(0., 0., 110.).intersects_frustum(&planes));
+            // partly inside
+            assert!(
+                Aabb::new(Point3::new(-50., -1., 5.), Point3::new(-4., 1., 6.))
+                    .intersects_frustum(&planes)
+            );
+        }
+    }
+}
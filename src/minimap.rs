@@ -0,0 +1,142 @@
+//! A top down map of the loaded chunks, made of lines so it can be drawn
+//! with the ui lines pipeline.
+
+use cgmath::{Point2, Vector2};
+
+use crate::world::CHUNK_SIZE;
+
+/// Distance in pixels between the minimap and the edges of the display
+const MARGIN: f32 = 10.;
+
+/// The corner of the display the minimap is drawn in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Corner {
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    BottomRight,
+}
+
+impl Corner {
+    /// Parses names like `top-left`, `None` for unknown names
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "top-left" => Some(Self::TopLeft),
+            "top-right" => Some(Self::TopRight),
+            "bottom-left" => Some(Self::BottomLeft),
+            "bottom-right" => Some(Self::BottomRight),
+            _ => None,
+        }
+    }
+}
+
+pub(crate) struct Minimap {
+    /// width and height of the minimap in pixels
+    pub size: f32,
+    pub corner: Corner,
+    /// number of blocks from the center of the minimap to its edges
+    pub range: f32,
+}
+
+impl Minimap {
+    /// The top left of the minimap in pixels
+    fn origin(&self, display_size: [u32; 2]) -> [f32; 2] {
+        let right = display_size[0] as f32 - MARGIN - self.size;
+        let bottom = display_size[1] as f32 - MARGIN - self.size;
+
+        match self.corner {
+            Corner::TopLeft => [MARGIN, MARGIN],
+            Corner::TopRight => [right, MARGIN],
+            Corner::BottomLeft => [MARGIN, bottom],
+            Corner::BottomRight => [right, bottom],
+        }
+    }
+
+    /// Converts a world `(x, z)` position into pixels, `+z` is up in the map
+    fn to_display(&self, origin: [f32; 2], center: Point2<f32>, pos: Point2<f32>) -> [f32; 2] {
+        let scale = self.size / (self.range * 2.);
+        let half = self.size / 2.;
+
+        [
+            origin[0] + half + (pos.x - center.x) * scale,
+            origin[1] + half - (pos.y - center.y) * scale,
+        ]
+    }
+
+    /// Returns pairs of points (in pixels) for the lines of the minimap border,
+    /// and the outlines of the chunks (starting at `chunk_ids`) that fit inside
+    /// it, `center` is the `(x, z)` of the player.
+    pub fn chunk_lines(
+        &self,
+        display_size: [u32; 2],
+        center: Point2<f32>,
+        chunk_ids: impl Iterator<Item = (i32, i32)>,
+    ) -> Vec<[f32; 2]> {
+        let origin = self.origin(display_size);
+        let [x0, y0] = origin;
+        let [x1, y1] = [x0 + self.size, y0 + self.size];
+
+        let mut lines = vec![
+            [x0, y0],
+            [x1, y0],
+            [x1, y0],
+            [x1, y1],
+            [x1, y1],
+            [x0, y1],
+            [x0, y1],
+            [x0, y0],
+        ];
+
+        for (x, z) in chunk_ids {
+            // cubes are centered on their position
+            let min = Point2::new(x as f32 - 0.5, z as f32 - 0.5);
+            let max = min + Vector2::new(CHUNK_SIZE as f32, CHUNK_SIZE as f32);
+
+            let inside = (min.x - center.x).abs() <= self.range
+                && (max.x - center.x).abs() <= self.range
+                && (min.y - center.y).abs() <= self.range
+                && (max.y - center.y).abs() <= self.range;
+            if !inside {
+                continue;
+            }
+
+            let corners = [
+                self.to_display(origin, center, min),
+                self.to_display(origin, center, Point2::new(max.x, min.y)),
+                self.to_display(origin, center, max),
+                self.to_display(origin, center, Point2::new(min.x, max.y)),
+            ];
+            for i in 0..4 {
+                lines.push(corners[i]);
+                lines.push(corners[(i + 1) % 4]);
+            }
+        }
+
+        lines
+    }
+
+    /// Returns pairs of points (in pixels) for a triangle in the center of
+    /// the minimap pointing in the `(x, z)` `direction`
+    pub fn player_lines(&self, display_size: [u32; 2], direction: Vector2<f32>) -> Vec<[f32; 2]> {
+        const LENGTH: f32 = 8.;
+
+        let origin = self.origin(display_size);
+        let center = [origin[0] + self.size / 2., origin[1] + self.size / 2.];
+
+        // the map `y` is flipped
+        let forward = Vector2::new(direction.x, -direction.y);
+        let right = Vector2::new(-forward.y, forward.x);
+
+        let point = |f: f32, r: f32| {
+            [
+                center[0] + (forward.x * f + right.x * r) * LENGTH,
+                center[1] + (forward.y * f + right.y * r) * LENGTH,
+            ]
+        };
+        let tip = point(1., 0.);
+        let left = point(-0.6, -0.5);
+        let right = point(-0.6, 0.5);
+
+        vec![tip, left, left, right, right, tip]
+    }
+}
@@ -1,10 +1,12 @@
-use std::{fmt, marker::PhantomData};
+use std::{collections::HashMap, fmt, marker::PhantomData};
 
 use bytemuck::{Pod, Zeroable};
 use vulkano::impl_vertex;
 
 pub mod cube;
-#[allow(dead_code)]
+#[cfg_attr(feature = "per_cube_mesh", allow(dead_code))]
+pub mod quad;
+pub mod slab;
 pub mod square;
 
 #[repr(C)]
@@ -12,9 +14,19 @@ pub mod square;
 pub struct Vertex {
     pub pos: [f32; 3],
     pub normal: [f32; 3],
+    pub tex_coord: [f32; 2],
+    /// the face of the block this vertex belongs to, in the order of
+    /// [`BlockFace::ALL`](crate::block::BlockFace::ALL), used to pick the
+    /// face color of the block, or [`NO_FACE`] to only use the instance color
+    pub face: u32,
 }
 
-impl_vertex!(Vertex, pos, normal);
+impl_vertex!(Vertex, pos, normal, tex_coord, face);
+
+/// [`Vertex::face`] of meshes that are not a part of a block, or are only
+/// one face, like the quads of the chunk mesh which get the face color in
+/// their instance color
+pub const NO_FACE: u32 = 6;
 
 #[repr(C)]
 #[derive(Clone, Copy, Pod, Zeroable)]
@@ -23,6 +35,16 @@ pub struct Instance {
     pub rotation: [f32; 3],
     pub translation: [f32; 3],
     pub scale: f32,
+    /// the tile in the texture atlas
+    pub texture_index: u32,
+    /// scale of the mesh in each axis, applied before rotation
+    pub size: [f32; 3],
+    /// ambient occlusion brightness of the corners of a quad, see
+    /// [`quad::Quad::ao`], `1` for other meshes
+    pub ao: [f32; 4],
+    /// `1` if the top of the mesh moves with the waves in the transparent
+    /// pipeline, for liquids with no liquid above them
+    pub wave: f32,
 }
 
 impl Default for Instance {
@@ -32,11 +54,25 @@ impl Default for Instance {
             rotation: [0.; 3],
             translation: [0.; 3],
             scale: 1.,
+            texture_index: 0,
+            size: [1.; 3],
+            ao: [1.; 4],
+            wave: 0.,
         }
     }
 }
 
-impl_vertex!(Instance, color, rotation, translation, scale);
+impl_vertex!(
+    Instance,
+    color,
+    rotation,
+    translation,
+    scale,
+    texture_index,
+    size,
+    ao,
+    wave
+);
 
 #[derive(Debug)]
 pub enum InstancesMeshError {
@@ -60,9 +96,20 @@ pub trait Mesh {
     fn to_instance(&self) -> Instance;
 }
 
+/// The data of an [`InstancesMesh`] without its mesh type, so meshes of
+/// different types can be drawn the same way
+pub struct MeshView<'a> {
+    pub vertices: &'a [Vertex],
+    pub indices: &'a [u32],
+    pub outline_indices: &'a [u32],
+    pub instances: &'a [Instance],
+}
+
 pub struct InstancesMesh<M: Mesh> {
     vertices: Vec<Vertex>,
     indices: Vec<u32>,
+    // line list of the outer edges of the mesh triangles, for wireframe rendering
+    outline_indices: Vec<u32>,
     instances: Vec<Instance>,
 
     phantom: PhantomData<M>,
@@ -81,16 +128,19 @@ impl<M: Mesh> InstancesMesh<M> {
 
         Ok(Self {
             vertices: mesh.0,
+            outline_indices: outline_indices(&mesh.1),
             indices: mesh.1,
             instances: Vec::new(),
             phantom: PhantomData,
         })
     }
 
+    #[allow(dead_code)]
     pub fn vertices(&self) -> &[Vertex] {
         &self.vertices
     }
 
+    #[allow(dead_code)]
     pub fn indices(&self) -> &[u32] {
         &self.indices
     }
@@ -99,6 +149,15 @@ impl<M: Mesh> InstancesMesh<M> {
         &self.instances
     }
 
+    pub fn view(&self) -> MeshView<'_> {
+        MeshView {
+            vertices: &self.vertices,
+            indices: &self.indices,
+            outline_indices: &self.outline_indices,
+            instances: &self.instances,
+        }
+    }
+
     pub fn is_empty(&self) -> bool {
         self.indices.is_empty() || self.instances.is_empty()
     }
@@ -106,8 +165,24 @@ impl<M: Mesh> InstancesMesh<M> {
     pub fn append_instance(&mut self, instance: &M) {
         self.instances.push(instance.to_instance());
     }
+}
 
-    pub fn extend_mesh(&mut self, mesh: &Self) {
-        self.instances.extend_from_slice(&mesh.instances);
+/// Converts triangle list indices into line list indices, only keeping edges
+/// that belong to one triangle, so the diagonals inside faces are not drawn
+fn outline_indices(indices: &[u32]) -> Vec<u32> {
+    let edges = indices
+        .chunks_exact(3)
+        .flat_map(|t| [(t[0], t[1]), (t[1], t[2]), (t[2], t[0])])
+        .collect::<Vec<_>>();
+
+    let mut counts = HashMap::new();
+    for &(a, b) in &edges {
+        *counts.entry((a.min(b), a.max(b))).or_insert(0) += 1;
     }
+
+    edges
+        .into_iter()
+        .filter(|&(a, b)| counts[&(a.min(b), a.max(b))] == 1)
+        .flat_map(|(a, b)| [a, b])
+        .collect()
 }
@@ -1,74 +1,88 @@
 use cgmath::Point3;
 
-use super::{Instance, Mesh, Vertex};
+use super::{Instance, Mesh, Vertex, NO_FACE};
 
 pub struct Cube {
     pub center: Point3<f32>,
+    /// multiplied with the face colors of the block of `texture_index`
     pub color: [f32; 4],
     pub rotation: [f32; 3],
+    pub texture_index: u32,
+    /// the top moves with the waves, see [`Instance::wave`]
+    pub wave: bool,
 }
 
 impl Mesh for Cube {
     fn mesh() -> (Vec<Vertex>, Vec<u32>) {
-        // creates a vertex with normal
+        // texture coordinates of the corners of a face
+        const TOP_LEFT: [f32; 2] = [0., 0.];
+        const TOP_RIGHT: [f32; 2] = [1., 0.];
+        const BOTTOM_LEFT: [f32; 2] = [0., 1.];
+        const BOTTOM_RIGHT: [f32; 2] = [1., 1.];
+
+        // creates a vertex with normal and texture coordinates
         macro_rules! create_vertex {
-            ($pos: expr, $normal: expr) => {
+            ($pos: expr, $normal: expr, $tex_coord: expr) => {
                 Vertex {
                     pos: $pos,
                     normal: $normal,
+                    tex_coord: $tex_coord,
+                    face: NO_FACE,
                 }
             };
-            (copy $vec: expr, $normal: expr) => {
+            (copy $vec: expr, $normal: expr, $tex_coord: expr) => {
                 Vertex {
                     pos: $vec.pos,
                     normal: $normal,
+                    tex_coord: $tex_coord,
+                    face: NO_FACE,
                 }
             };
         }
 
         // front
         let normal = [0., 0., -1.];
-        let front_top_left = create_vertex!([-0.5, 0.5, -0.5], normal);
-        let front_top_right = create_vertex!([0.5, 0.5, -0.5], normal);
-        let front_bottom_left = create_vertex!([-0.5, -0.5, -0.5], normal);
-        let front_bottom_right = create_vertex!([0.5, -0.5, -0.5], normal);
+        let front_top_left = create_vertex!([-0.5, 0.5, -0.5], normal, TOP_LEFT);
+        let front_top_right = create_vertex!([0.5, 0.5, -0.5], normal, TOP_RIGHT);
+        let front_bottom_left = create_vertex!([-0.5, -0.5, -0.5], normal, BOTTOM_LEFT);
+        let front_bottom_right = create_vertex!([0.5, -0.5, -0.5], normal, BOTTOM_RIGHT);
 
         // back
         let normal = [0., 0., 1.];
-        let back_top_left = create_vertex!([-0.5, 0.5, 0.5], normal);
-        let back_top_right = create_vertex!([0.5, 0.5, 0.5], normal);
-        let back_bottom_left = create_vertex!([-0.5, -0.5, 0.5], normal);
-        let back_bottom_right = create_vertex!([0.5, -0.5, 0.5], normal);
+        let back_top_left = create_vertex!([-0.5, 0.5, 0.5], normal, TOP_LEFT);
+        let back_top_right = create_vertex!([0.5, 0.5, 0.5], normal, TOP_RIGHT);
+        let back_bottom_left = create_vertex!([-0.5, -0.5, 0.5], normal, BOTTOM_LEFT);
+        let back_bottom_right = create_vertex!([0.5, -0.5, 0.5], normal, BOTTOM_RIGHT);
 
         // right
         let normal = [1., 0., 0.];
-        let right_top_left = create_vertex!(copy front_top_right, normal);
-        let right_top_right = create_vertex!(copy back_top_right, normal);
-        let right_bottom_left = create_vertex!(copy front_bottom_right, normal);
-        let right_bottom_right = create_vertex!(copy back_bottom_right, normal);
+        let right_top_left = create_vertex!(copy front_top_right, normal, TOP_LEFT);
+        let right_top_right = create_vertex!(copy back_top_right, normal, TOP_RIGHT);
+        let right_bottom_left = create_vertex!(copy front_bottom_right, normal, BOTTOM_LEFT);
+        let right_bottom_right = create_vertex!(copy back_bottom_right, normal, BOTTOM_RIGHT);
 
         // left
         let normal = [-1., 0., 0.];
-        let left_top_left = create_vertex!(copy back_top_left, normal);
-        let left_top_right = create_vertex!(copy front_top_left, normal);
-        let left_bottom_left = create_vertex!(copy back_bottom_left, normal);
-        let left_bottom_right = create_vertex!(copy front_bottom_left, normal);
+        let left_top_left = create_vertex!(copy back_top_left, normal, TOP_LEFT);
+        let left_top_right = create_vertex!(copy front_top_left, normal, TOP_RIGHT);
+        let left_bottom_left = create_vertex!(copy back_bottom_left, normal, BOTTOM_LEFT);
+        let left_bottom_right = create_vertex!(copy front_bottom_left, normal, BOTTOM_RIGHT);
 
         // up
         let normal = [0., 1., 0.];
-        let up_top_left = create_vertex!(copy back_top_left, normal);
-        let up_top_right = create_vertex!(copy back_top_right, normal);
-        let up_bottom_left = create_vertex!(copy front_top_left, normal);
-        let up_bottom_right = create_vertex!(copy front_top_right, normal);
+        let up_top_left = create_vertex!(copy back_top_left, normal, TOP_LEFT);
+        let up_top_right = create_vertex!(copy back_top_right, normal, TOP_RIGHT);
+        let up_bottom_left = create_vertex!(copy front_top_left, normal, BOTTOM_LEFT);
+        let up_bottom_right = create_vertex!(copy front_top_right, normal, BOTTOM_RIGHT);
 
         // bottom
         let normal = [0., -1., 0.];
-        let bottom_top_left = create_vertex!(copy back_bottom_left, normal);
-        let bottom_top_right = create_vertex!(copy back_bottom_right, normal);
-        let bottom_bottom_left = create_vertex!(copy front_bottom_left, normal);
-        let bottom_bottom_right = create_vertex!(copy front_bottom_right, normal);
+        let bottom_top_left = create_vertex!(copy back_bottom_left, normal, TOP_LEFT);
+        let bottom_top_right = create_vertex!(copy back_bottom_right, normal, TOP_RIGHT);
+        let bottom_bottom_left = create_vertex!(copy front_bottom_left, normal, BOTTOM_LEFT);
+        let bottom_bottom_right = create_vertex!(copy front_bottom_right, normal, BOTTOM_RIGHT);
 
-        let vertices = vec![
+        let mut vertices = vec![
             // front
             front_top_left,
             front_top_right,
@@ -100,6 +114,10 @@ impl Mesh for Cube {
             bottom_bottom_left,
             bottom_bottom_right,
         ];
+        // the vertices are grouped by face, in the same order as `BlockFace::ALL`
+        for (i, vertex) in vertices.iter_mut().enumerate() {
+            vertex.face = i as u32 / 4;
+        }
 
         // we have all distinct 24 vertices, just to make it easier later
         // to apply texture to specific face only
@@ -120,6 +138,8 @@ impl Mesh for Cube {
             translation: self.center.into(),
             color: self.color,
             rotation: self.rotation,
+            texture_index: self.texture_index,
+            wave: if self.wave { 1. } else { 0. },
             ..Default::default()
         }
     }
@@ -0,0 +1,70 @@
+use cgmath::Point3;
+
+use super::{Instance, Mesh, Vertex, NO_FACE};
+
+/// A square in the `xy` plane facing `-z`, it can be rotated to face
+/// other directions, and stretched with `size`
+pub struct Quad {
+    pub center: Point3<f32>,
+    pub color: [f32; 4],
+    pub rotation: [f32; 3],
+    /// the size in the local `x` and `y` axes (before rotation)
+    pub size: [f32; 2],
+    pub texture_index: u32,
+    /// brightness of the corners, in the order `(-x, -y)`, `(+x, -y)`,
+    /// `(-x, +y)` and `(+x, +y)` in the local axes
+    pub ao: [f32; 4],
+    /// the top edge moves with the waves, see [`Instance::wave`]
+    pub wave: bool,
+}
+
+impl Mesh for Quad {
+    fn mesh() -> (Vec<Vertex>, Vec<u32>) {
+        let normal = [0., 0., -1.];
+
+        // same as the front face of the cube
+        let vertices = vec![
+            Vertex {
+                pos: [-0.5, 0.5, 0.],
+                normal,
+                tex_coord: [0., 0.],
+                face: NO_FACE,
+            },
+            Vertex {
+                pos: [0.5, 0.5, 0.],
+                normal,
+                tex_coord: [1., 0.],
+                face: NO_FACE,
+            },
+            Vertex {
+                pos: [-0.5, -0.5, 0.],
+                normal,
+                tex_coord: [0., 1.],
+                face: NO_FACE,
+            },
+            Vertex {
+                pos: [0.5, -0.5, 0.],
+                normal,
+                tex_coord: [1., 1.],
+                face: NO_FACE,
+            },
+        ];
+
+        let indices = vec![0, 1, 2, 1, 2, 3];
+
+        (vertices, indices)
+    }
+
+    fn to_instance(&self) -> Instance {
+        Instance {
+            translation: self.center.into(),
+            color: self.color,
+            rotation: self.rotation,
+            size: [self.size[0], self.size[1], 1.],
+            texture_index: self.texture_index,
+            ao: self.ao,
+            wave: if self.wave { 1. } else { 0. },
+            ..Default::default()
+        }
+    }
+}
@@ -0,0 +1,48 @@
+use cgmath::Point3;
+
+use super::{cube::Cube, Instance, Mesh, Vertex};
+
+/// The bottom half of a cube
+pub struct Slab {
+    pub center: Point3<f32>,
+    /// multiplied with the face colors of the block of `texture_index`
+    pub color: [f32; 4],
+    pub rotation: [f32; 3],
+    pub texture_index: u32,
+}
+
+impl Mesh for Slab {
+    fn mesh() -> (Vec<Vertex>, Vec<u32>) {
+        let (vertices, indices) = Cube::mesh();
+
+        // squash the cube to the bottom half
+        let vertices = vertices
+            .into_iter()
+            .map(|vertex| {
+                let mut tex_coord = vertex.tex_coord;
+                // the sides use the bottom half of the texture, so it doesn't stretch
+                if vertex.normal[1] == 0. {
+                    tex_coord[1] = 0.5 + tex_coord[1] / 2.;
+                }
+
+                Vertex {
+                    pos: [vertex.pos[0], (vertex.pos[1] - 0.5) / 2., vertex.pos[2]],
+                    tex_coord,
+                    ..vertex
+                }
+            })
+            .collect();
+
+        (vertices, indices)
+    }
+
+    fn to_instance(&self) -> Instance {
+        Instance {
+            translation: self.center.into(),
+            color: self.color,
+            rotation: self.rotation,
+            texture_index: self.texture_index,
+            ..Default::default()
+        }
+    }
+}
@@ -1,11 +1,14 @@
 use cgmath::Point3;
 
-use super::{Instance, Mesh, Vertex};
+use super::{Instance, Mesh, Vertex, NO_FACE};
 
+/// A square in the `xy` plane, drawn as a billboard facing the camera
 pub struct Square {
     pub center: Point3<f32>,
     pub color: [f32; 4],
     pub rotation: [f32; 3],
+    /// the length of the sides
+    pub size: f32,
 }
 
 impl Mesh for Square {
@@ -21,18 +24,26 @@ impl Mesh for Square {
             Vertex {
                 pos: top_left,
                 normal,
+                tex_coord: [0., 0.],
+                face: NO_FACE,
             },
             Vertex {
                 pos: top_right,
                 normal,
+                tex_coord: [1., 0.],
+                face: NO_FACE,
             },
             Vertex {
                 pos: bottom_left,
                 normal,
+                tex_coord: [0., 1.],
+                face: NO_FACE,
             },
             Vertex {
                 pos: bottom_right,
                 normal,
+                tex_coord: [1., 1.],
+                face: NO_FACE,
             },
         ];
 
@@ -46,6 +57,7 @@ impl Mesh for Square {
             translation: self.center.into(),
             color: self.color,
             rotation: self.rotation,
+            scale: self.size,
             ..Default::default()
         }
     }
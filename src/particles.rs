@@ -0,0 +1,102 @@
+//! Short-lived colored squares used for feedback effects, drawn as
+//! billboards by the engine.
+
+use std::time::Duration;
+
+use cgmath::{Point3, Vector3};
+
+use crate::{
+    block::{BlockFace, BlockType},
+    object::square::Square,
+    terrain,
+};
+
+/// Number of particles spawned when a block is broken
+const BREAK_PARTICLES: u32 = 12;
+/// How long a particle lives, it fades out during this time
+const PARTICLE_LIFETIME: Duration = Duration::from_millis(500);
+/// The length of the sides of the particles in blocks
+const PARTICLE_SIZE: f32 = 0.12;
+/// Downward acceleration of the particles in blocks per second squared
+const PARTICLE_GRAVITY: f32 = 20.;
+
+/// A small colored square, which falls and fades out
+struct Particle {
+    position: Point3<f32>,
+    velocity: Vector3<f32>,
+    color: [f32; 4],
+    remaining: Duration,
+}
+
+pub(crate) struct ParticleSystem {
+    particles: Vec<Particle>,
+    // the oldest particles are removed to make room for new ones above this
+    max_particles: usize,
+    // changed on every burst, so bursts from the same cube look different
+    seed: u32,
+}
+
+impl ParticleSystem {
+    pub fn new(max_particles: usize) -> Self {
+        Self {
+            particles: Vec::new(),
+            max_particles,
+            seed: 0,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.particles.is_empty()
+    }
+
+    /// Bursts particles with the colors of `block` out of the cube at `pos`
+    pub fn spawn_block_break(&mut self, pos: Point3<i32>, block: BlockType) {
+        let seed = self.seed;
+        self.seed = self.seed.wrapping_add(BREAK_PARTICLES * 4);
+        let random = |i: u32| terrain::speckle(pos.x, pos.y, pos.z, seed.wrapping_add(i));
+
+        let center = pos.cast::<f32>().unwrap();
+        for i in 0..BREAK_PARTICLES {
+            let offset =
+                Vector3::new(random(i * 4), random(i * 4 + 1), random(i * 4 + 2)).map(|v| v - 0.5);
+            let face = BlockFace::ALL[(random(i * 4 + 3) * 5.99) as usize];
+            self.particles.push(Particle {
+                position: center + offset * 0.8,
+                // outwards and a bit up
+                velocity: offset * 4. + Vector3::new(0., 3., 0.),
+                color: block.face_color(face),
+                remaining: PARTICLE_LIFETIME,
+            });
+        }
+
+        if self.particles.len() > self.max_particles {
+            let extra = self.particles.len() - self.max_particles;
+            self.particles.drain(..extra);
+        }
+    }
+
+    /// Moves the particles and removes the ones that faded out
+    pub fn update(&mut self, delta: Duration) {
+        let delta_secs = delta.as_secs_f32();
+        self.particles.retain_mut(|particle| {
+            particle.velocity.y -= PARTICLE_GRAVITY * delta_secs;
+            particle.position += particle.velocity * delta_secs;
+            particle.remaining = particle.remaining.saturating_sub(delta);
+            !particle.remaining.is_zero()
+        });
+    }
+
+    /// The particles as squares, their alpha is lowered as they get older
+    pub fn squares(&self) -> impl ExactSizeIterator<Item = Square> + '_ {
+        self.particles.iter().map(|particle| {
+            let life = particle.remaining.as_secs_f32() / PARTICLE_LIFETIME.as_secs_f32();
+            let [r, g, b, a] = particle.color;
+            Square {
+                center: particle.position,
+                color: [r, g, b, a * life],
+                rotation: [0.; 3],
+                size: PARTICLE_SIZE,
+            }
+        })
+    }
+}
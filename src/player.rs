@@ -0,0 +1,279 @@
+use std::time::Duration;
+
+use cgmath::{Point3, Vector3};
+
+use crate::{camera::Camera, math::Aabb, world::World};
+
+/// Downward acceleration in blocks per second squared
+const GRAVITY: f32 = 32.;
+/// Maximum falling speed in blocks per second
+const TERMINAL_VELOCITY: f32 = 60.;
+/// Initial upward speed of a jump in blocks per second
+const JUMP_SPEED: f32 = 9.;
+/// How fast the player is pushed up when inside cubes, after noclip is
+/// disabled, in blocks per second
+const EJECT_SPEED: f32 = 4.;
+/// Landing faster than this (in blocks per second) shakes the camera
+const HARD_LANDING_SPEED: f32 = 20.;
+/// Distance from the feet of the player to the camera
+const EYE_HEIGHT: f32 = 1.6;
+const PLAYER_HEIGHT: f32 = 1.8;
+/// The camera and the top of the player are lowered by this while crouching
+const CROUCH_OFFSET: f32 = 0.3;
+const PLAYER_WIDTH: f32 = 0.6;
+
+/// The player, which is the camera with physics applied to it.
+///
+/// When physics is disabled, the camera flies freely.
+pub(crate) struct Player {
+    camera: Camera,
+
+    vertical_velocity: f32,
+    on_ground: bool,
+    physics_enabled: bool,
+    // moves through cubes without gravity, even with physics enabled
+    noclip: bool,
+    crouching: bool,
+}
+
+impl Player {
+    pub fn new(camera: Camera) -> Self {
+        Self {
+            camera,
+            vertical_velocity: 0.,
+            on_ground: false,
+            physics_enabled: false,
+            noclip: false,
+            crouching: false,
+        }
+    }
+
+    pub fn camera(&self) -> &Camera {
+        &self.camera
+    }
+
+    pub fn camera_mut(&mut self) -> &mut Camera {
+        &mut self.camera
+    }
+
+    pub fn physics_enabled(&self) -> bool {
+        self.physics_enabled
+    }
+
+    pub fn set_physics_enabled(&mut self, enabled: bool) {
+        self.physics_enabled = enabled;
+        self.reset_physics_state();
+    }
+
+    pub fn noclip(&self) -> bool {
+        self.noclip
+    }
+
+    /// With noclip, the player flies through cubes without gravity. When it
+    /// is disabled inside cubes, the player is pushed up slowly until free
+    pub fn set_noclip(&mut self, noclip: bool) {
+        self.noclip = noclip;
+        self.reset_physics_state();
+    }
+
+    /// Physics is applied only if it's enabled and noclip is not
+    fn physics_active(&self) -> bool {
+        self.physics_enabled && !self.noclip
+    }
+
+    /// Stops falling and stands up, called when physics is turned on or off
+    fn reset_physics_state(&mut self) {
+        self.vertical_velocity = 0.;
+        self.on_ground = false;
+
+        // we can't crouch while flying, there is nothing to collide with
+        // when standing up
+        if !self.physics_active() && self.crouching {
+            self.crouching = false;
+            self.camera
+                .set_position(self.camera.position() + Vector3::new(0., CROUCH_OFFSET, 0.));
+        }
+    }
+
+    pub fn crouching(&self) -> bool {
+        self.crouching
+    }
+
+    /// Crouching lowers the camera and the top of the player, this only
+    /// works with physics enabled. The player stays crouched if there is
+    /// no room above to stand up
+    pub fn set_crouching(&mut self, world: &World, crouching: bool) {
+        if !self.physics_active() || crouching == self.crouching {
+            return;
+        }
+
+        let offset = if crouching {
+            -CROUCH_OFFSET
+        } else {
+            let allowed = world
+                .resolve_movement(&self.aabb(), Vector3::new(0., CROUCH_OFFSET, 0.))
+                .y;
+            if allowed < CROUCH_OFFSET {
+                return;
+            }
+            CROUCH_OFFSET
+        };
+
+        self.crouching = crouching;
+        self.camera
+            .set_position(self.camera.position() + Vector3::new(0., offset, 0.));
+    }
+
+    /// Distance from the feet of the player to the camera
+    fn eye_height(&self) -> f32 {
+        if self.crouching {
+            EYE_HEIGHT - CROUCH_OFFSET
+        } else {
+            EYE_HEIGHT
+        }
+    }
+
+    #[allow(dead_code)]
+    pub fn on_ground(&self) -> bool {
+        self.on_ground
+    }
+
+    /// Moves the player to `position` without colliding with anything on the
+    /// way, and stops falling
+    pub fn teleport(&mut self, position: Point3<f32>) {
+        self.camera.set_position(position);
+        self.vertical_velocity = 0.;
+        self.on_ground = false;
+    }
+
+    /// Jumps if the player is standing on a block
+    pub fn jump(&mut self) {
+        if self.physics_active() && self.on_ground {
+            self.vertical_velocity = JUMP_SPEED;
+            self.on_ground = false;
+        }
+    }
+
+    /// The box the player body occupies in the world
+    pub fn aabb(&self) -> Aabb {
+        let eye = *self.camera.position();
+        let half_width = PLAYER_WIDTH / 2.;
+        let feet = eye.y - self.eye_height();
+        let height = if self.crouching {
+            PLAYER_HEIGHT - CROUCH_OFFSET
+        } else {
+            PLAYER_HEIGHT
+        };
+
+        Aabb::new(
+            Point3::new(eye.x - half_width, feet, eye.z - half_width),
+            Point3::new(eye.x + half_width, feet + height, eye.z + half_width),
+        )
+    }
+
+    /// Moves the player in `direction` relative to the camera, with physics
+    /// enabled (and no noclip), the player can't move through cubes
+    pub fn move_player(&mut self, world: &World, direction: Vector3<f32>) {
+        if self.physics_active() {
+            let delta =
+                world.resolve_movement(&self.aabb(), self.camera.movement_vector(direction));
+            self.camera.set_position(self.camera.position() + delta);
+        } else {
+            self.camera.move_camera(direction);
+        }
+    }
+
+    /// Applies gravity and moves the player vertically, stopping on top
+    /// of the ground or below the ceiling
+    pub fn apply_physics(&mut self, world: &World, delta: Duration) {
+        if !self.physics_active() {
+            return;
+        }
+
+        let delta = delta.as_secs_f32();
+
+        // stuck inside cubes (after noclip), rise out of them slowly instead
+        // of falling through
+        if world.intersects_cubes(&self.aabb()) {
+            self.vertical_velocity = 0.;
+            self.on_ground = false;
+            self.camera
+                .set_position(self.camera.position() + Vector3::new(0., EJECT_SPEED * delta, 0.));
+            return;
+        }
+
+        self.vertical_velocity = (self.vertical_velocity - GRAVITY * delta).max(-TERMINAL_VELOCITY);
+
+        let wanted = self.vertical_velocity * delta;
+        let allowed = world
+            .resolve_movement(&self.aabb(), Vector3::new(0., wanted, 0.))
+            .y;
+
+        self.camera
+            .set_position(self.camera.position() + Vector3::new(0., allowed, 0.));
+
+        // we were stopped by a cube
+        self.on_ground = false;
+        if allowed != wanted {
+            if wanted < 0. {
+                self.on_ground = true;
+
+                let impact = -self.vertical_velocity - HARD_LANDING_SPEED;
+                if impact > 0. {
+                    self.camera
+                        .add_shake(impact * 0.004, Duration::from_millis(250));
+                }
+            }
+            self.vertical_velocity = 0.;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cgmath::Deg;
+
+    use super::*;
+    use crate::block::BlockType;
+
+    #[test]
+    fn the_player_lands_on_the_ground_and_stops_at_walls() {
+        let mut world = World::empty();
+        // the top cubes are at `y = 2`, so the ground is at `2.5`
+        world.create_chunk(0, 3, 0);
+        world.fill_box(Point3::new(3, 3, 0), Point3::new(3, 4, 8), BlockType::Stone);
+
+        let camera = Camera::new(Deg(90.), 1., 0.1, 100., Point3::new(0., 6., 4.));
+        let mut player = Player::new(camera);
+        player.set_physics_enabled(true);
+        for _ in 0..120 {
+            player.apply_physics(&world, Duration::from_millis(16));
+        }
+        assert!(player.on_ground());
+        assert!((player.aabb().min.y - 2.5).abs() < 1e-3);
+
+        // the camera looks at `+z`, so `+x` is to the right
+        player.move_player(&world, Vector3::new(5., 0., 0.));
+        let aabb = player.aabb();
+        assert!(aabb.max.x <= 2.5 && aabb.max.x > 2.4);
+        assert!(!world.intersects_cubes(&aabb));
+    }
+
+    #[test]
+    fn teleport_moves_the_camera_and_stops_falling() {
+        let world = World::empty();
+        let camera = Camera::new(Deg(90.), 1., 0.1, 100., Point3::new(0., 60., 0.));
+        let mut player = Player::new(camera);
+        player.set_physics_enabled(true);
+        for _ in 0..10 {
+            player.apply_physics(&world, Duration::from_millis(16));
+        }
+        assert!(player.vertical_velocity < 0.);
+
+        let target = Point3::new(-12.25, 80.5, 1000.75);
+        player.teleport(target);
+        assert_eq!(*player.camera().position(), target);
+        assert_eq!(player.vertical_velocity, 0.);
+        assert!(!player.on_ground());
+    }
+}
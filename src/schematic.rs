@@ -0,0 +1,180 @@
+//! Prebuilt structures that can be pasted into the world with
+//! [`World::paste_schematic`], and exported back from a region of a world.
+//!
+//! Schematics are stored as plain text with a block on every line (see
+//! [`Schematic::load_from_path`]) instead of JSON, RON or the NBT `.schem`
+//! format of Minecraft, as those need parsers the crate doesn't depend on.
+//! Like the world saves, the format is our own, but it's easy to write by hand
+//! and Minecraft block ids are accepted, so converted structures can be used.
+
+use std::{fs, io, path::Path};
+
+use cgmath::{Point3, Vector3};
+
+use crate::{block::BlockType, world::World};
+
+/// Block names accepted in schematic files, both our own names and the
+/// Minecraft ids of the closest blocks
+const BLOCK_NAMES: [(&str, BlockType); 9] = [
+    ("grass", BlockType::Grass),
+    ("dirt", BlockType::Dirt),
+    ("stone", BlockType::Stone),
+    ("water", BlockType::Water),
+    ("minecraft:grass_block", BlockType::Grass),
+    ("minecraft:dirt", BlockType::Dirt),
+    ("minecraft:stone", BlockType::Stone),
+    ("minecraft:cobblestone", BlockType::Stone),
+    ("minecraft:water", BlockType::Water),
+];
+
+/// The block for a name in a schematic file, unknown names are stone
+fn block_from_name(name: &str) -> BlockType {
+    BLOCK_NAMES
+        .iter()
+        .find(|(block_name, _)| block_name.eq_ignore_ascii_case(name))
+        .map_or(BlockType::Stone, |&(_, block)| block)
+}
+
+/// A structure made of blocks, positioned relative to its origin
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Schematic {
+    blocks: Vec<(Vector3<i32>, BlockType)>,
+}
+
+impl Schematic {
+    /// Copies the blocks in the box between `a` and `b` (inclusive) of `world`,
+    /// relative to the corner with the smallest coordinates
+    pub fn from_world(world: &World, a: Point3<i32>, b: Point3<i32>) -> Self {
+        let min = Point3::new(a.x.min(b.x), a.y.min(b.y), a.z.min(b.z));
+        let max = Point3::new(a.x.max(b.x), a.y.max(b.y), a.z.max(b.z));
+
+        let blocks = (min.x..=max.x)
+            .flat_map(|x| {
+                (min.y..=max.y).flat_map(move |y| (min.z..=max.z).map(move |z| (x, y, z)))
+            })
+            .filter_map(|(x, y, z)| {
+                let pos = Point3::new(x, y, z);
+                world.block_at(pos).map(|block| (pos - min, block))
+            })
+            .collect();
+
+        Self { blocks }
+    }
+
+    pub fn blocks(&self) -> &[(Vector3<i32>, BlockType)] {
+        &self.blocks
+    }
+
+    /// Loads a schematic from a text file with a block on every line:
+    ///
+    /// ```text
+    /// # x y z block
+    /// 0 0 0 stone
+    /// 0 1 0 minecraft:grass_block
+    /// ```
+    ///
+    /// The positions are relative to the origin the schematic is pasted at,
+    /// unknown block names are loaded as stone. Empty lines and lines starting
+    /// with `#` are ignored.
+    pub fn load_from_path(path: &Path) -> io::Result<Self> {
+        let text = fs::read_to_string(path)?;
+
+        let mut blocks = Vec::new();
+        for (i, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let invalid_line = || {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("invalid block in line {}: {line}", i + 1),
+                )
+            };
+
+            let values = line.split_whitespace().collect::<Vec<_>>();
+            let [x, y, z, name]: [&str; 4] = values.try_into().map_err(|_| invalid_line())?;
+            let [x, y, z] = [x, y, z].map(|v| v.parse::<i32>());
+
+            blocks.push((
+                Vector3::new(
+                    x.map_err(|_| invalid_line())?,
+                    y.map_err(|_| invalid_line())?,
+                    z.map_err(|_| invalid_line())?,
+                ),
+                block_from_name(name),
+            ));
+        }
+
+        Ok(Self { blocks })
+    }
+
+    /// Saves the schematic in the format read by [`Self::load_from_path`]
+    pub fn save_to_path(&self, path: &Path) -> io::Result<()> {
+        let mut text = String::from("# x y z block\n");
+        for (pos, block) in &self.blocks {
+            text.push_str(&format!("{} {} {} {}\n", pos.x, pos.y, pos.z, block.name()));
+        }
+
+        fs::write(path, text)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn exported_schematics_load_back_the_same() {
+        let mut world = World::empty();
+        world
+            .push_cube(Point3::new(3, 1, 4), BlockType::Stone)
+            .unwrap();
+        world
+            .push_cube(Point3::new(4, 2, 4), BlockType::Water)
+            .unwrap();
+        world
+            .push_cube(Point3::new(5, 1, 6), BlockType::Grass)
+            .unwrap();
+        let schematic = Schematic::from_world(&world, Point3::new(5, 2, 6), Point3::new(3, 1, 4));
+
+        let path = std::env::temp_dir().join("minecraft-schematic-test.txt");
+        schematic.save_to_path(&path).unwrap();
+        let loaded = Schematic::load_from_path(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(loaded.blocks(), schematic.blocks());
+
+        let mut copy = World::empty();
+        copy.paste_schematic(Point3::new(-10, 5, 20), &loaded);
+        assert_eq!(
+            copy.block_at(Point3::new(-10, 5, 20)),
+            Some(BlockType::Stone)
+        );
+        assert_eq!(
+            copy.block_at(Point3::new(-9, 6, 20)),
+            Some(BlockType::Water)
+        );
+        assert_eq!(
+            copy.block_at(Point3::new(-8, 5, 22)),
+            Some(BlockType::Grass)
+        );
+        assert_eq!(copy.block_count(), 3);
+    }
+
+    #[test]
+    fn pasting_skips_offsets_that_overflow() {
+        let schematic = Schematic {
+            blocks: vec![
+                (Vector3::new(i32::MAX, 0, 0), BlockType::Stone),
+                (Vector3::new(0, 0, i32::MIN), BlockType::Stone),
+                (Vector3::new(1, 0, 0), BlockType::Dirt),
+            ],
+        };
+
+        let mut world = World::empty();
+        world.paste_schematic(Point3::new(1, 1, -1), &schematic);
+        assert_eq!(world.block_at(Point3::new(2, 1, -1)), Some(BlockType::Dirt));
+        assert_eq!(world.block_count(), 1);
+    }
+}
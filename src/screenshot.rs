@@ -0,0 +1,43 @@
+use std::{fmt, fs::File, io, io::BufWriter, path::Path};
+
+use vulkano::format::Format;
+
+/// An image format that [`to_rgba8`] can't convert
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct UnsupportedFormat(pub Format);
+
+impl fmt::Display for UnsupportedFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "unsupported image format for conversion: {:?}", self.0)
+    }
+}
+
+/// Converts pixels copied from an image of `format` into RGBA8, only
+/// 8 bit RGBA and BGRA formats are supported (which covers the common
+/// swapchain formats)
+pub(crate) fn to_rgba8(format: Format, mut data: Vec<u8>) -> Result<Vec<u8>, UnsupportedFormat> {
+    match format {
+        Format::R8G8B8A8_UNORM | Format::R8G8B8A8_SRGB => Ok(data),
+        Format::B8G8R8A8_UNORM | Format::B8G8R8A8_SRGB => {
+            for pixel in data.chunks_exact_mut(4) {
+                pixel.swap(0, 2);
+            }
+            Ok(data)
+        }
+        _ => Err(UnsupportedFormat(format)),
+    }
+}
+
+/// Writes RGBA8 `pixels` of an image with `size` into a PNG file at `path`
+pub(crate) fn save_png(path: &Path, size: [u32; 2], pixels: &[u8]) -> io::Result<()> {
+    let writer = BufWriter::new(File::create(path)?);
+
+    let mut encoder = png::Encoder::new(writer, size[0], size[1]);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+
+    encoder
+        .write_header()
+        .and_then(|mut writer| writer.write_image_data(pixels))
+        .map_err(io::Error::other)
+}
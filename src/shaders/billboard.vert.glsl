@@ -0,0 +1,25 @@
+#version 450
+
+layout(location = 0) in vec3 pos;
+layout(location = 2) in vec4 color;
+layout(location = 4) in vec3 translation;
+layout(location = 5) in float scale;
+
+layout(location = 0) out vec4 v_color;
+
+layout(push_constant) uniform PushConstants {
+    mat4 perspective;
+    mat4 view;
+} pc;
+
+void main() {
+    // the rows of the view rotation are the camera axes in world space, so
+    // the mesh is always facing the camera
+    vec3 right = vec3(pc.view[0][0], pc.view[1][0], pc.view[2][0]);
+    vec3 up = vec3(pc.view[0][1], pc.view[1][1], pc.view[2][1]);
+    vec3 world_position = translation + (right * pos.x + up * pos.y) * scale;
+
+    gl_Position = pc.perspective * pc.view * vec4(world_position, 1);
+
+    v_color = color;
+}
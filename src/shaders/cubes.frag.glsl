@@ -2,14 +2,57 @@
 
 layout(location = 0) in  vec4 v_color;
 layout(location = 1) in  vec3 v_normal;
+layout(location = 2) flat in vec3 v_light_direction;
+layout(location = 3) flat in float v_ambient;
+layout(location = 4) in vec2 v_tex_coord;
+layout(location = 5) flat in uint v_texture_index;
+layout(location = 6) in float v_view_distance;
+layout(location = 7) flat in vec3 v_fog_color;
+// (density, start)
+layout(location = 8) flat in vec2 v_fog;
+layout(location = 9) in float v_face_brightness;
+layout(location = 10) in vec3 v_world_position;
 
 layout(location = 0) out vec4 f_color;
 
-const vec3 DIRECTION_TO_LIGHT = normalize(vec3(1.0, 3.0, -2.0));
-const float AMBIENT_LIGHT = 0.2;
+// all tiles are square and in a single row
+layout(set = 1, binding = 0) uniform sampler2D atlas;
+
+// the arrays are `MAX_POINT_LIGHTS` long
+layout(set = 0, binding = 1) uniform PointLights {
+    // (x, y, z, radius)
+    vec4 positions[8];
+    // (r, g, b, unused)
+    vec4 colors[8];
+    uint count;
+} point_lights;
 
 void main() {
-    float light_intensity = max(dot(normalize(v_normal), DIRECTION_TO_LIGHT), 0);
+    vec3 normal = normalize(v_normal);
+    float light_intensity = max(dot(normal, v_light_direction), 0);
+
+    vec3 point_light = vec3(0);
+    for (uint i = 0; i < point_lights.count; i++) {
+        vec3 to_light = point_lights.positions[i].xyz - v_world_position;
+        float distance = length(to_light);
+        // fades out smoothly until the radius
+        float attenuation = pow(clamp(1 - distance / point_lights.positions[i].w, 0, 1), 2);
+        float diffuse = max(dot(normal, to_light / max(distance, 0.0001)), 0);
+        point_light += point_lights.colors[i].rgb * diffuse * attenuation;
+    }
+
+    ivec2 atlas_size = textureSize(atlas, 0);
+    float tiles_count = float(atlas_size.x / atlas_size.y);
+    vec2 tile_coord = fract(v_tex_coord);
+    vec2 uv = vec2((float(v_texture_index) + tile_coord.x) / tiles_count, tile_coord.y);
+    vec4 texel = texture(atlas, uv);
+
+    vec3 color = v_color.rgb * texel.rgb * (light_intensity + v_ambient + point_light)
+        * v_face_brightness;
+
+    // exponential fog, `1` is no fog
+    float fog_factor = exp(-v_fog.x * max(v_view_distance - v_fog.y, 0));
+    color = mix(v_fog_color, color, fog_factor);
 
-    f_color = vec4(v_color.rgb * (light_intensity + AMBIENT_LIGHT), v_color.a);
+    f_color = vec4(color, v_color.a * texel.a);
 }
@@ -6,15 +6,65 @@ layout(location = 2) in vec4 color;
 layout(location = 3) in vec3 rotation;
 layout(location = 4) in vec3 translation;
 layout(location = 5) in float scale;
+layout(location = 6) in vec2 tex_coord;
+layout(location = 7) in uint texture_index;
+layout(location = 8) in vec3 size;
+// brightness of the corners of quads (-x-y, +x-y, -x+y, +x+y)
+layout(location = 9) in vec4 ao;
+// index into the faces of `BlockColors`, `6` for meshes that only use `color`
+layout(location = 10) in uint face;
+// `1` if the top of the instance moves with the waves
+layout(location = 11) in float wave;
 
 layout(location = 0) out vec4 v_color;
 layout(location = 1) out vec3 v_normal;
+layout(location = 2) flat out vec3 v_light_direction;
+layout(location = 3) flat out float v_ambient;
+layout(location = 4) out vec2 v_tex_coord;
+layout(location = 5) flat out uint v_texture_index;
+layout(location = 6) out float v_view_distance;
+layout(location = 7) flat out vec3 v_fog_color;
+layout(location = 8) flat out vec2 v_fog;
+layout(location = 9) out float v_face_brightness;
+layout(location = 10) out vec3 v_world_position;
+
+// the depth prepass and the color pass must compute exactly the same depth
+invariant gl_Position;
+
+// only the water pipeline moves the surface of the blocks
+layout(constant_id = 0) const bool WAVES = false;
+// the surface moves down by up to twice this, so it never rises out of the block
+const float WAVE_HEIGHT = 0.06;
+// both sines of the waves repeat after this many seconds (20 pi), the time is
+// wrapped to it so the sines don't lose precision after running for long
+const float WAVE_PERIOD = 62.831853;
 
 layout(set = 0, binding = 0) uniform UniformData {
     mat4 perspective;
     mat4 view;
+    // direction to the light, its length is the light intensity
+    vec3 light_direction;
+    float ambient;
+    vec3 fog_color;
+    // how fast the fog gets thicker after `fog_start`
+    float fog_density;
+    // distance from the camera where the fog starts
+    float fog_start;
+    // `1` to darken the faces based on their direction, `0` to disable it
+    float face_shading;
+    // `1` to apply the ambient occlusion, `0` to disable it
+    float ambient_occlusion;
+    // seconds since the start, for animations
+    float time;
 } u;
 
+// the color of each face of every block type, indexed with
+// `texture_index * 6 + face` (the texture index is the block id), the length
+// must be `FACE_COLORS_LEN` in engine.rs, which is checked when building
+layout(set = 0, binding = 2) uniform BlockColors {
+    vec4 face_colors[24];
+} block_colors;
+
 void main() {
     float c1 = cos(rotation.x);
     float s1 = sin(rotation.x);
@@ -30,13 +80,58 @@ void main() {
         (s2), (-c2 * s1), (c1 * c2) * scale, 0,
         translation, 1
     );
-    gl_Position = u.perspective * u.view * object_transform * vec4(pos, 1);
+    vec4 world_position = object_transform * vec4(pos * size, 1);
+    if (WAVES) {
+        vec3 face_normal = mat3(object_transform) * normal;
+        // the top faces, and the top edge of the side faces
+        bool surface = face_normal.y > 0.5
+            || (face_normal.y > -0.5 && world_position.y > translation.y);
+        if (surface && wave > 0.5) {
+            float time = mod(u.time, WAVE_PERIOD);
+            float height = sin(world_position.x * 0.8 + time * 1.5)
+                + sin(world_position.z * 0.6 + time * 1.1);
+            world_position.y += WAVE_HEIGHT * (height * 0.5 - 1.0);
+        }
+    }
+    vec4 view_position = u.view * world_position;
+    gl_Position = u.perspective * view_position;
 
     // this transformation only works if scaling is uniform 
     // (scaling of x, y, z by the same value), currently, we don't scale so its ok
     // to compute the value correctly we should use transpose(inverse(mat3(transformation)))
     vec3 normal_world_space = normalize(mat3(object_transform) * normal);
 
-    v_color = color;
+    // the instance color tints the face color
+    v_color = face < 6 ? color * block_colors.face_colors[texture_index * 6 + face] : color;
     v_normal = normal_world_space;
+    // the uniform is only visible to the vertex stage, so we pass
+    // the light to the fragment shader from here
+    v_light_direction = u.light_direction;
+    v_ambient = u.ambient;
+    // repeat the texture over stretched faces
+    v_tex_coord = tex_coord * size.xy;
+    v_texture_index = texture_index;
+    // the distance is taken in view space, not from the (reversed) depth
+    v_view_distance = length(view_position.xyz);
+    v_world_position = world_position.xyz;
+    v_fog_color = u.fog_color;
+    v_fog = vec2(u.fog_density, u.fog_start);
+
+    // top is the brightest, then the sides, and the bottom is the darkest
+    float face_brightness;
+    if (normal_world_space.y > 0.5) {
+        face_brightness = 1.0;
+    } else if (normal_world_space.y < -0.5) {
+        face_brightness = 0.5;
+    } else if (abs(normal_world_space.z) > 0.5) {
+        face_brightness = 0.8;
+    } else {
+        face_brightness = 0.6;
+    }
+    // the corners of quads are at `-0.5` and `0.5`
+    vec2 corner = pos.xy + 0.5;
+    float corner_ao = mix(mix(ao.x, ao.y, corner.x), mix(ao.z, ao.w, corner.x), corner.y);
+
+    v_face_brightness = mix(1.0, face_brightness, u.face_shading)
+        * mix(1.0, corner_ao, u.ambient_occlusion);
 }
@@ -0,0 +1,19 @@
+#version 450
+
+layout(location = 0) out vec4 v_color;
+
+layout(push_constant) uniform PushConstants {
+    vec4 top;
+    vec4 bottom;
+} pc;
+
+void main() {
+    // a single triangle covering the whole screen
+    vec2 p = vec2((gl_VertexIndex << 1) & 2, gl_VertexIndex & 2) * 2 - 1;
+
+    // at the far plane (reversed depth), behind everything
+    gl_Position = vec4(p, 0, 1);
+
+    // `y` is `-1` at the top of the screen
+    v_color = mix(pc.top, pc.bottom, (p.y + 1) / 2);
+}
@@ -0,0 +1,59 @@
+use std::f32::consts::PI;
+
+use cgmath::{InnerSpace, Vector3};
+
+const NIGHT_SKY: [f32; 3] = [0.01, 0.01, 0.05];
+const DAY_SKY: [f32; 3] = [0., 0.7, 1.];
+const SUNSET_SKY: [f32; 3] = [1., 0.45, 0.15];
+
+fn lerp(a: [f32; 3], b: [f32; 3], t: f32) -> [f32; 3] {
+    [
+        a[0] + (b[0] - a[0]) * t,
+        a[1] + (b[1] - a[1]) * t,
+        a[2] + (b[2] - a[2]) * t,
+    ]
+}
+
+/// How the background behind the blocks is drawn
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Sky {
+    /// cleared with [`sky_color`] at the current time of day
+    DayCycle,
+    /// cleared with a fixed color
+    Solid([f32; 4]),
+    /// a vertical gradient from the `top` to the `bottom` of the screen
+    Gradient { top: [f32; 4], bottom: [f32; 4] },
+}
+
+/// The direction to the sun at `time_of_day`, where `0` is midnight,
+/// `0.25` is sunrise, `0.5` is noon and `0.75` is sunset.
+///
+/// The sun rises from `+x` and sets at `-x`, with a slight tilt
+/// so it's never directly above.
+pub(crate) fn sun_direction(time_of_day: f32) -> Vector3<f32> {
+    let angle = (time_of_day - 0.25) * 2. * PI;
+
+    Vector3::new(angle.cos(), angle.sin(), -0.3).normalize()
+}
+
+/// How much of the sun light reaches the world, `0` at night and `1` at day
+pub(crate) fn daylight(time_of_day: f32) -> f32 {
+    let elevation = sun_direction(time_of_day).y;
+
+    // smoothstep around the horizon
+    let t = ((elevation + 0.1) / 0.4).clamp(0., 1.);
+    t * t * (3. - 2. * t)
+}
+
+/// The clear color of the sky at `time_of_day`, orange around sunrise and
+/// sunset
+pub(crate) fn sky_color(time_of_day: f32) -> [f32; 4] {
+    let elevation = sun_direction(time_of_day).y;
+
+    let base = lerp(NIGHT_SKY, DAY_SKY, daylight(time_of_day));
+    // only tint when the sun is close to the horizon
+    let tint = (1. - elevation.abs() / 0.25).max(0.) * 0.8;
+    let [r, g, b] = lerp(base, SUNSET_SKY, tint);
+
+    [r, g, b, 1.]
+}
@@ -0,0 +1,181 @@
+use crate::block::BlockType;
+
+/// Returns the block to use for terrain layers, `depth` is the distance
+/// from the surface block (0 for the top most block)
+pub(crate) const fn layer_block(depth: i32) -> BlockType {
+    match depth {
+        0 => BlockType::Grass,
+        1..=3 => BlockType::Dirt,
+        _ => BlockType::Stone,
+    }
+}
+
+/// Deterministic hash of a grid point, used to pick gradients
+const fn hash(x: i32, z: i32, seed: u32) -> u32 {
+    let mut h = seed ^ (x as u32).wrapping_mul(0x27d4_eb2d) ^ (z as u32).wrapping_mul(0x1656_67b1);
+    h = (h ^ (h >> 15)).wrapping_mul(0x2c1b_3c6d);
+    h = (h ^ (h >> 12)).wrapping_mul(0x297a_2d39);
+    h ^ (h >> 15)
+}
+
+/// Deterministic value in `[0, 1]` for the cube at `(x, y, z)`, used to vary
+/// the brightness of cubes so flat areas aren't a single color
+pub(crate) fn speckle(x: i32, y: i32, z: i32, seed: u32) -> f32 {
+    (hash(x, z, hash(y, 0, seed)) & 0xffff) as f32 / 0xffff as f32
+}
+
+/// 2D Perlin (gradient) noise
+pub(crate) struct Noise {
+    seed: u32,
+}
+
+impl Noise {
+    pub fn new(seed: u32) -> Self {
+        Self { seed }
+    }
+
+    fn gradient(&self, x: i32, z: i32) -> [f32; 2] {
+        const DIAGONAL: f32 = std::f32::consts::FRAC_1_SQRT_2;
+        const GRADIENTS: [[f32; 2]; 8] = [
+            [1., 0.],
+            [-1., 0.],
+            [0., 1.],
+            [0., -1.],
+            [DIAGONAL, DIAGONAL],
+            [-DIAGONAL, DIAGONAL],
+            [DIAGONAL, -DIAGONAL],
+            [-DIAGONAL, -DIAGONAL],
+        ];
+
+        GRADIENTS[(hash(x, z, self.seed) % 8) as usize]
+    }
+
+    /// Noise value at the point, in the range `[-1, 1]`
+    pub fn get(&self, x: f32, z: f32) -> f32 {
+        fn fade(t: f32) -> f32 {
+            t * t * t * (t * (t * 6. - 15.) + 10.)
+        }
+
+        fn lerp(a: f32, b: f32, t: f32) -> f32 {
+            a + (b - a) * t
+        }
+
+        let x0 = x.floor();
+        let z0 = z.floor();
+        let fx = x - x0;
+        let fz = z - z0;
+        let (x0, z0) = (x0 as i32, z0 as i32);
+
+        let corner = |cx: i32, cz: i32| {
+            let g = self.gradient(x0 + cx, z0 + cz);
+            g[0] * (fx - cx as f32) + g[1] * (fz - cz as f32)
+        };
+
+        let u = fade(fx);
+        let v = fade(fz);
+
+        let value = lerp(
+            lerp(corner(0, 0), corner(1, 0), u),
+            lerp(corner(0, 1), corner(1, 1), u),
+            v,
+        );
+
+        // the maximum value of 2D perlin noise is `sqrt(2) / 2`
+        (value * std::f32::consts::SQRT_2).clamp(-1., 1.)
+    }
+
+    /// Sum of multiple noise layers (octaves), each with double the frequency
+    /// and half the amplitude of the previous one, in the range `[-1, 1]`
+    pub fn fractal(&self, x: f32, z: f32, octaves: u32) -> f32 {
+        let mut value = 0.;
+        let mut amplitude = 1.;
+        let mut frequency = 1.;
+        let mut total_amplitude = 0.;
+
+        for _ in 0..octaves {
+            value += self.get(x * frequency, z * frequency) * amplitude;
+            total_amplitude += amplitude;
+            amplitude *= 0.5;
+            frequency *= 2.;
+        }
+
+        value / total_amplitude
+    }
+}
+
+/// Generates terrain heights from noise, the heights only depend on the
+/// world position and the seed, so chunks generated separately line up.
+pub(crate) struct TerrainGenerator {
+    noise: Noise,
+    pub base_height: i32,
+    pub amplitude: f32,
+    pub scale: f32,
+    pub octaves: u32,
+}
+
+impl TerrainGenerator {
+    pub fn new(seed: u32) -> Self {
+        Self {
+            noise: Noise::new(seed),
+            base_height: 60,
+            amplitude: 20.,
+            scale: 1. / 64.,
+            octaves: 4,
+        }
+    }
+
+    /// The number of blocks in the column at `(x, z)`
+    pub fn height_at(&self, x: i32, z: i32) -> i32 {
+        let noise = self
+            .noise
+            .fractal(x as f32 * self.scale, z as f32 * self.scale, self.octaves);
+
+        (self.base_height + (noise * self.amplitude).round() as i32).clamp(1, 256)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_same_seed_gives_the_same_speckle() {
+        let points = (-8..8).flat_map(|x| (0..4).map(move |y| (x, y, 3 - x)));
+
+        let mut differs = false;
+        for (x, y, z) in points {
+            let value = speckle(x, y, z, 42);
+            assert!((0. ..=1.).contains(&value));
+            assert_eq!(value, speckle(x, y, z, 42));
+            differs |= value != speckle(x, y, z, 43);
+        }
+        assert!(differs, "a different seed should change the pattern");
+    }
+
+    #[test]
+    fn terrain_heights_are_smooth_and_repeatable() {
+        let generator = TerrainGenerator::new(7);
+        let same_seed = TerrainGenerator::new(7);
+
+        for x in -100..100 {
+            let height = generator.height_at(x, 30);
+            assert_eq!(height, same_seed.height_at(x, 30));
+            assert!((1..=256).contains(&height));
+            // neighbouring columns don't jump, even across chunks
+            assert!((height - generator.height_at(x + 1, 30)).abs() <= 3);
+            assert!((height - generator.height_at(x, 31)).abs() <= 3);
+        }
+    }
+
+    #[test]
+    fn noise_stays_in_range() {
+        let noise = Noise::new(3);
+        for i in 0..1000 {
+            let (x, z) = (i as f32 * 0.37 - 150., i as f32 * 0.61 - 200.);
+            assert!((-1. ..=1.).contains(&noise.get(x, z)));
+            assert!((-1. ..=1.).contains(&noise.fractal(x, z, 4)));
+        }
+        // the noise is zero on the grid points
+        assert_eq!(noise.get(4., -9.), 0.);
+    }
+}
@@ -0,0 +1,110 @@
+//! A minimal text renderer using seven segment characters made of lines,
+//! so it can be drawn with the ui lines pipeline.
+
+// the segments of a character:
+//
+//  -a-
+// f   b
+//  -g-
+// e   c
+//  -d-
+const A: u8 = 1 << 0;
+const B: u8 = 1 << 1;
+const C: u8 = 1 << 2;
+const D: u8 = 1 << 3;
+const E: u8 = 1 << 4;
+const F: u8 = 1 << 5;
+const G: u8 = 1 << 6;
+
+/// The segments to draw for `c`, unsupported characters are drawn empty
+const fn segments(c: char) -> u8 {
+    match c {
+        '0' => A | B | C | D | E | F,
+        '1' => B | C,
+        '2' => A | B | G | E | D,
+        '3' => A | B | G | C | D,
+        '4' => F | G | B | C,
+        '5' | 'S' => A | F | G | C | D,
+        '6' => A | F | G | E | C | D,
+        '7' => A | B | C,
+        '8' => A | B | C | D | E | F | G,
+        '9' => A | B | C | D | F | G,
+        'C' => A | F | E | D,
+        'E' => A | F | G | E | D,
+        'F' => A | F | G | E,
+        // `N` and `W` are approximated, they look like `∩` and `U`
+        'N' => A | B | C | E | F,
+        'P' => A | B | F | G | E,
+        'W' => B | C | D | E | F,
+        // lowercase letters are approximated, `w` looks like `u`
+        'a' => A | B | C | D | E | G,
+        'd' => B | C | D | E | G,
+        'e' => A | B | D | E | F | G,
+        'g' => A | B | C | D | F | G,
+        'i' => C,
+        'n' => C | E | G,
+        'o' => C | D | E | G,
+        'r' => E | G,
+        's' => A | F | G | C | D,
+        't' => F | G | E | D,
+        'u' | 'w' => C | D | E,
+        'x' => B | C | E | F | G,
+        'y' => B | C | D | F | G,
+        'z' => A | B | G | E | D,
+        '-' => G,
+        _ => 0,
+    }
+}
+
+/// Returns pairs of points (in pixels) for the lines drawing `text`, with
+/// the top left at `origin`, each character is `size` pixels high.
+pub(crate) fn text_lines(text: &str, origin: [f32; 2], size: f32) -> Vec<[f32; 2]> {
+    let width = size / 2.;
+    let half = size / 2.;
+    let spacing = size * 0.3;
+
+    let mut lines = Vec::new();
+    let mut x = origin[0];
+    let y = origin[1];
+
+    for c in text.chars() {
+        if c == '.' {
+            // a short line at the bottom
+            lines.push([x, y + size]);
+            lines.push([x + size * 0.1, y + size]);
+            x += spacing;
+            continue;
+        }
+        if c == '+' {
+            // a cross in the middle of the character
+            lines.push([x, y + half]);
+            lines.push([x + width, y + half]);
+            lines.push([x + width / 2., y + half - width / 2.]);
+            lines.push([x + width / 2., y + half + width / 2.]);
+            x += width + spacing;
+            continue;
+        }
+
+        let segments = segments(c);
+        let all = [
+            (A, [0., 0.], [width, 0.]),
+            (B, [width, 0.], [width, half]),
+            (C, [width, half], [width, size]),
+            (D, [0., size], [width, size]),
+            (E, [0., half], [0., size]),
+            (F, [0., 0.], [0., half]),
+            (G, [0., half], [width, half]),
+        ];
+
+        for (segment, start, end) in all {
+            if segments & segment != 0 {
+                lines.push([x + start[0], y + start[1]]);
+                lines.push([x + end[0], y + end[1]]);
+            }
+        }
+
+        x += width + spacing;
+    }
+
+    lines
+}
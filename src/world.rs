@@ -1,59 +1,225 @@
-use std::{cell::Cell, collections::HashMap, rc::Rc};
-
-use cgmath::{InnerSpace, Point2, Point3, Vector3};
+use std::{
+    cell::Cell,
+    collections::{HashMap, HashSet, VecDeque},
+    fmt,
+    fs::File,
+    io::{self, BufReader, BufWriter, Read, Write},
+    path::{Path, PathBuf},
+    rc::Rc,
+    sync::mpsc,
+    time::Instant,
+};
+
+use cgmath::{InnerSpace, Point2, Point3, Rad, Vector3};
+
+mod cubes;
+
+use crate::{
+    block::{BlockFace, BlockShape, BlockType},
+    camera::CameraState,
+    heightmap::{Heightmap, HeightmapTerrain},
+    math::Aabb,
+    object::{slab::Slab, InstancesMesh},
+    schematic::Schematic,
+    terrain::{self, layer_block, TerrainGenerator},
+    worker::WorkerPool,
+};
+
+use cubes::ChunkCubes;
+
+/// The height of the world in cubes, unless set with [`World::set_height`]
+pub(crate) const DEFAULT_HEIGHT: i32 = 256;
+/// The largest height [`World::set_height`] accepts, and saves can have
+pub(crate) const MAX_HEIGHT: i32 = 4096;
+
+/// Magic bytes at the start of saved world files
+const SAVE_MAGIC: &[u8; 4] = b"MCWD";
+/// version 2 added the shape of the cubes, version 3 the world height, and
+/// version 4 the camera
+const SAVE_VERSION: u32 = 4;
+
+/// A cube position outside the chunk (or above or below the world) it's
+/// placed in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct OutOfBounds(pub Point3<i32>);
+
+impl fmt::Display for OutOfBounds {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "cube position {:?} is out of bounds", self.0)
+    }
+}
 
-use crate::object::{cube::Cube, InstancesMesh};
+/// The most blocks [`World::flood_fill`] replaces at once
+const FLOOD_FILL_LIMIT: usize = 1 << 16;
+
+/// Offsets of the 6 neighbours of a cube
+const NEIGHBOURS: [Vector3<i32>; 6] = [
+    Vector3::new(1, 0, 0),
+    Vector3::new(-1, 0, 0),
+    Vector3::new(0, 1, 0),
+    Vector3::new(0, -1, 0),
+    Vector3::new(0, 0, 1),
+    Vector3::new(0, 0, -1),
+];
+
+/// The mesh of a chunk, by default the faces of the cubes are merged into
+/// bigger quads, but with the `per_cube_mesh` feature each visible cube
+/// is its own instance
+#[cfg(not(feature = "per_cube_mesh"))]
+pub(crate) type ChunkMesh = InstancesMesh<crate::object::quad::Quad>;
+#[cfg(feature = "per_cube_mesh")]
+pub(crate) type ChunkMesh = InstancesMesh<crate::object::cube::Cube>;
+
+/// Returns `true` if the face of `block` touching `neighbour` can't be seen,
+/// transparent blocks only hide the faces of other transparent blocks, and
+/// only full blocks hide faces
+fn face_hidden(block: BlockType, neighbour: Option<ChunkCube>) -> bool {
+    neighbour.is_some_and(|neighbour| {
+        neighbour.shape.is_full() && (!neighbour.block.is_transparent() || block.is_transparent())
+    })
+}
 
-const Y_STRIDE: i32 = 16;
-const Z_STRIDE: i32 = 16 * 256;
+/// Number of cubes in the `x` and `z` axes of a chunk
+pub(crate) const CHUNK_SIZE: i32 = 16;
 
-/// Helper function to convert an array index to a chunk position
-const fn index_to_chunk_pos(i: usize) -> Point3<i32> {
+/// Helper function to convert an array index to a position inside a chunk
+/// of `height` cubes
+const fn index_to_chunk_pos(i: usize, height: i32) -> Point3<i32> {
+    let height = height as usize;
+    let size = CHUNK_SIZE as usize;
     Point3::new(
-        (i % 16) as i32,
-        ((i / 16) % 256) as i32,
-        (i / 16 / 256) as i32,
+        (i % size) as i32,
+        ((i / size) % height) as i32,
+        (i / size / height) as i32,
     )
 }
 
-/// Helper function to convert position inside a chunk to an array index
-const fn chunk_pos_to_index(chunk_pos: Point3<i32>) -> usize {
-    (chunk_pos.x + chunk_pos.y * Y_STRIDE + chunk_pos.z * Z_STRIDE) as usize
+/// Helper function to convert position inside a chunk of `height` cubes
+/// to an array index
+const fn chunk_pos_to_index(chunk_pos: Point3<i32>, height: i32) -> usize {
+    (chunk_pos.x + chunk_pos.y * CHUNK_SIZE + chunk_pos.z * CHUNK_SIZE * height) as usize
 }
 
 /// Helper function to convert point to the chunk that contains it
-const fn chunk_id(pos: Point3<i32>) -> (i32, i32) {
-    (pos.x.div_euclid(16) * 16, pos.z.div_euclid(16) * 16)
+pub(crate) const fn chunk_id(pos: Point3<i32>) -> (i32, i32) {
+    (
+        pos.x.div_euclid(CHUNK_SIZE) * CHUNK_SIZE,
+        pos.z.div_euclid(CHUNK_SIZE) * CHUNK_SIZE,
+    )
+}
+
+/// The ids of the chunks in the square of `radius` around `pos`, even if
+/// they don't exist
+fn chunk_ids_around(pos: Point2<i32>, radius: f32) -> impl Iterator<Item = (i32, i32)> {
+    let chunk_containing_pos = chunk_id(Point3::new(pos.x, 0, pos.y));
+    let radius_chunks = (radius / CHUNK_SIZE as f32).ceil() as i32;
+
+    (-radius_chunks..=radius_chunks).flat_map(move |x| {
+        (-radius_chunks..=radius_chunks).map(move |y| {
+            (
+                chunk_containing_pos.0 + x * CHUNK_SIZE,
+                chunk_containing_pos.1 + y * CHUNK_SIZE,
+            )
+        })
+    })
 }
 
 #[derive(Clone, Copy)]
 pub(crate) struct ChunkCube {
-    color: [f32; 4],
+    block: BlockType,
+    shape: BlockShape,
+    // only the per cube mesh can draw rotated cubes
+    #[cfg_attr(not(feature = "per_cube_mesh"), allow(dead_code))]
     rotation: [f32; 3],
 }
 
+impl ChunkCube {
+    pub fn block(&self) -> BlockType {
+        self.block
+    }
+
+    pub fn shape(&self) -> BlockShape {
+        self.shape
+    }
+}
+
 pub(crate) struct Chunk {
     start: Point2<i32>,
-    cubes: Box<[Option<ChunkCube>; 16 * 256 * 16]>,
-
-    mesh: InstancesMesh<Cube>,
+    // number of cubes in the `y` axis
+    height: i32,
+    cubes: ChunkCubes,
+
+    mesh: ChunkMesh,
+    // the faces of transparent blocks, drawn after all the opaque meshes
+    transparent_mesh: ChunkMesh,
+    // blocks that are not cubes can't be merged, so they have their own instances
+    slab_mesh: InstancesMesh<Slab>,
+    // only the top faces of the columns, drawn instead of the other meshes
+    // when the chunk is far away
+    surface_mesh: ChunkMesh,
     dirty: bool,
+    // increased on every change, used to know if a mesh built in the
+    // background is newer than the current one
+    version: u64,
+    mesh_version: u64,
     world_dirty_ref: Rc<Cell<bool>>,
+    // the last version given to any chunk of the world
+    world_last_version: Rc<Cell<u64>>,
+    // inside `begin_bulk` and `end_bulk`, changes only mark the chunk
+    // dirty once at the end
+    bulk: bool,
+    bulk_changed: bool,
+    // if set, the brightness of every cube is varied from this seed
+    color_seed: Option<u32>,
+    // changed with the world editing methods or loaded from a save, the
+    // other chunks can be generated again, so they are not kept when unloaded
+    edited: bool,
+    // loaded while moving around, so it fades in instead of popping in
+    fade_in: bool,
+    // when the first mesh of a fading chunk was ready, the fade starts from it
+    load_time: Option<Instant>,
 }
 
 impl Chunk {
-    fn new(start: Point2<i32>, world_dirty_ref: Rc<Cell<bool>>) -> Self {
+    fn new(
+        start: Point2<i32>,
+        height: i32,
+        world_dirty_ref: Rc<Cell<bool>>,
+        world_last_version: Rc<Cell<u64>>,
+    ) -> Self {
         world_dirty_ref.set(true);
+        // versions are unique in the world, so the meshes still being built
+        // for a removed chunk with the same id are older than this one
+        let mesh_version = world_last_version.get();
+        world_last_version.set(mesh_version + 1);
         Self {
-            cubes: Box::new([None; 16 * 256 * 16]),
+            cubes: ChunkCubes::new((CHUNK_SIZE * CHUNK_SIZE * height) as usize),
             start,
+            height,
 
             mesh: InstancesMesh::new().unwrap(),
+            transparent_mesh: InstancesMesh::new().unwrap(),
+            slab_mesh: InstancesMesh::new().unwrap(),
+            surface_mesh: InstancesMesh::new().unwrap(),
             dirty: true,
+            version: mesh_version + 1,
+            mesh_version,
             world_dirty_ref,
+            world_last_version,
+            bulk: false,
+            bulk_changed: false,
+            color_seed: None,
+            edited: false,
+            fade_in: false,
+            load_time: None,
         }
     }
 
+    /// Number of cells in the chunk, empty or not
+    fn cells_count(&self) -> usize {
+        (CHUNK_SIZE * CHUNK_SIZE * self.height) as usize
+    }
+
     fn in_relative_chunk_pos(&self, pos: Point3<i32>) -> Point3<i32> {
         pos - Vector3::new(self.start.x, 0, self.start.y)
     }
@@ -62,11 +228,11 @@ impl Chunk {
         let chunk_pos = self.in_relative_chunk_pos(pos);
 
         if chunk_pos.x >= 0
-            && chunk_pos.x < 16
+            && chunk_pos.x < CHUNK_SIZE
             && chunk_pos.y >= 0
-            && chunk_pos.y < 256
+            && chunk_pos.y < self.height
             && chunk_pos.z >= 0
-            && chunk_pos.z < 16
+            && chunk_pos.z < CHUNK_SIZE
         {
             Some(chunk_pos)
         } else {
@@ -79,87 +245,193 @@ impl Chunk {
         &self.start
     }
 
-    pub fn push_cube(&mut self, cube: Cube) {
-        let position = cube.center.cast::<i32>().unwrap();
-        // must be inside the chunk
-        let chunk_position = self.in_chunk_pos(position).unwrap();
+    pub fn push_cube(&mut self, pos: Point3<i32>, block: BlockType) -> Result<(), OutOfBounds> {
+        self.push_cube_with_shape(pos, block, BlockShape::Cube)
+    }
+
+    pub fn push_cube_with_shape(
+        &mut self,
+        pos: Point3<i32>,
+        block: BlockType,
+        shape: BlockShape,
+    ) -> Result<(), OutOfBounds> {
+        self.set_cube(pos, block, shape)?;
+        self.mark_dirty();
+        Ok(())
+    }
+
+    /// Same as [`Chunk::push_cube_with_shape`] but doesn't mark the chunk
+    /// as dirty, used when changing many cubes at once
+    fn set_cube(
+        &mut self,
+        pos: Point3<i32>,
+        block: BlockType,
+        shape: BlockShape,
+    ) -> Result<(), OutOfBounds> {
+        let chunk_position = self.in_chunk_pos(pos).ok_or(OutOfBounds(pos))?;
+
+        let index = chunk_pos_to_index(chunk_position, self.height);
+
+        self.cubes.set(
+            index,
+            Some(ChunkCube {
+                block,
+                shape,
+                rotation: [0., 0., 0.],
+            }),
+        );
+        Ok(())
+    }
 
-        let index = chunk_pos_to_index(chunk_position);
+    /// Returns the removed cube, `None` if there was no cube at `pos`
+    pub fn remove_cube(&mut self, pos: Point3<i32>) -> Result<Option<ChunkCube>, OutOfBounds> {
+        let chunk_position = self.in_chunk_pos(pos).ok_or(OutOfBounds(pos))?;
 
-        self.cubes[index] = Some(ChunkCube {
-            color: cube.color,
-            rotation: cube.rotation,
-        });
+        let index = chunk_pos_to_index(chunk_position, self.height);
 
-        self.dirty = true;
-        self.world_dirty_ref.set(true);
+        let removed = self.cubes.get(index);
+        self.cubes.set(index, None);
+        self.mark_dirty();
+        Ok(removed)
     }
 
-    pub fn remove_cube(&mut self, pos: Point3<i32>) {
-        // must be inside the chunk
-        let chunk_position = self.in_chunk_pos(pos).unwrap();
+    /// Starts changing many cubes, the chunk is marked dirty only once
+    /// in [`Chunk::end_bulk`] instead of on every change
+    pub fn begin_bulk(&mut self) {
+        self.bulk = true;
+    }
+
+    pub fn end_bulk(&mut self) {
+        self.bulk = false;
+        if self.bulk_changed {
+            self.bulk_changed = false;
+            self.mark_dirty();
+        }
+    }
 
-        let index = chunk_pos_to_index(chunk_position);
+    /// The mesh needs to be rebuilt
+    fn mark_dirty(&mut self) {
+        if self.bulk {
+            self.bulk_changed = true;
+            return;
+        }
 
-        self.cubes[index] = None;
         self.dirty = true;
+        self.version = self.world_last_version.get() + 1;
+        self.world_last_version.set(self.version);
         self.world_dirty_ref.set(true);
     }
 
-    fn add_to_mesh(&mut self, mesh: &mut InstancesMesh<Cube>) {
-        if self.dirty {
-            self.mesh = InstancesMesh::new().unwrap();
-            self.dirty = false;
-
-            for (i, cube) in self.cubes.iter().enumerate() {
-                if let Some(cube) = cube {
-                    let chunk_pos = index_to_chunk_pos(i);
-
-                    let is_edge = chunk_pos.x == 0
-                        || chunk_pos.x == 15
-                        || chunk_pos.y == 0
-                        || chunk_pos.y == 255
-                        || chunk_pos.z == 0
-                        || chunk_pos.z == 15;
-
-                    // if cubes on all sides are present, don't draw this one
-                    if is_edge
-                        || self.cubes[i - 1].is_none()
-                        || self.cubes[i + 1].is_none()
-                        || self.cubes[i - Y_STRIDE as usize].is_none()
-                        || self.cubes[i + Y_STRIDE as usize].is_none()
-                        || self.cubes[i - Z_STRIDE as usize].is_none()
-                        || self.cubes[i + Z_STRIDE as usize].is_none()
-                    {
-                        let pos = chunk_pos + Vector3::new(self.start.x, 0, self.start.y);
-                        self.mesh.append_instance(&Cube {
-                            center: pos.cast().unwrap(),
-                            color: cube.color,
-                            rotation: cube.rotation,
-                        });
-                    }
-                }
-            }
+    /// Copies the data needed to build the mesh of this chunk, including the
+    /// cubes touching it from the neighbouring chunks in `world`
+    fn snapshot(&self, world: &World) -> ChunkSnapshot {
+        // (neighbour chunk offset, `x` or `z` of the touching side in that chunk)
+        let last = CHUNK_SIZE - 1;
+        let sides = [
+            (CHUNK_SIZE, 0, 0),
+            (-CHUNK_SIZE, 0, last),
+            (0, CHUNK_SIZE, 0),
+            (0, -CHUNK_SIZE, last),
+        ];
+
+        let borders = sides.map(|(dx, dz, side)| {
+            world
+                .chunks
+                .get(&(self.start.x + dx, self.start.y + dz))
+                .map(|chunk| {
+                    (0..CHUNK_SIZE)
+                        .flat_map(|along| (0..chunk.height).map(move |y| (along, y)))
+                        .map(|(along, y)| {
+                            let pos = if dx != 0 {
+                                Point3::new(side, y, along)
+                            } else {
+                                Point3::new(along, y, side)
+                            };
+                            chunk.cubes.get(chunk_pos_to_index(pos, chunk.height))
+                        })
+                        .collect()
+                })
+        });
+
+        ChunkSnapshot {
+            start: self.start,
+            height: self.height,
+            cubes: self.cubes.to_dense(self.cells_count()),
+            borders,
+            color_seed: self.color_seed,
+        }
+    }
+
+    /// The mesh of the opaque blocks
+    pub fn mesh(&self) -> &ChunkMesh {
+        &self.mesh
+    }
+
+    pub fn transparent_mesh(&self) -> &ChunkMesh {
+        &self.transparent_mesh
+    }
+
+    pub fn slab_mesh(&self) -> &InstancesMesh<Slab> {
+        &self.slab_mesh
+    }
+
+    /// The mesh for the level of detail `level`, `0` is the full opaque
+    /// [`Chunk::mesh`] and any higher level is the surface mesh, which only
+    /// has the top face of the highest cube of every column, transparent and
+    /// slab blocks included. The other meshes of the chunk are not drawn with it
+    pub fn mesh_lod(&self, level: u32) -> &ChunkMesh {
+        match level {
+            0 => &self.mesh,
+            _ => &self.surface_mesh,
         }
+    }
+
+    /// When the chunk was first drawn after being loaded by
+    /// [`World::update_loaded_chunks`], `None` for the chunks that appear
+    /// right away
+    pub fn load_time(&self) -> Option<Instant> {
+        self.load_time
+    }
 
-        mesh.extend_mesh(&self.mesh);
+    /// Number of instances in all the meshes of this chunk
+    pub fn instance_count(&self) -> usize {
+        self.mesh.instances().len()
+            + self.transparent_mesh.instances().len()
+            + self.slab_mesh.instances().len()
+    }
+
+    /// The world space box containing all the cubes of this chunk
+    pub fn bounding_box(&self) -> Aabb {
+        Aabb::new(
+            Point3::new(self.start.x as f32 - 0.5, -0.5, self.start.y as f32 - 0.5),
+            Point3::new(
+                (self.start.x + CHUNK_SIZE) as f32 - 0.5,
+                self.height as f32 - 0.5,
+                (self.start.y + CHUNK_SIZE) as f32 - 0.5,
+            ),
+        )
     }
 
     #[allow(dead_code)]
     pub fn cubes(&self) -> impl Iterator<Item = Point3<i32>> + '_ {
-        self.cubes.iter().enumerate().filter_map(|(i, cube)| {
-            if cube.is_some() {
-                let chunk_pos = index_to_chunk_pos(i);
-                let pos = chunk_pos + Vector3::new(self.start.x, 0, self.start.y);
-                Some(pos)
-            } else {
-                None
-            }
+        self.cubes.iter().map(|(i, _)| {
+            let chunk_pos = index_to_chunk_pos(i, self.height);
+            chunk_pos + Vector3::new(self.start.x, 0, self.start.y)
+        })
+    }
+
+    /// The positions of the cubes in the chunk with their block type
+    pub fn blocks(&self) -> impl Iterator<Item = (Point3<i32>, BlockType)> + '_ {
+        self.cubes.iter().map(|(i, cube)| {
+            let chunk_pos = index_to_chunk_pos(i, self.height);
+            (
+                chunk_pos + Vector3::new(self.start.x, 0, self.start.y),
+                cube.block,
+            )
         })
     }
 
     /// Returns cubes around the given position with the given radius
-    #[allow(dead_code)]
     pub fn cubes_around(
         &self,
         pos: Point3<i32>,
@@ -172,17 +444,17 @@ impl Chunk {
         // get the size of the cube around pos with radius
         let area_cube_radius = radius.ceil() as i32;
         let min_x = (chunk_pos.x - area_cube_radius).max(0);
-        let max_x = (chunk_pos.x + area_cube_radius).min(15);
+        let max_x = (chunk_pos.x + area_cube_radius).min(CHUNK_SIZE - 1);
         let min_y = (chunk_pos.y - area_cube_radius).max(0);
-        let max_y = (chunk_pos.y + area_cube_radius).min(255);
+        let max_y = (chunk_pos.y + area_cube_radius).min(self.height - 1);
         let min_z = (chunk_pos.z - area_cube_radius).max(0);
-        let max_z = (chunk_pos.z + area_cube_radius).min(15);
+        let max_z = (chunk_pos.z + area_cube_radius).min(CHUNK_SIZE - 1);
 
         for x in min_x..=max_x {
             for y in min_y..=max_y {
                 for z in min_z..=max_z {
-                    let index = chunk_pos_to_index(Point3::new(x, y, z));
-                    if self.cubes[index].is_some() {
+                    let index = chunk_pos_to_index(Point3::new(x, y, z), self.height);
+                    if self.cubes.get(index).is_some() {
                         // is inside radius
                         let cube_pos =
                             Point3::new(x, y, z) + Vector3::new(self.start.x, 0, self.start.y);
@@ -203,8 +475,9 @@ impl Chunk {
 // --- Looking at section ---
 #[derive(Debug)]
 enum TraceChunkResult {
-    /// A block was found at the position
-    BlockFound(Point3<i32>, Vector3<i32>),
+    /// A block was found at the position, with the direction we came from
+    /// and the distance along the ray
+    BlockFound(Point3<i32>, Vector3<i32>, f32),
     /// We should move to the next chunk
     ChunkChange((i32, i32)),
     /// Radius exceeded without finding a block, abort search...
@@ -214,7 +487,14 @@ enum TraceChunkResult {
 #[derive(Debug)]
 pub struct CubeLookAt {
     pub cube: Point3<i32>,
+    /// The step the ray took to enter the cube (reversed), this is
+    /// zero if the ray started inside the cube
+    #[allow(dead_code)]
     pub direction: Vector3<i32>,
+    /// The face the ray entered the cube from
+    pub face: BlockFace,
+    /// The distance along the ray to the hit point
+    pub t: f32,
 }
 
 #[derive(Debug)]
@@ -233,13 +513,14 @@ struct BlockRayTracer<'world> {
     dt: Vector3<f32>,
 
     current_chunk: (i32, i32),
-    chunk_inc_dir: (i32, i32),
 
     last_cube: Point3<i32>,
     current_cube: Point3<i32>,
     origin_cube_i32: Point3<i32>,
     cube_inc_dir: Vector3<i32>,
     t_next_cube: Vector3<f32>,
+    /// distance along the ray where we entered `current_cube`
+    t_current: f32,
 
     max_radius_i32: i32,
     path: Vec<Point3<i32>>,
@@ -306,64 +587,48 @@ impl<'world> BlockRayTracer<'world> {
             )
         };
 
-        let chunk_inc_dir = (cube_inc_dir.x * 16, cube_inc_dir.z * 16);
-
         Self {
             world,
             dt: Vector3::new(dt_dx, dt_dy, dt_dz),
 
             current_chunk,
-            chunk_inc_dir,
 
             last_cube: current_cube,
             current_cube,
             origin_cube_i32,
             cube_inc_dir,
             t_next_cube,
+            t_current: 0.,
             max_radius_i32,
             path: Vec::new(),
         }
     }
 
     fn move_to_next_cube(&mut self) -> Option<TraceChunkResult> {
-        const fn chunk_change(dir: i32, val: i32) -> bool {
-            (dir == -1 && val.rem_euclid(16) == 15) || (dir == 1 && val.rem_euclid(16) == 0)
-        }
-
         self.last_cube = self.current_cube;
 
-        if self.t_next_cube.x < self.t_next_cube.y {
+        // step on the axis with the closest cube boundary
+        let axis = if self.t_next_cube.x < self.t_next_cube.y {
             if self.t_next_cube.x < self.t_next_cube.z {
-                self.current_cube.x += self.cube_inc_dir.x;
-                self.t_next_cube.x += self.dt.x;
-                if chunk_change(self.cube_inc_dir.x, self.current_cube.x) {
-                    return Some(TraceChunkResult::ChunkChange((
-                        self.current_chunk.0 + self.chunk_inc_dir.0,
-                        self.current_chunk.1,
-                    )));
-                }
+                0
             } else {
-                self.current_cube.z += self.cube_inc_dir.z;
-                self.t_next_cube.z += self.dt.z;
-                if chunk_change(self.cube_inc_dir.z, self.current_cube.z) {
-                    return Some(TraceChunkResult::ChunkChange((
-                        self.current_chunk.0,
-                        self.current_chunk.1 + self.chunk_inc_dir.1,
-                    )));
-                }
+                2
             }
         } else if self.t_next_cube.y < self.t_next_cube.z {
-            self.current_cube.y += self.cube_inc_dir.y;
-            self.t_next_cube.y += self.dt.y;
+            1
         } else {
-            self.current_cube.z += self.cube_inc_dir.z;
-            self.t_next_cube.z += self.dt.z;
-            if chunk_change(self.cube_inc_dir.z, self.current_cube.z) {
-                return Some(TraceChunkResult::ChunkChange((
-                    self.current_chunk.0,
-                    self.current_chunk.1 + self.chunk_inc_dir.1,
-                )));
-            }
+            2
+        };
+        self.current_cube[axis] += self.cube_inc_dir[axis];
+        self.t_current = self.t_next_cube[axis];
+        self.t_next_cube[axis] += self.dt[axis];
+
+        // the chunk is taken from the cube instead of being stepped along with
+        // it, so crossing a chunk corner diagonally (one axis at a time) can't
+        // leave the two out of sync
+        let chunk = chunk_id(self.current_cube);
+        if chunk != self.current_chunk {
+            return Some(TraceChunkResult::ChunkChange(chunk));
         }
 
         let distance = (self.current_cube - self.origin_cube_i32).magnitude2(); // squared distance
@@ -381,14 +646,15 @@ impl<'world> BlockRayTracer<'world> {
             self.path.push(self.current_cube);
 
             // This will almost always be some, unless we are outside the `y`
-            // range (0-255), then we should just follow the trace until we
+            // world height, then we should just follow the trace until we
             // get back on range.
             if let Some(chunk_pos) = chunk.in_chunk_pos(self.current_cube) {
-                let index = chunk_pos_to_index(chunk_pos);
-                if chunk.cubes[index].is_some() {
+                let index = chunk_pos_to_index(chunk_pos, chunk.height);
+                if chunk.cubes.get(index).is_some() {
                     return TraceChunkResult::BlockFound(
                         self.current_cube,
                         self.last_cube - self.current_cube,
+                        self.t_current,
                     );
                 }
             }
@@ -411,6 +677,21 @@ impl<'world> BlockRayTracer<'world> {
         }
     }
 
+    /// The face looking towards the ray origin on the main axis of the ray,
+    /// used when the ray starts inside a cube
+    fn facing_origin_face(&self) -> BlockFace {
+        // the main axis has the smallest `dt`
+        let normal = if self.dt.x <= self.dt.y && self.dt.x <= self.dt.z {
+            Vector3::new(-self.cube_inc_dir.x, 0, 0)
+        } else if self.dt.y <= self.dt.z {
+            Vector3::new(0, -self.cube_inc_dir.y, 0)
+        } else {
+            Vector3::new(0, 0, -self.cube_inc_dir.z)
+        };
+
+        BlockFace::from_normal(normal).unwrap_or(BlockFace::Top)
+    }
+
     pub fn run(mut self) -> TraceResult {
         let result = loop {
             let result = if let Some(chunk) = self.world.chunks.get(&self.current_chunk) {
@@ -420,8 +701,15 @@ impl<'world> BlockRayTracer<'world> {
             };
 
             match result {
-                TraceChunkResult::BlockFound(cube, direction) => {
-                    break Some(CubeLookAt { cube, direction })
+                TraceChunkResult::BlockFound(cube, direction, t) => {
+                    let face = BlockFace::from_normal(direction)
+                        .unwrap_or_else(|| self.facing_origin_face());
+                    break Some(CubeLookAt {
+                        cube,
+                        direction,
+                        face,
+                        t,
+                    });
                 }
                 TraceChunkResult::ChunkChange(next_chunk) => {
                     self.current_chunk = next_chunk;
@@ -435,149 +723,2213 @@ impl<'world> BlockRayTracer<'world> {
             result_cube: result,
         }
     }
+
+    /// Same as [`BlockRayTracer::run`], but continues after a block is found,
+    /// returning all the blocks on the ray in order
+    pub fn run_all(mut self) -> Vec<Point3<i32>> {
+        let mut cubes = Vec::new();
+
+        loop {
+            let mut result = if let Some(chunk) = self.world.chunks.get(&self.current_chunk) {
+                self.trace_chunk(chunk)
+            } else {
+                self.trace_no_chunk()
+            };
+
+            if let TraceChunkResult::BlockFound(cube, _, _) = result {
+                cubes.push(cube);
+                // skip the found block, or we will find it again
+                match self.move_to_next_cube() {
+                    Some(r) => result = r,
+                    None => continue,
+                }
+            }
+
+            match result {
+                TraceChunkResult::ChunkChange(next_chunk) => {
+                    self.current_chunk = next_chunk;
+                }
+                TraceChunkResult::ExceededRadius => break,
+                TraceChunkResult::BlockFound(..) => unreachable!(),
+            }
+        }
+
+        cubes
+    }
 }
 
-pub(crate) struct World {
-    chunks: HashMap<(i32, i32), Chunk>,
+/// A copy of a chunk and the cubes touching it, used to build the mesh of
+/// the chunk on another thread
+struct ChunkSnapshot {
+    start: Point2<i32>,
+    height: i32,
+    cubes: Box<[Option<ChunkCube>]>,
+    /// the cubes of the neighbouring chunks touching this chunk, in the order
+    /// `+x`, `-x`, `+z`, `-z`, indexed by `y + along * height` where `along` is the
+    /// position along the side, `None` if the neighbour doesn't exist
+    borders: [Option<Vec<Option<ChunkCube>>>; 4],
+    color_seed: Option<u32>,
+}
 
-    mesh: InstancesMesh<Cube>,
-    dirty: Rc<Cell<bool>>,
+/// The meshes built from a [`ChunkSnapshot`]
+struct ChunkMeshes {
+    mesh: ChunkMesh,
+    transparent_mesh: ChunkMesh,
+    slab_mesh: InstancesMesh<Slab>,
+    surface_mesh: ChunkMesh,
 }
 
-impl Default for World {
-    fn default() -> Self {
-        Self {
-            chunks: HashMap::new(),
-            mesh: InstancesMesh::new().unwrap(),
-            dirty: Rc::new(Cell::new(false)),
+impl ChunkSnapshot {
+    /// Returns the cube at `chunk_pos`, which can be outside the chunk by
+    /// one cube in the `x` or `z` axis
+    fn cube_at(&self, chunk_pos: Point3<i32>) -> Option<ChunkCube> {
+        // outside the world is always empty
+        if chunk_pos.y < 0 || chunk_pos.y >= self.height {
+            return None;
+        }
+        // the chunks touching only the corners are not in the snapshot
+        if !(0..CHUNK_SIZE).contains(&chunk_pos.x) && !(0..CHUNK_SIZE).contains(&chunk_pos.z) {
+            return None;
         }
+
+        let (border, along) = match (chunk_pos.x, chunk_pos.z) {
+            (CHUNK_SIZE, z) => (0, z),
+            (-1, z) => (1, z),
+            (x, CHUNK_SIZE) => (2, x),
+            (x, -1) => (3, x),
+            _ => return self.cubes[chunk_pos_to_index(chunk_pos, self.height)],
+        };
+
+        self.borders[border]
+            .as_ref()
+            .and_then(|border| border[(chunk_pos.y + along * self.height) as usize])
     }
-}
 
-impl World {
-    #[allow(dead_code)]
-    pub fn push_cube(&mut self, block: Cube) {
-        let chunk_id = chunk_id(block.center.cast().unwrap());
-        self.chunks
-            .entry(chunk_id)
-            .or_insert_with(|| Chunk::new(chunk_id.into(), self.dirty.clone()))
-            .push_cube(block);
+    /// Returns `true` if `block` at `chunk_pos` is a liquid without liquid
+    /// above it, so the top of its faces moves with the waves. Lower liquid
+    /// blocks stay still, or there would be gaps between them
+    fn liquid_surface(&self, block: BlockType, chunk_pos: Point3<i32>) -> bool {
+        block.is_liquid()
+            && !self
+                .cube_at(chunk_pos + Vector3::unit_y())
+                .is_some_and(|above| above.block.is_liquid())
     }
 
-    #[allow(dead_code)]
-    pub fn remove_cube(&mut self, pos: Point3<i32>) {
-        assert!(pos.y >= 0);
-        let chunk_id = chunk_id(pos.cast().unwrap());
-        let chunk = self
-            .chunks
-            .entry(chunk_id)
-            .or_insert_with(|| Chunk::new(chunk_id.into(), self.dirty.clone()));
+    /// The brightness of the cube at `chunk_pos`, `1` unless the chunk has a
+    /// color seed
+    fn brightness(&self, chunk_pos: Point3<i32>) -> f32 {
+        /// The darkest a cube can be made
+        const MIN_BRIGHTNESS: f32 = 0.88;
+
+        match self.color_seed {
+            Some(seed) => {
+                let x = self.start.x + chunk_pos.x;
+                let z = self.start.y + chunk_pos.z;
+                let speckle = terrain::speckle(x, chunk_pos.y, z, seed);
+                MIN_BRIGHTNESS + (1. - MIN_BRIGHTNESS) * speckle
+            }
+            None => 1.,
+        }
+    }
+
+    /// The ambient occlusion of the 4 corners of a face in front of the cell
+    /// `front`, the face lies on the `u` and `v` axes. From `0` (darkest) to
+    /// `3` (not occluded), in the order `(-u, -v)`, `(+u, -v)`, `(-u, +v)`
+    /// and `(+u, +v)`
+    #[cfg(not(feature = "per_cube_mesh"))]
+    fn face_ao(&self, front: Point3<i32>, u: usize, v: usize) -> [u8; 4] {
+        let occludes = |du: i32, dv: i32| {
+            let mut pos = front;
+            pos[u] += du;
+            pos[v] += dv;
+            self.cube_at(pos)
+                .is_some_and(|cube| cube.shape.is_full() && !cube.block.is_transparent())
+        };
 
-        chunk.remove_cube(pos);
+        [(-1, -1), (1, -1), (-1, 1), (1, 1)].map(|(du, dv)| {
+            let side_u = occludes(du, 0);
+            let side_v = occludes(0, dv);
+            // the corner is fully hidden between the two sides
+            if side_u && side_v {
+                0
+            } else {
+                3 - side_u as u8 - side_v as u8 - occludes(du, dv) as u8
+            }
+        })
     }
 
-    pub fn create_chunk(&mut self, x: i32, y: u32, z: i32, color: [f32; 4]) {
-        let chunk_id = chunk_id(Point3::new(x, 0, z));
-        let start_x = chunk_id.0;
-        let start_y = y;
-        let start_z = chunk_id.1;
+    /// Builds the mesh of the visible faces, for every face direction and every
+    /// slice of the chunk along it, faces of the same block type next to each other
+    /// are merged into a single quad (greedy meshing).
+    ///
+    /// The rotation of the cubes is ignored here.
+    ///
+    /// Only full cubes are merged, the other shapes are in their own mesh.
+    ///
+    /// Faces are only merged if their corners have the same ambient
+    /// occlusion, which is stored in the quad instance for its 4 corners.
+    #[cfg(not(feature = "per_cube_mesh"))]
+    fn build_mesh(&self) -> ChunkMeshes {
+        use crate::object::quad::Quad;
+        use std::f32::consts::{FRAC_PI_2, PI};
+
+        /// Brightness of the ambient occlusion levels
+        const AO_BRIGHTNESS: [f32; 4] = [0.5, 0.7, 0.85, 1.];
+
+        let size = [CHUNK_SIZE, self.height, CHUNK_SIZE];
+
+        let mut mesh = InstancesMesh::new().unwrap();
+        let mut transparent_mesh = InstancesMesh::new().unwrap();
+        let chunk_offset = Vector3::new(self.start.x, 0, self.start.y);
+
+        for face in BlockFace::ALL {
+            // `n` is the axis of the face normal, `u` and `v` are the local
+            // `x` and `y` axes of the quad after rotation
+            let (n, u, v, rotation) = match face {
+                BlockFace::Front => (2, 0, 1, [0., 0., 0.]),
+                BlockFace::Back => (2, 0, 1, [0., PI, 0.]),
+                BlockFace::Right => (0, 2, 1, [0., -FRAC_PI_2, 0.]),
+                BlockFace::Left => (0, 2, 1, [0., FRAC_PI_2, 0.]),
+                BlockFace::Top => (1, 0, 2, [FRAC_PI_2, 0., 0.]),
+                BlockFace::Bottom => (1, 0, 2, [-FRAC_PI_2, 0., 0.]),
+            };
+            // the rotation of the quad can flip its local `x` and `y` axes
+            // against `u` and `v`, the ambient occlusion corners follow them
+            let (flip_u, flip_v) = match face {
+                BlockFace::Back | BlockFace::Left => (true, false),
+                BlockFace::Bottom => (false, true),
+                _ => (false, false),
+            };
+            let normal = face.normal();
+            let (size_u, size_v) = (size[u] as usize, size[v] as usize);
+
+            let mut mask = vec![None; size_u * size_v];
+
+            for slice in 0..size[n] {
+                // collect the visible faces in this slice
+                for b in 0..size_v {
+                    for a in 0..size_u {
+                        let mut chunk_pos = Point3::new(0, 0, 0);
+                        chunk_pos[n] = slice;
+                        chunk_pos[u] = a as i32;
+                        chunk_pos[v] = b as i32;
+
+                        let index = chunk_pos_to_index(chunk_pos, self.height);
+                        mask[a + b * size_u] = self.cubes[index]
+                            .filter(|cube| cube.shape.is_full())
+                            .map(|cube| cube.block)
+                            .filter(|&block| !face_hidden(block, self.cube_at(chunk_pos + normal)))
+                            .map(|block| (block, self.face_ao(chunk_pos + normal, u, v)));
+                    }
+                }
+
+                // merge them into rectangles
+                for b in 0..size_v {
+                    let mut a = 0;
+                    while a < size_u {
+                        let entry = match mask[a + b * size_u] {
+                            Some(entry) => entry,
+                            None => {
+                                a += 1;
+                                continue;
+                            }
+                        };
+
+                        // the waves move the corners of liquid faces, so
+                        // they need a corner on every cube, and cubes with
+                        // varied colors can't share a quad
+                        let merge = !entry.0.is_liquid() && self.color_seed.is_none();
+
+                        let mut width = 1;
+                        while merge
+                            && a + width < size_u
+                            && mask[a + width + b * size_u] == Some(entry)
+                        {
+                            width += 1;
+                        }
 
-        let mut chunk = Chunk::new(chunk_id.into(), self.dirty.clone());
+                        let mut height = 1;
+                        while merge
+                            && b + height < size_v
+                            && (a..a + width)
+                                .all(|i| mask[i + (b + height) * size_u] == Some(entry))
+                        {
+                            height += 1;
+                        }
 
-        for x in start_x..(start_x + 16) {
-            for y in 0..start_y {
-                for z in start_z..(start_z + 16) {
-                    chunk.push_cube(Cube {
-                        center: Point3::new(x, y as i32, z).cast().unwrap(),
-                        color,
-                        rotation: [0.0, 0.0, 0.0],
-                    });
+                        // don't use these faces again
+                        for j in b..b + height {
+                            mask[a + j * size_u..a + width + j * size_u].fill(None);
+                        }
+
+                        let (block, ao) = entry;
+                        // in the order of the quad local corners
+                        let ao = [0, 1, 2, 3].map(|i| {
+                            let x = (i & 1) ^ flip_u as usize;
+                            let y = (i >> 1) ^ flip_v as usize;
+                            AO_BRIGHTNESS[ao[x | y << 1] as usize]
+                        });
+
+                        let mut corner = Point3::new(0, 0, 0);
+                        corner[n] = slice;
+                        corner[u] = a as i32;
+                        corner[v] = b as i32;
+                        let brightness = self.brightness(corner);
+                        let [red, green, blue, alpha] = block.face_color(face);
+
+                        let mut center = Point3::new(0., 0., 0.);
+                        center[n] = slice as f32 + normal[n] as f32 * 0.5;
+                        center[u] = a as f32 + (width - 1) as f32 / 2.;
+                        center[v] = b as f32 + (height - 1) as f32 / 2.;
+
+                        let mesh = if block.is_transparent() {
+                            &mut transparent_mesh
+                        } else {
+                            &mut mesh
+                        };
+                        mesh.append_instance(&Quad {
+                            center: center + chunk_offset.cast().unwrap(),
+                            color: [
+                                red * brightness,
+                                green * brightness,
+                                blue * brightness,
+                                alpha,
+                            ],
+                            rotation,
+                            size: [width as f32, height as f32],
+                            texture_index: block.texture_index(),
+                            ao,
+                            // liquids are not merged, so this is a single cube
+                            wave: self.liquid_surface(block, corner),
+                        });
+
+                        a += width;
+                    }
                 }
             }
         }
 
-        if self.chunks.insert(chunk_id, chunk).is_some() {
-            eprintln!("WARN: Replacing chunk in {:?}", chunk_id);
-        };
-        self.dirty.set(true);
-    }
-
-    #[allow(dead_code)]
-    pub fn chunks(&self) -> impl Iterator<Item = &Chunk> {
-        self.chunks.values()
+        ChunkMeshes {
+            mesh,
+            transparent_mesh,
+            slab_mesh: self.build_slab_mesh(),
+            surface_mesh: self.build_surface_mesh(),
+        }
     }
 
-    #[allow(dead_code)]
-    pub fn chunks_around(&self, pos: Point2<i32>, radius: f32) -> impl Iterator<Item = &Chunk> {
-        let mut chunks = Vec::new();
+    /// Builds the mesh of the visible cubes, cubes on the edge of the chunk
+    /// are checked against the neighbouring chunks.
+    ///
+    /// Only full cubes are in the cube meshes, the other shapes are in their own mesh.
+    #[cfg(feature = "per_cube_mesh")]
+    fn build_mesh(&self) -> ChunkMeshes {
+        use crate::object::cube::Cube;
 
-        let chunk_containing_pos = chunk_id(Point3::new(pos.x, 0, pos.y));
+        let mut mesh = InstancesMesh::new().unwrap();
+        let mut transparent_mesh = InstancesMesh::new().unwrap();
 
-        let radius_chunks = (radius / 16.).ceil() as i32;
+        for (i, cube) in self.cubes.iter().enumerate() {
+            if let Some(cube) = cube.filter(|cube| cube.shape.is_full()) {
+                let chunk_pos = index_to_chunk_pos(i, self.height);
+                let pos = chunk_pos + Vector3::new(self.start.x, 0, self.start.y);
 
-        for x in -radius_chunks..=radius_chunks {
-            for y in -radius_chunks..=radius_chunks {
-                let chunk_id = (
-                    chunk_containing_pos.0 + x * 16,
-                    chunk_containing_pos.1 + y * 16,
-                );
-                if let Some(chunk) = self.chunks.get(&chunk_id) {
-                    chunks.push(chunk);
+                // some of the neighbours may be in other chunks (or outside
+                // the world in the `y` axis, which is always empty)
+                let surrounded = NEIGHBOURS
+                    .iter()
+                    .all(|&dir| face_hidden(cube.block, self.cube_at(chunk_pos + dir)));
+
+                // if cubes on all sides hide it, don't draw this one
+                if !surrounded {
+                    let mesh = if cube.block.is_transparent() {
+                        &mut transparent_mesh
+                    } else {
+                        &mut mesh
+                    };
+                    let brightness = self.brightness(chunk_pos);
+                    mesh.append_instance(&Cube {
+                        center: pos.cast().unwrap(),
+                        color: [brightness, brightness, brightness, 1.],
+                        rotation: cube.rotation,
+                        texture_index: cube.block.texture_index(),
+                        wave: self.liquid_surface(cube.block, chunk_pos),
+                    });
                 }
             }
         }
 
-        chunks.into_iter()
+        ChunkMeshes {
+            mesh,
+            transparent_mesh,
+            slab_mesh: self.build_slab_mesh(),
+            surface_mesh: self.build_surface_mesh(),
+        }
     }
 
-    /// Since we can't create a mut iterator easily because of lifetimes errors,
-    /// we used callback function to mutate chunks if needed.
-    #[allow(dead_code)]
-    pub fn chunks_around_mut_callback(
-        &mut self,
-        pos: Point2<i32>,
-        radius: f32,
-        f: impl Fn(&mut Chunk),
-    ) {
-        let chunk_containing_pos = chunk_id(Point3::new(pos.x, 0, pos.y));
-        let radius_chunks = (radius / 16.).ceil() as i32;
-
-        for x in -radius_chunks..=radius_chunks {
-            for y in -radius_chunks..=radius_chunks {
-                let chunk_id = (
-                    chunk_containing_pos.0 + x * 16,
-                    chunk_containing_pos.1 + y * 16,
-                );
-                if let Some(chunk) = self.chunks.get_mut(&chunk_id) {
-                    f(chunk);
+    /// Builds the mesh of all the slabs, they are always drawn since they
+    /// don't cover a full cube
+    fn build_slab_mesh(&self) -> InstancesMesh<Slab> {
+        let mut mesh = InstancesMesh::new().unwrap();
+
+        for (i, cube) in self.cubes.iter().enumerate() {
+            if let Some(cube) = cube.filter(|cube| cube.shape == BlockShape::Slab) {
+                let chunk_pos = index_to_chunk_pos(i, self.height);
+                let pos = chunk_pos + Vector3::new(self.start.x, 0, self.start.y);
+
+                let brightness = self.brightness(chunk_pos);
+                mesh.append_instance(&Slab {
+                    center: pos.cast().unwrap(),
+                    color: [brightness, brightness, brightness, 1.],
+                    rotation: cube.rotation,
+                    texture_index: cube.block.texture_index(),
+                });
+            }
+        }
+
+        mesh
+    }
+
+    /// Builds the low detail mesh of [`Chunk::mesh_lod`], a single face on top
+    /// of the highest cube of every column. Everything is opaque, so far
+    /// water hides what is under it
+    fn build_surface_mesh(&self) -> ChunkMesh {
+        let mut mesh = InstancesMesh::new().unwrap();
+        let chunk_offset = Vector3::new(self.start.x, 0, self.start.y);
+
+        for x in 0..CHUNK_SIZE {
+            for z in 0..CHUNK_SIZE {
+                let top = (0..self.height).rev().find_map(|y| {
+                    let chunk_pos = Point3::new(x, y, z);
+                    self.cubes[chunk_pos_to_index(chunk_pos, self.height)]
+                        .map(|cube| (chunk_pos, cube))
+                });
+                let (chunk_pos, cube) = match top {
+                    Some(top) => top,
+                    None => continue,
+                };
+
+                let brightness = self.brightness(chunk_pos);
+                let center = (chunk_pos + chunk_offset).cast::<f32>().unwrap();
+
+                #[cfg(not(feature = "per_cube_mesh"))]
+                {
+                    let [red, green, blue, _] = cube.block.face_color(BlockFace::Top);
+                    mesh.append_instance(&crate::object::quad::Quad {
+                        center: center + Vector3::new(0., 0.5, 0.),
+                        color: [red * brightness, green * brightness, blue * brightness, 1.],
+                        rotation: [std::f32::consts::FRAC_PI_2, 0., 0.],
+                        size: [1., 1.],
+                        texture_index: cube.block.texture_index(),
+                        ao: [1.; 4],
+                        wave: false,
+                    });
                 }
+                #[cfg(feature = "per_cube_mesh")]
+                mesh.append_instance(&crate::object::cube::Cube {
+                    center,
+                    color: [brightness, brightness, brightness, 1.],
+                    rotation: cube.rotation,
+                    texture_index: cube.block.texture_index(),
+                    wave: false,
+                });
             }
         }
+
+        mesh
     }
+}
 
-    pub fn cube_looking_at(
-        &self,
-        origin: &Point3<f32>,
-        direction: &Vector3<f32>,
-        max_radius: f32,
-    ) -> TraceResult {
-        let tracer = BlockRayTracer::new(self, origin, direction, max_radius);
+/// A change of a single cube through the [`World`] editing methods, passed to
+/// the callbacks of [`World::on_block_event`]
+#[allow(dead_code)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum BlockEvent {
+    Placed {
+        pos: Point3<i32>,
+        block: BlockType,
+    },
+    /// `block` is the block that was removed
+    Removed {
+        pos: Point3<i32>,
+        block: BlockType,
+    },
+}
 
-        tracer.run()
+/// Number of chunks and mesh instances in the world, and how many of them
+/// were drawn in the last frame, after the culling, the level of detail and
+/// the instance limit
+#[derive(Debug, Default, Clone, Copy)]
+pub(crate) struct RenderStats {
+    pub chunks: usize,
+    pub visible_chunks: usize,
+    pub instances: usize,
+    pub visible_instances: usize,
+}
+
+/// How the terrain of generated chunks is shaped
+enum TerrainSource {
+    /// hills from noise, using the seed of the world
+    Noise,
+    Heightmap(HeightmapTerrain),
+    /// the same layers everywhere, from the bottom, see [`World::generate_superflat`]
+    Superflat(Vec<(BlockType, u32)>),
+}
+
+/// Sizes of the world data, logged periodically to follow the memory usage
+#[derive(Debug, Default, Clone, Copy)]
+pub(crate) struct MemoryReport {
+    pub chunks: usize,
+    pub unloaded_chunks: usize,
+    pub instances: usize,
+    /// bytes used by the cubes of the loaded and unloaded chunks
+    pub cubes_memory: usize,
+    /// bytes of the mesh instances, uploaded to the gpu every frame
+    pub instances_memory: usize,
+}
+
+/// A chunk mesh built in the background, with the version of the chunk it was built from
+type MeshResult = ((i32, i32), u64, ChunkMeshes);
+
+pub(crate) struct World {
+    chunks: HashMap<(i32, i32), Chunk>,
+
+    dirty: Rc<Cell<bool>>,
+    // the last version given to a chunk, shared by all the chunks so that
+    // a mesh is only applied to the chunk it was built from
+    last_version: Rc<Cell<u64>>,
+
+    // used to generate new chunks when streaming
+    seed: u32,
+    // the average height of generated terrain
+    base_height: i32,
+    // where the shape of generated chunks comes from
+    terrain: TerrainSource,
+    // number of cubes in the `y` axis of all chunks
+    height: i32,
+    // the cubes of chunks that were unloaded, so that changes to them are
+    // kept when they are loaded again
+    unloaded_chunks: HashMap<(i32, i32), Vec<(u32, ChunkCube)>>,
+    keep_unloaded_chunks: bool,
+
+    // chunk meshes are built on these threads, and sent back through the channel
+    mesh_workers: WorkerPool,
+    mesh_sender: mpsc::Sender<MeshResult>,
+    mesh_receiver: mpsc::Receiver<MeshResult>,
+    // number of meshes being built
+    pending_meshes: usize,
+
+    // called after every cube placed or removed, but not for generated or
+    // loaded chunks
+    block_callbacks: Vec<Box<dyn FnMut(BlockEvent)>>,
+}
+
+impl Default for World {
+    fn default() -> Self {
+        Self::with_mesh_threads(WorkerPool::default_threads())
     }
 }
 
 impl World {
-    pub(crate) fn mesh(&mut self) -> &InstancesMesh<Cube> {
-        if self.dirty.get() {
-            self.mesh = InstancesMesh::new().unwrap();
+    /// A world building meshes on a single thread, where the generated chunks
+    /// are empty, so the tests only see the cubes they place
+    #[cfg(test)]
+    pub fn empty() -> Self {
+        let mut world = Self::with_mesh_threads(1);
+        world.terrain = TerrainSource::Superflat(Vec::new());
+        world
+    }
+
+    /// Creates an empty world that builds chunk meshes on `threads` threads
+    pub fn with_mesh_threads(threads: usize) -> Self {
+        let (mesh_sender, mesh_receiver) = mpsc::channel();
+
+        Self {
+            chunks: HashMap::new(),
+            dirty: Rc::new(Cell::new(false)),
+            last_version: Rc::new(Cell::new(0)),
+            seed: 0,
+            base_height: 60,
+            terrain: TerrainSource::Noise,
+            height: DEFAULT_HEIGHT,
+            unloaded_chunks: HashMap::new(),
+            keep_unloaded_chunks: true,
+            mesh_workers: WorkerPool::new(threads),
+            mesh_sender,
+            mesh_receiver,
+            pending_meshes: 0,
+            block_callbacks: Vec::new(),
+        }
+    }
+
+    #[allow(dead_code)]
+    pub fn push_cube(&mut self, pos: Point3<i32>, block: BlockType) -> Result<(), OutOfBounds> {
+        self.push_cube_with_shape(pos, block, BlockShape::Cube)
+    }
+
+    /// Places `block` at `pos`, fails if `pos` is above or below the world
+    pub fn push_cube_with_shape(
+        &mut self,
+        pos: Point3<i32>,
+        block: BlockType,
+        shape: BlockShape,
+    ) -> Result<(), OutOfBounds> {
+        // don't create a chunk for a position that can't be in it
+        if !(0..self.height).contains(&pos.y) {
+            return Err(OutOfBounds(pos));
+        }
+
+        let chunk = self.chunk_for_edit(chunk_id(pos));
+        chunk.push_cube_with_shape(pos, block, shape)?;
+        chunk.edited = true;
+        self.mark_neighbour_chunks_dirty(pos);
+        self.emit_block_event(BlockEvent::Placed { pos, block });
+        Ok(())
+    }
+
+    /// Fills the box between `a` and `b` (inclusive) with `block`, the
+    /// corners can be in any order
+    #[allow(dead_code)]
+    pub fn fill_box(&mut self, a: Point3<i32>, b: Point3<i32>, block: BlockType) {
+        let min = Point3::new(a.x.min(b.x), a.y.min(b.y), a.z.min(b.z));
+        let max = Point3::new(a.x.max(b.x), a.y.max(b.y), a.z.max(b.z));
+
+        self.push_cubes(
+            (min.x..=max.x)
+                .flat_map(|x| {
+                    (min.y..=max.y)
+                        .flat_map(move |y| (min.z..=max.z).map(move |z| Point3::new(x, y, z)))
+                })
+                .map(|pos| (pos, block)),
+        );
+    }
+
+    /// Places a straight line of `block` from `from` to `to` (inclusive)
+    #[allow(dead_code)]
+    pub fn set_line(&mut self, from: Point3<i32>, to: Point3<i32>, block: BlockType) {
+        let delta = to - from;
+        let steps = delta.x.abs().max(delta.y.abs()).max(delta.z.abs()).max(1);
+        let step = delta.cast::<f32>().unwrap() / steps as f32;
+
+        self.push_cubes((0..=steps).map(|i| {
+            let offset = step * i as f32;
+            let pos = from
+                + Vector3::new(
+                    offset.x.round() as i32,
+                    offset.y.round() as i32,
+                    offset.z.round() as i32,
+                );
+            (pos, block)
+        }));
+    }
+
+    /// Places the blocks of `schematic` relative to `origin`, blocks outside
+    /// the world height or too far to have a position are skipped
+    pub fn paste_schematic(&mut self, origin: Point3<i32>, schematic: &Schematic) {
+        self.push_cubes(schematic.blocks().iter().filter_map(|&(offset, block)| {
+            // the offsets come from files, and can be anything
+            let pos = Point3::new(
+                origin.x.checked_add(offset.x)?,
+                origin.y.checked_add(offset.y)?,
+                origin.z.checked_add(offset.z)?,
+            );
+            Some((pos, block))
+        }));
+    }
+
+    /// Replaces every `from` block in the box between `a` and `b` (inclusive)
+    /// with `to`, keeping their shapes. Returns the number of replaced blocks.
+    #[allow(dead_code)]
+    pub fn replace_region(
+        &mut self,
+        a: Point3<i32>,
+        b: Point3<i32>,
+        from: BlockType,
+        to: BlockType,
+    ) -> usize {
+        if from == to {
+            return 0;
+        }
+
+        let min = Point3::new(a.x.min(b.x), a.y.min(b.y).max(0), a.z.min(b.z));
+        let max = Point3::new(
+            a.x.max(b.x),
+            a.y.max(b.y).min(self.height - 1),
+            a.z.max(b.z),
+        );
+
+        let replaced = (min.x..=max.x)
+            .flat_map(|x| {
+                (min.y..=max.y)
+                    .flat_map(move |y| (min.z..=max.z).map(move |z| Point3::new(x, y, z)))
+            })
+            .filter(|&pos| self.block_at(pos) == Some(from))
+            .map(|pos| (pos, to, self.shape_at(pos).unwrap_or(BlockShape::Cube)))
+            .collect::<Vec<_>>();
+
+        let count = replaced.len();
+        self.push_cubes_with_shapes(replaced.into_iter());
+        count
+    }
+
+    /// Replaces the blocks connected to `start` by their faces that are the
+    /// same type as it with `to`, keeping their shapes. Returns the number of
+    /// replaced blocks.
+    ///
+    /// Only loaded chunks are filled, and it stops after [`FLOOD_FILL_LIMIT`]
+    /// blocks, so filling the ground doesn't go through the whole world.
+    #[allow(dead_code)]
+    pub fn flood_fill(&mut self, start: Point3<i32>, to: BlockType) -> usize {
+        let from = match self.block_at(start) {
+            Some(block) if block != to => block,
+            _ => return 0,
+        };
 
-            for chunk in self.chunks.values_mut() {
-                chunk.add_to_mesh(&mut self.mesh);
+        let mut visited = HashSet::from([start]);
+        let mut queue = VecDeque::from([start]);
+        let mut replaced = Vec::new();
+        while let Some(pos) = queue.pop_front() {
+            replaced.push((pos, to, self.shape_at(pos).unwrap_or(BlockShape::Cube)));
+            if replaced.len() >= FLOOD_FILL_LIMIT {
+                break;
+            }
+
+            for dir in NEIGHBOURS {
+                let neighbour = pos + dir;
+                if self.block_at(neighbour) == Some(from) && visited.insert(neighbour) {
+                    queue.push_back(neighbour);
+                }
             }
-            self.dirty.set(false);
         }
 
-        &self.mesh
+        let count = replaced.len();
+        self.push_cubes_with_shapes(replaced.into_iter());
+        count
+    }
+
+    /// Places the blocks at their positions, every changed chunk (and neighbour)
+    /// is marked dirty only once at the end. Positions outside the world
+    /// height are ignored.
+    fn push_cubes(&mut self, blocks: impl Iterator<Item = (Point3<i32>, BlockType)>) {
+        self.push_cubes_with_shapes(blocks.map(|(pos, block)| (pos, block, BlockShape::Cube)));
+    }
+
+    /// Same as [`World::push_cubes`], with the shape of every cube
+    fn push_cubes_with_shapes(
+        &mut self,
+        blocks: impl Iterator<Item = (Point3<i32>, BlockType, BlockShape)>,
+    ) {
+        let mut changed_chunks = HashSet::new();
+        // the events are sent after all the cubes are placed
+        let mut placed = Vec::new();
+
+        let height = self.height;
+        for (pos, block, shape) in blocks.filter(|(pos, ..)| pos.y >= 0 && pos.y < height) {
+            let id = chunk_id(pos);
+            let chunk = self.chunk_for_edit(id);
+            chunk
+                .set_cube(pos, block, shape)
+                .expect("the chunk is picked from the position");
+            chunk.edited = true;
+
+            // the neighbours may show or hide faces touching this cube
+            changed_chunks.insert(id);
+            changed_chunks.extend(NEIGHBOURS.iter().map(|&dir| chunk_id(pos + dir)));
+            if !self.block_callbacks.is_empty() {
+                placed.push((pos, block));
+            }
+        }
+
+        for id in changed_chunks {
+            if let Some(chunk) = self.chunks.get_mut(&id) {
+                chunk.mark_dirty();
+            }
+        }
+
+        for (pos, block) in placed {
+            self.emit_block_event(BlockEvent::Placed { pos, block });
+        }
+    }
+
+    /// Removes the cube at `pos` and returns it, `None` if there was no cube.
+    /// Fails if `pos` is above or below the world
+    pub fn remove_cube(&mut self, pos: Point3<i32>) -> Result<Option<ChunkCube>, OutOfBounds> {
+        if !(0..self.height).contains(&pos.y) {
+            return Err(OutOfBounds(pos));
+        }
+
+        let chunk_id = chunk_id(pos);
+        // a chunk that is neither loaded nor kept has nothing placed in it yet,
+        // creating it here would leave a hole where the terrain is generated
+        if !self.chunks.contains_key(&chunk_id) && self.unloaded_chunks.contains_key(&chunk_id) {
+            self.load_chunk(chunk_id);
+        }
+        let chunk = match self.chunks.get_mut(&chunk_id) {
+            Some(chunk) => chunk,
+            None => return Ok(None),
+        };
+
+        let removed = chunk.remove_cube(pos)?;
+        chunk.edited = true;
+        self.mark_neighbour_chunks_dirty(pos);
+        if let Some(cube) = removed {
+            self.emit_block_event(BlockEvent::Removed {
+                pos,
+                block: cube.block,
+            });
+        }
+        Ok(removed)
+    }
+
+    /// Calls `callback` after every cube placed or removed with the editing
+    /// methods of the world, like [`World::push_cube`] and [`World::fill_box`]
+    #[allow(dead_code)]
+    pub fn on_block_event(&mut self, callback: impl FnMut(BlockEvent) + 'static) {
+        self.block_callbacks.push(Box::new(callback));
+    }
+
+    fn emit_block_event(&mut self, event: BlockEvent) {
+        for callback in &mut self.block_callbacks {
+            callback(event);
+        }
+    }
+
+    pub fn has_cube(&self, pos: Point3<i32>) -> bool {
+        self.block_at(pos).is_some()
+    }
+
+    /// The block at `pos`, `None` if the cell is empty or its chunk is not loaded
+    pub fn block_at(&self, pos: Point3<i32>) -> Option<BlockType> {
+        let chunk = self.chunks.get(&chunk_id(pos))?;
+        let chunk_pos = chunk.in_chunk_pos(pos)?;
+
+        chunk
+            .cubes
+            .get(chunk_pos_to_index(chunk_pos, chunk.height))
+            .map(|cube| cube.block)
+    }
+
+    /// The shape of the block at `pos`, `None` if there is no block
+    pub fn shape_at(&self, pos: Point3<i32>) -> Option<BlockShape> {
+        let chunk = self.chunks.get(&chunk_id(pos))?;
+        let chunk_pos = chunk.in_chunk_pos(pos)?;
+
+        chunk
+            .cubes
+            .get(chunk_pos_to_index(chunk_pos, chunk.height))
+            .map(|cube| cube.shape)
+    }
+
+    /// If `pos` is on the edge of its chunk, the chunks next to it should
+    /// rebuild their meshes, since the visibility of their cubes may change
+    fn mark_neighbour_chunks_dirty(&mut self, pos: Point3<i32>) {
+        let current_chunk = chunk_id(pos);
+        for dir in NEIGHBOURS {
+            let neighbour_chunk = chunk_id(pos + dir);
+            if neighbour_chunk != current_chunk {
+                if let Some(chunk) = self.chunks.get_mut(&neighbour_chunk) {
+                    chunk.mark_dirty();
+                }
+            }
+        }
+    }
+
+    /// Creates a flat chunk of height `y`, with grass on top, then 3 layers
+    /// of dirt, and the rest is stone
+    #[allow(dead_code)]
+    pub fn create_chunk(&mut self, x: i32, y: u32, z: i32) {
+        self.create_flat_chunk(x, y, z, None);
+    }
+
+    /// Same as [`Self::create_chunk`], but the brightness of every cube is
+    /// slightly varied, the same `seed` always gives the same pattern.
+    ///
+    /// The variation is only in the mesh, it is not saved with the world.
+    #[allow(dead_code)]
+    pub fn create_chunk_textured(&mut self, x: i32, y: u32, z: i32, seed: u32) {
+        self.create_flat_chunk(x, y, z, Some(seed));
+    }
+
+    fn create_flat_chunk(&mut self, x: i32, y: u32, z: i32, color_seed: Option<u32>) {
+        let chunk_id = chunk_id(Point3::new(x, 0, z));
+        let start_x = chunk_id.0;
+        let start_y = (y as i32).min(self.height);
+        let start_z = chunk_id.1;
+
+        let mut chunk = Chunk::new(
+            chunk_id.into(),
+            self.height,
+            self.dirty.clone(),
+            self.last_version.clone(),
+        );
+        chunk.color_seed = color_seed;
+
+        chunk.begin_bulk();
+        for x in start_x..(start_x + CHUNK_SIZE) {
+            for y in 0..start_y {
+                let block = layer_block(start_y - 1 - y);
+                for z in start_z..(start_z + CHUNK_SIZE) {
+                    chunk
+                        .push_cube(Point3::new(x, y, z), block)
+                        .expect("the cube is inside the chunk");
+                }
+            }
+        }
+        chunk.end_bulk();
+
+        self.insert_chunk(chunk_id, chunk);
+    }
+
+    /// Generates a chunk containing the position `(x, z)` with hills
+    /// generated from noise using `seed`, chunks generated with the same
+    /// seed will connect with each other
+    pub fn generate_chunk(&mut self, x: i32, z: i32, seed: u32) {
+        let mut generator = TerrainGenerator::new(seed);
+        generator.base_height = self.base_height;
+
+        let chunk_id = chunk_id(Point3::new(x, 0, z));
+        let start_x = chunk_id.0;
+        let start_z = chunk_id.1;
+
+        let mut chunk = Chunk::new(
+            chunk_id.into(),
+            self.height,
+            self.dirty.clone(),
+            self.last_version.clone(),
+        );
+
+        chunk.begin_bulk();
+        for x in start_x..(start_x + CHUNK_SIZE) {
+            for z in start_z..(start_z + CHUNK_SIZE) {
+                let height = match &self.terrain {
+                    TerrainSource::Noise => generator.height_at(x, z),
+                    TerrainSource::Heightmap(heightmap) => heightmap.height_at(x, z),
+                    TerrainSource::Superflat(layers) => {
+                        let blocks = layers
+                            .iter()
+                            .flat_map(|&(block, count)| std::iter::repeat_n(block, count as usize))
+                            .take(self.height as usize);
+                        for (y, block) in blocks.enumerate() {
+                            chunk
+                                .push_cube(Point3::new(x, y as i32, z), block)
+                                .expect("the cube is inside the chunk");
+                        }
+                        continue;
+                    }
+                }
+                .min(self.height);
+                for y in 0..height {
+                    chunk
+                        .push_cube(Point3::new(x, y, z), layer_block(height - 1 - y))
+                        .expect("the cube is inside the chunk");
+                }
+            }
+        }
+        chunk.end_bulk();
+
+        self.insert_chunk(chunk_id, chunk);
+    }
+
+    /// Replaces the world with terrain generated from the grayscale PNG image
+    /// at `path`, every pixel is a `scale` by `scale` blocks area, and white
+    /// pixels are `max_height` blocks tall. The image repeats beyond its
+    /// size, the chunks are generated when they are loaded.
+    pub fn generate_from_heightmap(
+        &mut self,
+        path: &Path,
+        scale: f32,
+        max_height: i32,
+    ) -> io::Result<()> {
+        let heightmap = Heightmap::load_from_path(path)?;
+        self.set_heightmap(Some(HeightmapTerrain {
+            heightmap,
+            scale: scale.max(f32::EPSILON),
+            max_height: max_height.clamp(1, self.height),
+        }));
+        Ok(())
+    }
+
+    /// Sets the heightmap used to generate chunks, `None` to use noise again.
+    /// The world is cleared, so all the chunks use the new terrain
+    pub fn set_heightmap(&mut self, heightmap: Option<HeightmapTerrain>) {
+        self.terrain = match heightmap {
+            Some(heightmap) => TerrainSource::Heightmap(heightmap),
+            None => TerrainSource::Noise,
+        };
+        self.clear();
+    }
+
+    /// Replaces the world with flat terrain made of `layers`, each is a block
+    /// and how many blocks tall it is, starting from the bottom of the world.
+    /// Layers above the world height are cut, the chunks are generated when
+    /// they are loaded.
+    ///
+    /// Go back to noise terrain with `set_heightmap(None)`.
+    pub fn generate_superflat(&mut self, layers: &[(BlockType, u32)]) {
+        self.terrain = TerrainSource::Superflat(layers.to_vec());
+        self.clear();
+    }
+
+    fn insert_chunk(&mut self, chunk_id: (i32, i32), chunk: Chunk) {
+        if self.chunks.insert(chunk_id, chunk).is_some() {
+            eprintln!("WARN: Replacing chunk in {:?}", chunk_id);
+        };
+        self.mark_chunks_around_dirty(chunk_id);
+        self.dirty.set(true);
+    }
+
+    /// The average height of the terrain generated by [`World::generate_chunk`]
+    pub fn set_base_height(&mut self, base_height: i32) {
+        self.base_height = base_height;
+    }
+
+    /// Sets the number of cubes in the `y` axis of the world, clamped to
+    /// `1..=MAX_HEIGHT`. Chunks can't be resized, so all the chunks are removed
+    pub fn set_height(&mut self, height: i32) {
+        self.height = height.clamp(1, MAX_HEIGHT);
+        self.chunks.clear();
+        self.unloaded_chunks.clear();
+        self.dirty.set(true);
+    }
+
+    /// Removes all the chunks, including the unloaded ones. Meshes that are
+    /// still being built are dropped when they finish, even if a chunk with
+    /// the same id is created again
+    pub fn clear(&mut self) {
+        self.chunks.clear();
+        self.unloaded_chunks.clear();
+        self.dirty.set(false);
+    }
+
+    /// Number of cubes in the `y` axis of the world
+    pub fn height(&self) -> i32 {
+        self.height
+    }
+
+    /// The seed used to generate chunks in [`World::update_loaded_chunks`]
+    pub fn set_seed(&mut self, seed: u32) {
+        self.seed = seed;
+    }
+
+    /// Moves the settings used to generate new chunks (seed, base height, terrain
+    /// source) and to unload them from `other`, which is left generating noise.
+    /// These are not saved, so a loaded world takes them from the one it replaces
+    pub fn take_generation_settings(&mut self, other: &mut World) {
+        self.seed = other.seed;
+        self.base_height = other.base_height;
+        self.terrain = std::mem::replace(&mut other.terrain, TerrainSource::Noise);
+        self.set_keep_unloaded_chunks(other.keep_unloaded_chunks);
+    }
+
+    /// If `true` (the default), unloaded chunks that were edited are kept in a
+    /// compact form and restored when loaded again, otherwise they are
+    /// generated again. Unedited chunks are always generated again, since
+    /// they are the same every time
+    pub fn set_keep_unloaded_chunks(&mut self, keep: bool) {
+        self.keep_unloaded_chunks = keep;
+        if !keep {
+            self.unloaded_chunks.clear();
+        }
+    }
+
+    /// Loads the missing chunks within `radius` of `camera_pos` and unloads the
+    /// chunks outside it.
+    ///
+    /// Only a few chunks are loaded every call (closest first) to avoid
+    /// stalling a single frame.
+    pub fn update_loaded_chunks(&mut self, camera_pos: Point3<f32>, radius: f32) {
+        const MAX_LOADS_PER_UPDATE: usize = 4;
+
+        self.update_loaded_chunks_limited(camera_pos, radius, MAX_LOADS_PER_UPDATE, true);
+    }
+
+    /// Same as [`World::update_loaded_chunks`], but loads all the chunks at once
+    pub fn load_all_chunks_around(&mut self, camera_pos: Point3<f32>, radius: f32) {
+        self.update_loaded_chunks_limited(camera_pos, radius, usize::MAX, false);
+    }
+
+    fn update_loaded_chunks_limited(
+        &mut self,
+        camera_pos: Point3<f32>,
+        radius: f32,
+        max_loads: usize,
+        fade_in: bool,
+    ) {
+        let pos = Point2::new(camera_pos.x.floor() as i32, camera_pos.z.floor() as i32);
+        let in_range = chunk_ids_around(pos, radius).collect::<HashSet<_>>();
+
+        let out_of_range = self
+            .chunks
+            .keys()
+            .filter(|chunk_id| !in_range.contains(chunk_id))
+            .copied()
+            .collect::<Vec<_>>();
+        for chunk_id in out_of_range {
+            self.unload_chunk(chunk_id);
+        }
+
+        let mut missing = in_range
+            .into_iter()
+            .filter(|chunk_id| !self.chunks.contains_key(chunk_id))
+            .collect::<Vec<_>>();
+        // distance to the center of the chunk
+        missing.sort_by_key(|&(x, z)| {
+            let dx = x + 8 - pos.x;
+            let dz = z + 8 - pos.y;
+            dx * dx + dz * dz
+        });
+
+        for chunk_id in missing.into_iter().take(max_loads) {
+            self.load_chunk(chunk_id);
+            if let Some(chunk) = self.chunks.get_mut(&chunk_id) {
+                chunk.fade_in = fade_in;
+            }
+        }
+    }
+
+    fn unload_chunk(&mut self, chunk_id: (i32, i32)) {
+        if let Some(chunk) = self.chunks.remove(&chunk_id) {
+            if self.keep_unloaded_chunks && chunk.edited {
+                let cubes = chunk
+                    .cubes
+                    .iter()
+                    .map(|(i, cube)| (i as u32, cube))
+                    .collect();
+                self.unloaded_chunks.insert(chunk_id, cubes);
+            }
+
+            // the edges of the neighbours are not hidden anymore
+            self.mark_chunks_around_dirty(chunk_id);
+        }
+    }
+
+    /// The chunk with `chunk_id` to place cubes in. If it's not loaded, it's
+    /// restored or generated first, so the edit doesn't go into an empty chunk
+    /// that would replace the kept cubes or the terrain
+    fn chunk_for_edit(&mut self, chunk_id: (i32, i32)) -> &mut Chunk {
+        if !self.chunks.contains_key(&chunk_id) {
+            self.load_chunk(chunk_id);
+        }
+        self.chunks
+            .get_mut(&chunk_id)
+            .expect("the chunk was just loaded")
+    }
+
+    /// Restores the chunk if it was unloaded before, otherwise generates it
+    fn load_chunk(&mut self, chunk_id: (i32, i32)) {
+        match self.unloaded_chunks.remove(&chunk_id) {
+            Some(cubes) => {
+                let mut chunk = Chunk::new(
+                    chunk_id.into(),
+                    self.height,
+                    self.dirty.clone(),
+                    self.last_version.clone(),
+                );
+                for (i, cube) in cubes {
+                    chunk.cubes.set(i as usize, Some(cube));
+                }
+                chunk.edited = true;
+                self.insert_chunk(chunk_id, chunk);
+            }
+            None => self.generate_chunk(chunk_id.0, chunk_id.1, self.seed),
+        }
+    }
+
+    /// Marks the 4 chunks sharing a side with `chunk_id` as dirty, this is
+    /// needed when a chunk is added or removed, since the cubes on the
+    /// edges of the neighbours may become hidden or visible
+    fn mark_chunks_around_dirty(&mut self, chunk_id: (i32, i32)) {
+        for (x, z) in [
+            (CHUNK_SIZE, 0),
+            (-CHUNK_SIZE, 0),
+            (0, CHUNK_SIZE),
+            (0, -CHUNK_SIZE),
+        ] {
+            if let Some(chunk) = self.chunks.get_mut(&(chunk_id.0 + x, chunk_id.1 + z)) {
+                chunk.mark_dirty();
+            }
+        }
+    }
+
+    /// Saves the world into `path` in a simple binary format:
+    ///
+    /// ```text
+    /// magic "MCWD" | version: u32 | height: u32
+    /// has camera: u8 | (if 1) x, y, z, yaw, pitch, fov: f32
+    /// chunk count: u32
+    /// per chunk: start x: i32 | start z: i32 | cube count: u32
+    ///     per cube: index in chunk: u32 | block id: u8 | shape id: u8
+    /// ```
+    ///
+    /// All numbers are little endian and the angles are in radians.
+    pub fn save_to_path(&self, path: &Path, camera: Option<&CameraState>) -> io::Result<()> {
+        self.save_snapshot(camera).write_to_path(path)
+    }
+
+    /// Copies the data written by [`World::save_to_path`], edits made to the
+    /// world afterwards don't change the snapshot. The unloaded chunks that
+    /// were kept are saved too
+    pub fn save_snapshot(&self, camera: Option<&CameraState>) -> SaveSnapshot {
+        // sorted, so saving the same world always gives the same file
+        fn saved_cubes(cubes: impl Iterator<Item = (usize, ChunkCube)>) -> SavedCubes {
+            let mut cubes = cubes
+                .map(|(i, cube)| (i as u32, [cube.block.id(), cube.shape.id()]))
+                .collect::<SavedCubes>();
+            cubes.sort_unstable_by_key(|&(i, _)| i);
+            cubes
+        }
+
+        let loaded = self
+            .chunks
+            .values()
+            .map(|chunk| (chunk.start, saved_cubes(chunk.cubes.iter())));
+        let unloaded = self.unloaded_chunks.iter().map(|(&chunk_id, cubes)| {
+            let cubes = cubes.iter().map(|&(i, cube)| (i as usize, cube));
+            (chunk_id.into(), saved_cubes(cubes))
+        });
+
+        let mut chunks = loaded.chain(unloaded).collect::<Vec<_>>();
+        chunks.sort_unstable_by_key(|(start, _)| (start.x, start.y));
+
+        SaveSnapshot {
+            height: self.height,
+            camera: camera.copied(),
+            chunks,
+        }
+    }
+
+    /// Like [`World::save_to_path`], but only the copying of the world is done
+    /// here, the file is written on another thread
+    pub fn save_to_path_in_background(
+        &self,
+        path: PathBuf,
+        camera: Option<&CameraState>,
+    ) -> PendingSave {
+        let snapshot = self.save_snapshot(camera);
+        let (sender, receiver) = mpsc::channel();
+        std::thread::spawn(move || {
+            // nothing to report to if the receiver was dropped
+            let _ = sender.send(snapshot.write_to_path(&path));
+        });
+
+        PendingSave { receiver }
+    }
+}
+
+/// The index in the chunk, block id and shape id of every cube in a chunk
+type SavedCubes = Vec<(u32, [u8; 2])>;
+
+/// A copy of the world taken by [`World::save_snapshot`], which can be written
+/// from any thread
+pub(crate) struct SaveSnapshot {
+    height: i32,
+    camera: Option<CameraState>,
+    // the start of every chunk and its cubes
+    chunks: Vec<(Point2<i32>, SavedCubes)>,
+}
+
+impl SaveSnapshot {
+    /// Writes the snapshot in the format described in [`World::save_to_path`]
+    pub fn write_to_path(&self, path: &Path) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+
+        writer.write_all(SAVE_MAGIC)?;
+        writer.write_all(&SAVE_VERSION.to_le_bytes())?;
+        writer.write_all(&(self.height as u32).to_le_bytes())?;
+        match self.camera {
+            Some(camera) => {
+                writer.write_all(&[1])?;
+                for value in [
+                    camera.position.x,
+                    camera.position.y,
+                    camera.position.z,
+                    camera.yaw.0,
+                    camera.pitch.0,
+                    camera.fov.0,
+                ] {
+                    writer.write_all(&value.to_le_bytes())?;
+                }
+            }
+            None => writer.write_all(&[0])?,
+        }
+        writer.write_all(&(self.chunks.len() as u32).to_le_bytes())?;
+
+        for (start, cubes) in &self.chunks {
+            writer.write_all(&start.x.to_le_bytes())?;
+            writer.write_all(&start.y.to_le_bytes())?;
+
+            writer.write_all(&(cubes.len() as u32).to_le_bytes())?;
+
+            for (i, ids) in cubes {
+                writer.write_all(&i.to_le_bytes())?;
+                writer.write_all(ids)?;
+            }
+        }
+
+        writer.flush()
+    }
+}
+
+/// A save started by [`World::save_to_path_in_background`]
+pub(crate) struct PendingSave {
+    receiver: mpsc::Receiver<io::Result<()>>,
+}
+
+impl PendingSave {
+    /// The result of the save if it finished, `None` while it's still writing
+    pub fn poll(&self) -> Option<io::Result<()>> {
+        match self.receiver.try_recv() {
+            Ok(result) => Some(result),
+            Err(mpsc::TryRecvError::Empty) => None,
+            Err(mpsc::TryRecvError::Disconnected) => Some(Err(io::Error::other(
+                "the save thread stopped unexpectedly",
+            ))),
+        }
+    }
+}
+
+impl World {
+    /// Loads a world saved with [`World::save_to_path`], with the camera if it
+    /// was saved with one
+    pub fn load_from_path(path: &Path) -> io::Result<(World, Option<CameraState>)> {
+        fn invalid_data(msg: &str) -> io::Error {
+            io::Error::new(io::ErrorKind::InvalidData, msg)
+        }
+
+        fn read_u32(reader: &mut impl Read) -> io::Result<u32> {
+            let mut buf = [0; 4];
+            reader.read_exact(&mut buf)?;
+            Ok(u32::from_le_bytes(buf))
+        }
+
+        fn read_i32(reader: &mut impl Read) -> io::Result<i32> {
+            let mut buf = [0; 4];
+            reader.read_exact(&mut buf)?;
+            Ok(i32::from_le_bytes(buf))
+        }
+
+        fn read_f32(reader: &mut impl Read) -> io::Result<f32> {
+            let mut buf = [0; 4];
+            reader.read_exact(&mut buf)?;
+            Ok(f32::from_le_bytes(buf))
+        }
+
+        let mut reader = BufReader::new(File::open(path)?);
+
+        let mut magic = [0; 4];
+        reader.read_exact(&mut magic)?;
+        if &magic != SAVE_MAGIC {
+            return Err(invalid_data("not a world save file"));
+        }
+        let version = read_u32(&mut reader)?;
+        if version == 0 || version > SAVE_VERSION {
+            return Err(invalid_data("unsupported world save version"));
+        }
+
+        let mut world = World::default();
+        // older versions were always the default height
+        if version >= 3 {
+            let height = read_u32(&mut reader)?;
+            if height == 0 || height > MAX_HEIGHT as u32 {
+                return Err(invalid_data("invalid world height"));
+            }
+            world.set_height(height as i32);
+        }
+
+        // older versions didn't save the camera
+        let mut camera = None;
+        if version >= 4 {
+            let mut has_camera = [0; 1];
+            reader.read_exact(&mut has_camera)?;
+            if has_camera[0] == 1 {
+                let mut values = [0.; 6];
+                for value in &mut values {
+                    *value = read_f32(&mut reader)?;
+                }
+                if values.iter().any(|value| !value.is_finite()) {
+                    return Err(invalid_data("invalid camera"));
+                }
+                let [x, y, z, yaw, pitch, fov] = values;
+                camera = Some(CameraState {
+                    position: Point3::new(x, y, z),
+                    yaw: Rad(yaw),
+                    pitch: Rad(pitch),
+                    fov: Rad(fov),
+                });
+            }
+        }
+
+        let chunks_count = read_u32(&mut reader)?;
+        for _ in 0..chunks_count {
+            let start = Point2::new(read_i32(&mut reader)?, read_i32(&mut reader)?);
+            let chunk_id = chunk_id(Point3::new(start.x, 0, start.y));
+            if chunk_id != start.into() {
+                return Err(invalid_data("chunk start is not aligned"));
+            }
+
+            let mut chunk = Chunk::new(
+                start,
+                world.height,
+                world.dirty.clone(),
+                world.last_version.clone(),
+            );
+
+            let cubes_count = read_u32(&mut reader)?;
+            chunk.begin_bulk();
+            for _ in 0..cubes_count {
+                let index = read_u32(&mut reader)? as usize;
+                let mut block_id = [0; 1];
+                reader.read_exact(&mut block_id)?;
+                // version 1 only had cubes
+                let mut shape_id = [BlockShape::Cube.id()];
+                if version >= 2 {
+                    reader.read_exact(&mut shape_id)?;
+                }
+
+                if index >= chunk.cells_count() {
+                    return Err(invalid_data("cube index out of chunk range"));
+                }
+                let block =
+                    BlockType::from_id(block_id[0]).ok_or_else(|| invalid_data("unknown block"))?;
+                let shape = BlockShape::from_id(shape_id[0])
+                    .ok_or_else(|| invalid_data("unknown shape"))?;
+
+                let pos =
+                    index_to_chunk_pos(index, world.height) + Vector3::new(start.x, 0, start.y);
+                chunk
+                    .push_cube_with_shape(pos, block, shape)
+                    .map_err(|_| invalid_data("cube position out of chunk range"))?;
+            }
+            chunk.end_bulk();
+            // it may not match the generated terrain
+            chunk.edited = true;
+
+            if world.chunks.insert(chunk_id, chunk).is_some() {
+                return Err(invalid_data("duplicate chunk"));
+            }
+        }
+
+        Ok((world, camera))
+    }
+
+    pub fn chunks(&self) -> impl Iterator<Item = &Chunk> {
+        self.chunks.values()
+    }
+
+    /// Every cube in the loaded chunks with its block type, in no particular order
+    #[allow(dead_code)]
+    pub fn iter_blocks(&self) -> impl Iterator<Item = (Point3<i32>, BlockType)> + '_ {
+        self.chunks.values().flat_map(Chunk::blocks)
+    }
+
+    /// The ids of the loaded chunks, which are the `(x, z)` of their first cube
+    pub fn loaded_chunk_ids(&self) -> impl Iterator<Item = (i32, i32)> + '_ {
+        self.chunks.keys().copied()
+    }
+
+    pub fn chunk_count(&self) -> usize {
+        self.chunks.len()
+    }
+
+    /// Counts all the cubes in the world, this goes through all the chunks
+    /// so don't call it every frame
+    pub fn block_count(&self) -> usize {
+        self.chunks.values().map(|chunk| chunk.cubes.count()).sum()
+    }
+
+    /// Counts the instances of the full detail meshes of all the chunks
+    pub fn instance_count(&self) -> usize {
+        self.chunks
+            .values()
+            .map(|chunk| chunk.instance_count())
+            .sum()
+    }
+
+    /// Rough estimation of the memory used by the chunks cubes and meshes
+    pub fn estimated_memory(&self) -> usize {
+        self.chunks
+            .values()
+            .map(|chunk| {
+                chunk.cubes.memory()
+                    + std::mem::size_of_val(chunk.mesh.instances())
+                    + std::mem::size_of_val(chunk.transparent_mesh.instances())
+                    + std::mem::size_of_val(chunk.slab_mesh.instances())
+                    + std::mem::size_of_val(chunk.surface_mesh.instances())
+            })
+            .sum()
+    }
+
+    /// Counts the chunks and measures their data, this goes through all the
+    /// chunks, so it shouldn't be called every frame
+    pub fn memory_report(&self) -> MemoryReport {
+        let mut report = MemoryReport {
+            chunks: self.chunks.len(),
+            unloaded_chunks: self.unloaded_chunks.len(),
+            cubes_memory: self
+                .unloaded_chunks
+                .values()
+                .map(|cubes| cubes.capacity() * std::mem::size_of::<(u32, ChunkCube)>())
+                .sum(),
+            ..Default::default()
+        };
+
+        for chunk in self.chunks.values() {
+            report.instances += chunk.instance_count();
+            report.cubes_memory += chunk.cubes.memory();
+            report.instances_memory += std::mem::size_of_val(chunk.mesh.instances())
+                + std::mem::size_of_val(chunk.transparent_mesh.instances())
+                + std::mem::size_of_val(chunk.slab_mesh.instances())
+                + std::mem::size_of_val(chunk.surface_mesh.instances());
+        }
+
+        report
+    }
+
+    pub fn chunks_around(&self, pos: Point2<i32>, radius: f32) -> impl Iterator<Item = &Chunk> {
+        chunk_ids_around(pos, radius).filter_map(|chunk_id| self.chunks.get(&chunk_id))
+    }
+
+    /// Since we can't create a mut iterator easily because of lifetimes errors,
+    /// we used callback function to mutate chunks if needed.
+    #[allow(dead_code)]
+    pub fn chunks_around_mut_callback(
+        &mut self,
+        pos: Point2<i32>,
+        radius: f32,
+        f: impl Fn(&mut Chunk),
+    ) {
+        for chunk_id in chunk_ids_around(pos, radius) {
+            if let Some(chunk) = self.chunks.get_mut(&chunk_id) {
+                f(chunk);
+            }
+        }
+    }
+
+    /// Returns the part of `delta` that `aabb` can move without going
+    /// into any cube.
+    ///
+    /// Each axis is resolved separately (`y` first), so that moving
+    /// diagonally into a wall slides along it. Cubes that already overlap
+    /// `aabb` are ignored, so that we can always get out of them.
+    pub fn resolve_movement(&self, aabb: &Aabb, delta: Vector3<f32>) -> Vector3<f32> {
+        let mut aabb = *aabb;
+        let mut result = Vector3::new(0., 0., 0.);
+
+        for axis in [1, 0, 2] {
+            if delta[axis] == 0. {
+                continue;
+            }
+
+            let allowed = self.sweep_axis(&aabb, axis, delta[axis]);
+            let mut offset = Vector3::new(0., 0., 0.);
+            offset[axis] = allowed;
+            aabb = aabb.translate(offset);
+            result[axis] = allowed;
+        }
+
+        result
+    }
+
+    /// Returns `true` if `aabb` overlaps any cube, touching cubes don't count
+    pub fn intersects_cubes(&self, aabb: &Aabb) -> bool {
+        // same as in `sweep_axis`
+        const EPSILON: f32 = 1e-3;
+
+        // cube at `p` occupies `p - 0.5` to `p + 0.5`
+        let min = aabb.min.map(|a| (a + EPSILON - 0.5).floor() as i32 + 1);
+        let max = aabb.max.map(|a| (a - EPSILON + 0.5).ceil() as i32 - 1);
+
+        (min.x..=max.x).any(|x| {
+            (min.y..=max.y).any(|y| (min.z..=max.z).any(|z| self.has_cube(Point3::new(x, y, z))))
+        })
+    }
+
+    /// Clamps movement of `aabb` by `distance` along `axis` against the cubes
+    fn sweep_axis(&self, aabb: &Aabb, axis: usize, distance: f32) -> f32 {
+        // shrink the box a bit, so that touching cubes (like the ground while
+        // walking) are not considered blocking
+        const EPSILON: f32 = 1e-3;
+
+        let mut min = aabb.min;
+        let mut max = aabb.max;
+        if distance > 0. {
+            max[axis] += distance;
+        } else {
+            min[axis] += distance;
+        }
+
+        // cube at `p` occupies `p - 0.5` to `p + 0.5`
+        let min_cube = min.map(|a| (a + EPSILON - 0.5).floor() as i32 + 1);
+        let max_cube = max.map(|a| (a - EPSILON + 0.5).ceil() as i32 - 1);
+
+        let mut distance = distance;
+        for x in min_cube.x..=max_cube.x {
+            for y in min_cube.y..=max_cube.y {
+                for z in min_cube.z..=max_cube.z {
+                    let cube = Point3::new(x, y, z);
+                    if !self.has_cube(cube) {
+                        continue;
+                    }
+
+                    let cube = cube.cast::<f32>().unwrap();
+                    if distance > 0. {
+                        let limit = cube[axis] - 0.5 - aabb.max[axis];
+                        if limit >= -EPSILON {
+                            distance = distance.min(limit.max(0.));
+                        }
+                    } else {
+                        let limit = cube[axis] + 0.5 - aabb.min[axis];
+                        if limit <= EPSILON {
+                            distance = distance.max(limit.min(0.));
+                        }
+                    }
+                }
+            }
+        }
+
+        distance
+    }
+
+    pub fn cube_looking_at(
+        &self,
+        origin: &Point3<f32>,
+        direction: &Vector3<f32>,
+        max_radius: f32,
+    ) -> TraceResult {
+        let tracer = BlockRayTracer::new(self, origin, direction, max_radius);
+
+        tracer.run()
+    }
+
+    /// All the blocks the ray from `origin` in `direction` passes through
+    /// within `max_radius`, ordered from the nearest
+    #[allow(dead_code)]
+    pub fn raycast_all(
+        &self,
+        origin: &Point3<f32>,
+        direction: &Vector3<f32>,
+        max_radius: f32,
+    ) -> Vec<Point3<i32>> {
+        BlockRayTracer::new(self, origin, direction, max_radius).run_all()
+    }
+}
+
+impl World {
+    /// Starts rebuilding the meshes of the chunks that changed in the
+    /// background, and swaps in the meshes that finished building
+    pub(crate) fn update_meshes(&mut self) {
+        while let Ok(result) = self.mesh_receiver.try_recv() {
+            self.apply_mesh(result);
+        }
+
+        if self.dirty.get() {
+            let dirty_chunks = self
+                .chunks
+                .iter()
+                .filter(|(_, chunk)| chunk.dirty)
+                .map(|(&id, _)| id)
+                .collect::<Vec<_>>();
+
+            for chunk_id in dirty_chunks {
+                // the snapshot needs access to the neighbouring chunks, and
+                // changes after this will mark the chunk dirty again, so it
+                // will be queued again with a newer version
+                let snapshot = self.chunks[&chunk_id].snapshot(self);
+                let chunk = self.chunks.get_mut(&chunk_id).unwrap();
+                chunk.dirty = false;
+
+                let version = chunk.version;
+                let sender = self.mesh_sender.clone();
+                self.pending_meshes += 1;
+                self.mesh_workers.execute(move || {
+                    // the world may be dropped before we finish
+                    let _ = sender.send((chunk_id, version, snapshot.build_mesh()));
+                });
+            }
+            self.dirty.set(false);
+        }
+    }
+
+    /// Blocks until all the meshes being built are done, and swaps them in
+    pub(crate) fn wait_for_meshes(&mut self) {
+        while self.pending_meshes > 0 {
+            let result = self.mesh_receiver.recv().unwrap();
+            self.apply_mesh(result);
+        }
+    }
+
+    fn apply_mesh(&mut self, (chunk_id, version, meshes): MeshResult) {
+        self.pending_meshes -= 1;
+
+        // the chunk may have been removed while building
+        if let Some(chunk) = self.chunks.get_mut(&chunk_id) {
+            // meshes can finish out of order, don't replace a newer mesh
+            if version > chunk.mesh_version {
+                // the chunk was invisible until now
+                if chunk.fade_in && chunk.load_time.is_none() {
+                    chunk.load_time = Some(Instant::now());
+                }
+                chunk.mesh = meshes.mesh;
+                chunk.transparent_mesh = meshes.transparent_mesh;
+                chunk.slab_mesh = meshes.slab_mesh;
+                chunk.surface_mesh = meshes.surface_mesh;
+                chunk.mesh_version = version;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+
+    use super::*;
+
+    /// Every cube of the loaded chunks with its block and shape, sorted by position
+    fn sorted_cubes(world: &World) -> Vec<((i32, i32, i32), BlockType, BlockShape)> {
+        let mut cubes = world
+            .iter_blocks()
+            .map(|(pos, block)| ((pos.x, pos.y, pos.z), block, world.shape_at(pos).unwrap()))
+            .collect::<Vec<_>>();
+        cubes.sort_by_key(|&(pos, ..)| pos);
+        cubes
+    }
+
+    #[test]
+    fn save_and_load_keep_the_cubes_and_camera() {
+        let mut world = World::empty();
+        world.create_chunk(0, 3, 0);
+        world.fill_box(
+            Point3::new(-3, 0, -20),
+            Point3::new(-1, 2, -18),
+            BlockType::Water,
+        );
+        world
+            .push_cube_with_shape(Point3::new(5, 50, 5), BlockType::Stone, BlockShape::Slab)
+            .unwrap();
+        world.remove_cube(Point3::new(0, 2, 0)).unwrap();
+        let camera = CameraState {
+            position: Point3::new(1.5, 70., -3.),
+            yaw: Rad(0.5),
+            pitch: Rad(-0.25),
+            fov: Rad(1.),
+        };
+
+        let path = std::env::temp_dir().join("minecraft-world-round-trip-test.sav");
+        world.save_to_path(&path, Some(&camera)).unwrap();
+        let (loaded, loaded_camera) = World::load_from_path(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(loaded.height(), world.height());
+        assert_eq!(loaded.chunk_count(), world.chunk_count());
+        assert_eq!(sorted_cubes(&loaded), sorted_cubes(&world));
+        let loaded_camera = loaded_camera.unwrap();
+        assert_eq!(loaded_camera.position, camera.position);
+        assert_eq!(loaded_camera.yaw, camera.yaw);
+        assert_eq!(loaded_camera.pitch, camera.pitch);
+        assert_eq!(loaded_camera.fov, camera.fov);
+    }
+
+    #[test]
+    fn clear_removes_all_the_chunks() {
+        let mut world = World::empty();
+        world.create_chunk(0, 4, 0);
+        world.create_chunk(CHUNK_SIZE, 4, 0);
+        world
+            .push_cube(Point3::new(0, 10, 0), BlockType::Stone)
+            .unwrap();
+        world.update_meshes();
+
+        world.clear();
+        world.wait_for_meshes();
+
+        assert_eq!(world.chunk_count(), 0);
+        assert_eq!(world.block_count(), 0);
+        assert!(world.unloaded_chunks.is_empty());
+        assert!(world.iter_blocks().next().is_none());
+    }
+
+    #[test]
+    fn meshes_built_before_clear_are_not_applied() {
+        let mut world = World::empty();
+        world.create_chunk(0, 4, 0);
+        world.update_meshes();
+
+        // the same chunk id is created again while the old mesh is building
+        world.clear();
+        world.create_chunk(0, 4, 0);
+        world.wait_for_meshes();
+        let chunk = world.chunks().next().unwrap();
+        assert!(chunk.mesh().instances().is_empty());
+
+        // the new chunk still gets its own mesh
+        world.update_meshes();
+        world.wait_for_meshes();
+        let chunk = world.chunks().next().unwrap();
+        assert!(!chunk.mesh().instances().is_empty());
+    }
+
+    #[test]
+    fn block_at_reads_loaded_chunks_only() {
+        let mut world = World::empty();
+        world.create_chunk(0, 5, 0);
+
+        assert_eq!(world.block_at(Point3::new(0, 4, 0)), Some(BlockType::Grass));
+        assert_eq!(world.block_at(Point3::new(3, 2, 7)), Some(BlockType::Dirt));
+        assert_eq!(
+            world.block_at(Point3::new(CHUNK_SIZE - 1, 0, CHUNK_SIZE - 1)),
+            Some(BlockType::Stone)
+        );
+        assert_eq!(world.block_at(Point3::new(0, 5, 0)), None);
+        // outside the world height
+        assert_eq!(world.block_at(Point3::new(0, -1, 0)), None);
+        assert_eq!(world.block_at(Point3::new(0, world.height(), 0)), None);
+        // in a chunk that is not loaded
+        assert_eq!(world.block_at(Point3::new(CHUNK_SIZE, 0, 0)), None);
+        assert_eq!(world.block_at(Point3::new(-1, 0, 0)), None);
+    }
+
+    #[test]
+    fn remove_cube_rejects_positions_out_of_bounds() {
+        let mut world = World::empty();
+        world.create_chunk(0, 3, 0);
+
+        assert!(world.remove_cube(Point3::new(0, -1, 0)).is_err());
+        assert!(world
+            .remove_cube(Point3::new(0, world.height(), 0))
+            .is_err());
+        assert_eq!(world.block_count(), (CHUNK_SIZE * CHUNK_SIZE * 3) as usize);
+
+        let removed = world.remove_cube(Point3::new(0, 2, 0)).unwrap().unwrap();
+        assert_eq!(removed.block, BlockType::Grass);
+        assert!(world.remove_cube(Point3::new(0, 2, 0)).unwrap().is_none());
+
+        // the chunk only accepts positions inside it
+        let chunk = world
+            .chunks
+            .get_mut(&chunk_id(Point3::new(0, 0, 0)))
+            .unwrap();
+        assert!(chunk.remove_cube(Point3::new(CHUNK_SIZE, 0, 0)).is_err());
+        assert!(chunk.remove_cube(Point3::new(-1, 0, 0)).is_err());
+        assert!(chunk.remove_cube(Point3::new(0, -1, 0)).is_err());
+    }
+
+    #[test]
+    fn raycast_all_finds_every_block_on_a_line_in_order() {
+        let mut world = World::empty();
+        let on_line = [
+            Point3::new(2, 10, 0),
+            Point3::new(5, 10, 0),
+            Point3::new(CHUNK_SIZE + 1, 10, 0),
+        ];
+        for pos in on_line {
+            world.push_cube(pos, BlockType::Stone).unwrap();
+        }
+        // off the line, and past the radius
+        world
+            .push_cube(Point3::new(3, 11, 0), BlockType::Stone)
+            .unwrap();
+        world
+            .push_cube(Point3::new(3 * CHUNK_SIZE, 10, 0), BlockType::Stone)
+            .unwrap();
+
+        let cubes = world.raycast_all(
+            &Point3::new(0., 10., 0.),
+            &Vector3::unit_x(),
+            (2 * CHUNK_SIZE) as f32,
+        );
+        assert_eq!(cubes, on_line);
+    }
+
+    #[test]
+    fn set_height_clamps_the_height() {
+        let mut world = World::empty();
+        world.set_height(0);
+        assert_eq!(world.height(), 1);
+        world.set_height(-5);
+        assert_eq!(world.height(), 1);
+        world.set_height(MAX_HEIGHT + 1);
+        assert_eq!(world.height(), MAX_HEIGHT);
+    }
+
+    #[test]
+    fn cubes_are_indexed_with_the_world_height() {
+        let mut world = World::empty();
+        world.set_height(40);
+        let top = Point3::new(CHUNK_SIZE - 1, 39, CHUNK_SIZE - 1);
+        world.push_cube(top, BlockType::Stone).unwrap();
+        world
+            .push_cube(Point3::new(0, 0, 0), BlockType::Dirt)
+            .unwrap();
+        assert!(world
+            .push_cube(Point3::new(0, 40, 0), BlockType::Stone)
+            .is_err());
+
+        assert_eq!(world.block_at(top), Some(BlockType::Stone));
+        assert_eq!(world.block_at(Point3::new(0, 0, 0)), Some(BlockType::Dirt));
+        assert_eq!(world.block_count(), 2);
+    }
+
+    #[test]
+    fn load_rejects_heights_above_the_maximum() {
+        let mut world = World::empty();
+        world.set_height(16);
+        let path = std::env::temp_dir().join("minecraft-world-height-test.sav");
+        world.save_to_path(&path, None).unwrap();
+
+        // the height is saved right after the magic and version
+        let mut bytes = std::fs::read(&path).unwrap();
+        bytes[8..12].copy_from_slice(&(MAX_HEIGHT as u32 + 1).to_le_bytes());
+        std::fs::write(&path, bytes).unwrap();
+        let result = World::load_from_path(&path);
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn fill_box_sends_an_event_per_placed_cube() {
+        let mut world = World::empty();
+        let events = Rc::new(RefCell::new(Vec::new()));
+        let events_ref = events.clone();
+        world.on_block_event(move |event| events_ref.borrow_mut().push(event));
+
+        // the layer at `y = -1` is below the world, and not placed
+        world.fill_box(
+            Point3::new(-1, -1, 2),
+            Point3::new(2, 1, 0),
+            BlockType::Stone,
+        );
+        assert_eq!(events.borrow().len(), 4 * 2 * 3);
+        assert!(events.borrow().iter().all(|event| matches!(
+            event,
+            BlockEvent::Placed { pos, block: BlockType::Stone } if pos.y >= 0
+        )));
+
+        events.borrow_mut().clear();
+        world.remove_cube(Point3::new(0, 0, 0)).unwrap();
+        // removing an empty cell doesn't send an event
+        world.remove_cube(Point3::new(0, 5, 0)).unwrap();
+        assert_eq!(
+            *events.borrow(),
+            [BlockEvent::Removed {
+                pos: Point3::new(0, 0, 0),
+                block: BlockType::Stone
+            }]
+        );
+    }
+
+    #[test]
+    fn diagonal_rays_cross_chunk_corners() {
+        let mut world = World::empty();
+        for (dx, dz) in [(1, 1), (1, -1), (-1, 1), (-1, -1)] {
+            world.clear();
+            // the ray passes exactly through the corner shared by the 4 chunks
+            // around `(CHUNK_SIZE, CHUNK_SIZE)`, on both sides of it
+            let corner = Point3::new(CHUNK_SIZE, 10, CHUNK_SIZE);
+            let start = corner - Vector3::new(dx, 0, dz) * 3;
+            let target = corner + Vector3::new(dx, 0, dz) * 2;
+            world.push_cube(target, BlockType::Stone).unwrap();
+
+            let result = world.cube_looking_at(
+                &start.cast().unwrap(),
+                &Vector3::new(dx as f32, 0., dz as f32),
+                10.,
+            );
+            assert_eq!(result.result_cube.map(|cube| cube.cube), Some(target));
+            // the path moves one axis at a time, without skipping cubes
+            for step in result.path.windows(2) {
+                let delta = step[1] - step[0];
+                assert_eq!(delta.x.abs() + delta.y.abs() + delta.z.abs(), 1);
+            }
+        }
+    }
+
+    #[test]
+    fn textured_chunks_with_the_same_seed_have_the_same_colors() {
+        fn colors(seed: u32) -> Vec<[f32; 4]> {
+            let mut world = World::empty();
+            world.create_chunk_textured(0, 3, 0, seed);
+            world.update_meshes();
+            world.wait_for_meshes();
+            let chunk = world.chunks().next().unwrap();
+            chunk
+                .mesh()
+                .instances()
+                .iter()
+                .map(|instance| instance.color)
+                .collect()
+        }
+
+        assert_eq!(colors(7), colors(7));
+        assert_ne!(colors(7), colors(8));
+    }
+
+    #[test]
+    fn iter_blocks_returns_every_cube_with_its_block() {
+        let mut world = World::empty();
+        let mut expected = vec![
+            ((0, 0, 0), BlockType::Grass),
+            ((CHUNK_SIZE - 1, 7, 2), BlockType::Water),
+            ((CHUNK_SIZE, 3, -1), BlockType::Stone),
+            ((-5, world.height() - 1, 40), BlockType::Dirt),
+        ];
+        for &((x, y, z), block) in &expected {
+            world.push_cube(Point3::new(x, y, z), block).unwrap();
+        }
+
+        let mut blocks = world
+            .iter_blocks()
+            .map(|(pos, block)| ((pos.x, pos.y, pos.z), block))
+            .collect::<Vec<_>>();
+        blocks.sort_by_key(|&(pos, _)| pos);
+        expected.sort_by_key(|&(pos, _)| pos);
+        assert_eq!(blocks, expected);
+    }
+
+    #[test]
+    fn replace_region_only_replaces_the_from_block() {
+        let mut world = World::empty();
+        // grass at `y = 4`, dirt at `1..=3` and stone at `0`
+        world.create_chunk(0, 5, 0);
+        world
+            .push_cube_with_shape(Point3::new(1, 5, 1), BlockType::Dirt, BlockShape::Slab)
+            .unwrap();
+
+        let count = world.replace_region(
+            Point3::new(2, -3, 2),
+            Point3::new(0, 5, 0),
+            BlockType::Dirt,
+            BlockType::Water,
+        );
+        assert_eq!(count, 3 * 3 * 3 + 1);
+        assert_eq!(world.block_at(Point3::new(1, 5, 1)), Some(BlockType::Water));
+        assert_eq!(world.shape_at(Point3::new(1, 5, 1)), Some(BlockShape::Slab));
+        assert_eq!(world.block_at(Point3::new(2, 2, 2)), Some(BlockType::Water));
+        // other blocks in the box and dirt outside it are kept
+        assert_eq!(world.block_at(Point3::new(2, 4, 2)), Some(BlockType::Grass));
+        assert_eq!(world.block_at(Point3::new(0, 0, 0)), Some(BlockType::Stone));
+        assert_eq!(world.block_at(Point3::new(3, 2, 2)), Some(BlockType::Dirt));
+    }
+
+    #[test]
+    fn flood_fill_stops_at_other_blocks() {
+        let mut world = World::empty();
+        // a 3x3 pool of water in stone, and water outside the stone walls
+        world.fill_box(Point3::new(0, 0, 0), Point3::new(4, 1, 4), BlockType::Stone);
+        world.fill_box(Point3::new(1, 1, 1), Point3::new(3, 1, 3), BlockType::Water);
+        world.fill_box(Point3::new(5, 1, 0), Point3::new(5, 1, 4), BlockType::Water);
+
+        let count = world.flood_fill(Point3::new(2, 1, 2), BlockType::Dirt);
+        assert_eq!(count, 9);
+        assert_eq!(world.block_at(Point3::new(1, 1, 3)), Some(BlockType::Dirt));
+        assert_eq!(world.block_at(Point3::new(4, 1, 2)), Some(BlockType::Stone));
+        assert_eq!(world.block_at(Point3::new(5, 1, 2)), Some(BlockType::Water));
+
+        // filling with the same block does nothing
+        assert_eq!(world.flood_fill(Point3::new(2, 1, 2), BlockType::Dirt), 0);
+    }
+
+    #[test]
+    fn superflat_layers_are_stacked_from_the_bottom() {
+        let mut world = World::empty();
+        world.set_height(8);
+        world.generate_superflat(&[
+            (BlockType::Stone, 2),
+            (BlockType::Dirt, 0),
+            (BlockType::Water, 3),
+            (BlockType::Grass, 10),
+        ]);
+        world.generate_chunk(-3, 20, 0);
+        let (start_x, start_z) = chunk_id(Point3::new(-3, 0, 20));
+        let end_x = start_x + CHUNK_SIZE - 1;
+        let end_z = start_z + CHUNK_SIZE - 1;
+
+        let expected = [
+            BlockType::Stone,
+            BlockType::Stone,
+            BlockType::Water,
+            BlockType::Water,
+            BlockType::Water,
+            // the grass layer is cut at the world height
+            BlockType::Grass,
+            BlockType::Grass,
+            BlockType::Grass,
+        ];
+        for (y, block) in expected.into_iter().enumerate() {
+            let y = y as i32;
+            assert_eq!(
+                world.block_at(Point3::new(start_x, y, start_z)),
+                Some(block)
+            );
+            assert_eq!(world.block_at(Point3::new(end_x, y, end_z)), Some(block));
+        }
+        assert_eq!(world.block_count(), (CHUNK_SIZE * CHUNK_SIZE * 8) as usize);
+    }
+
+    #[test]
+    fn saving_the_same_world_gives_the_same_file() {
+        let mut world = World::empty();
+        for x in 0..4 {
+            world.create_chunk(x * CHUNK_SIZE, 3, -CHUNK_SIZE);
+        }
+        world
+            .push_cube(Point3::new(1, 40, 1), BlockType::Water)
+            .unwrap();
+
+        let path = std::env::temp_dir().join("minecraft-world-deterministic-test.sav");
+        world.save_to_path(&path, None).unwrap();
+        let first = std::fs::read(&path).unwrap();
+        // a copy has its chunks and cubes in a different order in memory
+        let (loaded, _) = World::load_from_path(&path).unwrap();
+        loaded.save_to_path(&path, None).unwrap();
+        let second = std::fs::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(first, second);
+    }
+
+    #[test]
+    fn load_rejects_a_non_finite_camera() {
+        let world = World::empty();
+        let camera = CameraState {
+            position: Point3::new(0., f32::NAN, 0.),
+            yaw: Rad(0.),
+            pitch: Rad(0.),
+            fov: Rad(1.),
+        };
+        let path = std::env::temp_dir().join("minecraft-world-camera-test.sav");
+        world.save_to_path(&path, Some(&camera)).unwrap();
+        let result = World::load_from_path(&path);
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn generation_settings_move_to_the_loaded_world() {
+        let mut old = World::empty();
+        old.set_seed(12);
+        old.set_base_height(30);
+        old.set_keep_unloaded_chunks(false);
+        old.generate_superflat(&[(BlockType::Stone, 2)]);
+
+        let mut loaded = World::empty();
+        loaded.take_generation_settings(&mut old);
+        assert_eq!(loaded.seed, 12);
+        assert_eq!(loaded.base_height, 30);
+        assert!(!loaded.keep_unloaded_chunks);
+        loaded.generate_chunk(0, 0, loaded.seed);
+        assert_eq!(
+            loaded.block_at(Point3::new(0, 1, 0)),
+            Some(BlockType::Stone)
+        );
+        assert_eq!(loaded.block_at(Point3::new(0, 2, 0)), None);
+    }
+
+    #[test]
+    fn only_liquids_without_liquid_above_wave() {
+        let mut world = World::empty();
+        world
+            .push_cube(Point3::new(1, 1, 1), BlockType::Water)
+            .unwrap();
+        world
+            .push_cube(Point3::new(1, 2, 1), BlockType::Water)
+            .unwrap();
+        world.update_meshes();
+        world.wait_for_meshes();
+
+        let chunk = world.chunks().next().unwrap();
+        let instances = chunk.transparent_mesh().instances();
+        assert!(!instances.is_empty());
+        for instance in instances {
+            // the faces of the lower cube are centred at or below its center
+            let lower = instance.translation[1] < 1.5;
+            assert_eq!(instance.wave, if lower { 0. } else { 1. });
+        }
+    }
+
+    #[test]
+    fn edits_to_unloaded_chunks_keep_their_cubes() {
+        let mut world = World::empty();
+        let far = Point3::new(CHUNK_SIZE * 4 + 1, 3, 2);
+        world
+            .push_cube(Point3::new(1, 3, 1), BlockType::Stone)
+            .unwrap();
+        world.push_cube(far, BlockType::Dirt).unwrap();
+        world.unload_chunk(chunk_id(far));
+
+        // like redoing an edit after walking away
+        world
+            .push_cube(far + Vector3::unit_x(), BlockType::Grass)
+            .unwrap();
+        assert_eq!(world.block_at(far), Some(BlockType::Dirt));
+        world.unload_chunk(chunk_id(far));
+        world
+            .push_cube(far + Vector3::unit_z(), BlockType::Water)
+            .unwrap();
+        world.unload_chunk(chunk_id(far));
+        // nothing is created in chunks that are neither loaded nor kept
+        let chunk_count = world.chunk_count();
+        assert!(world
+            .remove_cube(Point3::new(-CHUNK_SIZE * 3, 3, 0))
+            .unwrap()
+            .is_none());
+        assert_eq!(world.chunk_count(), chunk_count);
+
+        let path = std::env::temp_dir().join("minecraft-world-unloaded-edits-test.sav");
+        world.save_to_path(&path, None).unwrap();
+        let (loaded, _) = World::load_from_path(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(loaded.chunk_count(), 2);
+        assert_eq!(loaded.block_at(far), Some(BlockType::Dirt));
+        assert_eq!(
+            loaded.block_at(far + Vector3::unit_x()),
+            Some(BlockType::Grass)
+        );
+        assert_eq!(
+            loaded.block_at(far + Vector3::unit_z()),
+            Some(BlockType::Water)
+        );
+        assert_eq!(loaded.block_count(), 4);
+    }
+
+    #[test]
+    fn rays_cross_into_the_neighbouring_chunks() {
+        let mut world = World::empty();
+        // just past the `x = CHUNK_SIZE` and `z = -1` chunk edges, and past both
+        let east = Point3::new(CHUNK_SIZE, 5, 2);
+        let north = Point3::new(2, 5, -1);
+        let corner = Point3::new(CHUNK_SIZE + 1, 5, -2);
+        for pos in [east, north, corner] {
+            world.push_cube(pos, BlockType::Stone).unwrap();
+        }
+        assert_ne!(chunk_id(east), chunk_id(east - Vector3::unit_x()));
+        assert_ne!(chunk_id(north), chunk_id(north + Vector3::unit_z()));
+        assert_ne!(chunk_id(corner), chunk_id(east));
+        assert_ne!(chunk_id(corner), chunk_id(north));
+
+        let rays = [
+            (
+                Point3::new(CHUNK_SIZE - 4, 5, 2),
+                Vector3::new(1., 0., 0.),
+                east,
+            ),
+            (Point3::new(2, 5, 3), Vector3::new(0., 0., -1.), north),
+            (
+                Point3::new(CHUNK_SIZE - 2, 5, 1),
+                Vector3::new(1., 0., -1.),
+                corner,
+            ),
+        ];
+        for (origin, direction, expected) in rays {
+            let result =
+                BlockRayTracer::new(&world, &origin.cast().unwrap(), &direction, 10.).run();
+            assert_eq!(result.result_cube.map(|cube| cube.cube), Some(expected));
+
+            let all =
+                BlockRayTracer::new(&world, &origin.cast().unwrap(), &direction, 10.).run_all();
+            assert_eq!(all, vec![expected]);
+        }
     }
 }
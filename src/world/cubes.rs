@@ -0,0 +1,173 @@
+//! The cubes of a chunk indexed by their position in the chunk.
+//!
+//! By default only the existing cubes are stored in a map, which saves a lot
+//! of memory for chunks that are mostly air. With the `dense_chunks` feature
+//! every cell of the chunk is stored in an array, which is faster to access
+//! for chunks that are mostly full.
+
+#[cfg(not(feature = "dense_chunks"))]
+use std::collections::HashMap;
+
+use super::ChunkCube;
+
+#[cfg(not(feature = "dense_chunks"))]
+#[derive(Clone)]
+pub(super) struct ChunkCubes {
+    // number of cells in the chunk
+    len: usize,
+    cubes: HashMap<u32, ChunkCube>,
+}
+
+#[cfg(not(feature = "dense_chunks"))]
+impl ChunkCubes {
+    /// Creates an empty chunk with `len` cells
+    pub fn new(len: usize) -> Self {
+        Self {
+            len,
+            cubes: HashMap::new(),
+        }
+    }
+
+    pub fn get(&self, index: usize) -> Option<ChunkCube> {
+        self.cubes.get(&(index as u32)).copied()
+    }
+
+    pub fn set(&mut self, index: usize, cube: Option<ChunkCube>) {
+        debug_assert!(index < self.len);
+
+        match cube {
+            Some(cube) => self.cubes.insert(index as u32, cube),
+            None => self.cubes.remove(&(index as u32)),
+        };
+    }
+
+    /// The existing cubes with their index, in no specific order
+    pub fn iter(&self) -> impl Iterator<Item = (usize, ChunkCube)> + '_ {
+        self.cubes.iter().map(|(&i, &cube)| (i as usize, cube))
+    }
+
+    /// Number of existing cubes
+    pub fn count(&self) -> usize {
+        self.cubes.len()
+    }
+
+    /// Rough estimation of the memory used to store the cubes
+    pub fn memory(&self) -> usize {
+        self.cubes.capacity() * std::mem::size_of::<(u32, ChunkCube)>()
+    }
+}
+
+#[cfg(feature = "dense_chunks")]
+#[derive(Clone)]
+pub(super) struct ChunkCubes {
+    cubes: Box<[Option<ChunkCube>]>,
+}
+
+#[cfg(feature = "dense_chunks")]
+impl ChunkCubes {
+    /// Creates an empty chunk with `len` cells
+    pub fn new(len: usize) -> Self {
+        Self {
+            cubes: vec![None; len].into_boxed_slice(),
+        }
+    }
+
+    pub fn get(&self, index: usize) -> Option<ChunkCube> {
+        self.cubes[index]
+    }
+
+    pub fn set(&mut self, index: usize, cube: Option<ChunkCube>) {
+        self.cubes[index] = cube;
+    }
+
+    /// The existing cubes with their index, ordered by index
+    pub fn iter(&self) -> impl Iterator<Item = (usize, ChunkCube)> + '_ {
+        self.cubes
+            .iter()
+            .enumerate()
+            .filter_map(|(i, cube)| cube.map(|cube| (i, cube)))
+    }
+
+    /// Number of existing cubes
+    pub fn count(&self) -> usize {
+        self.cubes.iter().filter(|cube| cube.is_some()).count()
+    }
+
+    /// Rough estimation of the memory used to store the cubes
+    pub fn memory(&self) -> usize {
+        std::mem::size_of_val(&*self.cubes)
+    }
+}
+
+impl ChunkCubes {
+    /// Copies the cubes into an array with all the cells, which is faster to
+    /// look up when building the mesh
+    pub fn to_dense(&self, len: usize) -> Box<[Option<ChunkCube>]> {
+        let mut cubes = vec![None; len].into_boxed_slice();
+        for (i, cube) in self.iter() {
+            cubes[i] = Some(cube);
+        }
+        cubes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Instant;
+
+    use super::*;
+    use crate::{
+        block::{BlockShape, BlockType},
+        world::{CHUNK_SIZE, DEFAULT_HEIGHT},
+    };
+
+    /// Compares the storage of the cubes with and without `dense_chunks`, run
+    /// with `cargo test --release storage_benchmark -- --ignored --nocapture`
+    /// and again with `--features dense_chunks`
+    #[test]
+    #[ignore]
+    fn storage_benchmark() {
+        const CHUNKS: usize = 64;
+        let len = (CHUNK_SIZE * CHUNK_SIZE * DEFAULT_HEIGHT) as usize;
+        let cube = ChunkCube {
+            block: BlockType::Stone,
+            shape: BlockShape::Cube,
+            rotation: [0.; 3],
+        };
+
+        // mostly air with the ground at the bottom, and completely full
+        for (name, filled) in [("terrain", len / 4), ("full", len)] {
+            let start = Instant::now();
+            let mut chunks = (0..CHUNKS)
+                .map(|_| ChunkCubes::new(len))
+                .collect::<Vec<_>>();
+            for chunk in &mut chunks {
+                for i in 0..filled {
+                    chunk.set(i, Some(cube));
+                }
+            }
+            let fill_time = start.elapsed();
+
+            let start = Instant::now();
+            let mut found = 0;
+            for chunk in &chunks {
+                found += (0..len).filter(|&i| chunk.get(i).is_some()).count();
+            }
+            let lookup_time = start.elapsed();
+            assert_eq!(found, filled * CHUNKS);
+
+            let start = Instant::now();
+            for chunk in &chunks {
+                std::hint::black_box(chunk.to_dense(len));
+            }
+            let to_dense_time = start.elapsed();
+
+            let memory = chunks.iter().map(ChunkCubes::memory).sum::<usize>();
+            println!(
+                "{name}: fill={fill_time:?} lookup={lookup_time:?} to_dense={to_dense_time:?} \
+                 memory_kib={}",
+                memory / 1024
+            );
+        }
+    }
+}